@@ -0,0 +1,860 @@
+//! Per-entity rigid body reducers: motion, forces, body type and freezing.
+
+use log::info;
+use rapier3d::na::Vector3;
+use rapier3d::parry::query::PointQuery;
+use rapier3d::prelude::*;
+use spacetimedb::{reducer, ReducerContext, Table};
+use std::collections::HashMap;
+
+use crate::replay::log_call;
+use crate::state::*;
+use crate::tables::*;
+use crate::types::*;
+
+// --- Reducers ---
+
+#[reducer]
+pub fn move_character(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    desired_x: f32,
+    desired_y: f32,
+    desired_z: f32,
+) -> Result<(), String> {
+    if ![desired_x, desired_y, desired_z].iter().all(|v| v.is_finite()) {
+        return Err("Desired movement must be finite".to_string());
+    }
+    let mut character_state = ctx
+        .db
+        .entity_character_state()
+        .entity_id()
+        .find(entity_id)
+        .ok_or_else(|| format!("Entity {} is not a character", entity_id))?;
+    let (world_id, rb_handle, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let (pose, movement) = move_character_body(
+        state,
+        rb_handle,
+        co_handle,
+        Vector3::new(desired_x, desired_y, desired_z),
+    )
+    .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+
+    // The tick only writes dynamic bodies, so publish the character's position here
+    ctx.db
+        .entity_transform()
+        .entity_id()
+        .update(EntityTransform::from_pose(world_id, entity_id, &pose));
+    character_state.grounded = movement.grounded;
+    character_state.sliding_down_slope = movement.is_sliding_down_slope;
+    ctx.db
+        .entity_character_state()
+        .entity_id()
+        .update(character_state);
+    Ok(())
+}
+
+/// Moves a kinematic character body by `desired` as far as the world allows and returns
+/// its target pose with the controller's result. Obstacles clamp or slide the movement
+/// rather than rejecting it, so walking into a wall simply yields a shorter (possibly
+/// zero) corrected translation.
+pub(crate) fn move_character_body(
+    state: &mut PhysicsState,
+    rb_handle: RigidBodyHandle,
+    co_handle: ColliderHandle,
+    desired: Vector3<f32>,
+) -> Option<(Isometry<Real>, EffectiveCharacterMovement)> {
+    let PhysicsState {
+        rigid_body_set,
+        collider_set,
+        integration_parameters,
+        query_pipeline,
+        ..
+    } = state;
+
+    let collider = collider_set.get(co_handle)?;
+    let movement = KinematicCharacterController::default().move_shape(
+        integration_parameters.dt,
+        rigid_body_set,
+        collider_set,
+        query_pipeline,
+        collider.shape(),
+        collider.position(),
+        desired,
+        QueryFilter::default().exclude_rigid_body(rb_handle),
+        |_| {},
+    );
+
+    let rigid_body = rigid_body_set.get_mut(rb_handle)?;
+    let new_translation = rigid_body.translation() + movement.translation;
+    rigid_body.set_next_kinematic_translation(new_translation);
+    let pose = Isometry::from_parts(new_translation.into(), *rigid_body.rotation());
+    Some((pose, movement))
+}
+
+#[reducer]
+pub fn set_tag(ctx: &ReducerContext, entity_id: u32, tag: u32) -> Result<(), String> {
+    info!("set_tag called for entity {}: {}", entity_id, tag);
+    if ctx.db.entity().id().find(entity_id).is_none() {
+        return Err(format!("Entity {} not found", entity_id));
+    }
+    let row = EntityTag { entity_id, tag };
+    if ctx.db.entity_tag().entity_id().find(entity_id).is_some() {
+        ctx.db.entity_tag().entity_id().update(row);
+    } else {
+        ctx.db
+            .entity_tag()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn set_damping(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    linear_damping: f32,
+    angular_damping: f32,
+) -> Result<(), String> {
+    info!(
+        "set_damping called for entity {}: linear {}, angular {}",
+        entity_id, linear_damping, angular_damping
+    );
+    if ![linear_damping, angular_damping].iter().all(|d| d.is_finite() && *d >= 0.0) {
+        return Err("Damping must be >= 0".to_string());
+    }
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    rigid_body.set_linear_damping(linear_damping);
+    rigid_body.set_angular_damping(angular_damping);
+    rigid_body.wake_up(true);
+    Ok(())
+}
+
+#[reducer]
+pub fn set_ccd(ctx: &ReducerContext, entity_id: u32, enabled: bool) -> Result<(), String> {
+    info!("set_ccd called for entity {}: {}", entity_id, enabled);
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    rigid_body.enable_ccd(enabled);
+    Ok(())
+}
+
+#[reducer]
+pub fn set_body_type(ctx: &ReducerContext, entity_id: u32, body_type: BodyType) -> Result<(), String> {
+    info!("set_body_type called for entity {}: {:?}", entity_id, body_type);
+    let body_type = RigidBodyType::from(body_type);
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    switch_body_type(rigid_body, body_type);
+    if body_type != RigidBodyType::Dynamic {
+        stop_body(ctx, world_id, entity_id, rigid_body);
+    }
+    Ok(())
+}
+
+/// Changes a body's type. Bodies that stop being dynamic stay where they are.
+fn switch_body_type(rigid_body: &mut RigidBody, body_type: RigidBodyType) {
+    rigid_body.set_body_type(body_type, true);
+    if body_type != RigidBodyType::Dynamic {
+        rigid_body.set_linvel(Vector3::zeros(), true);
+        rigid_body.set_angvel(Vector3::zeros(), true);
+    }
+}
+
+/// Publishes the pose and (zero) velocity a no-longer-dynamic body stopped with,
+/// since the tick only writes dynamic bodies.
+fn stop_body(ctx: &ReducerContext, world_id: u32, entity_id: u32, rigid_body: &RigidBody) {
+    let transform = EntityTransform::from_pose(world_id, entity_id, rigid_body.position());
+    ctx.db
+        .entity_transform_prev()
+        .entity_id()
+        .update(transform.clone().into());
+    ctx.db.entity_transform().entity_id().update(transform);
+    ctx.db
+        .entity_velocity()
+        .entity_id()
+        .update(EntityVelocity::from_body(entity_id, rigid_body));
+}
+
+/// Shifts a body by a world-space offset, keeping its rotation and velocity.
+pub(crate) fn offset_body(
+    state: &mut PhysicsState,
+    entity_id: u32,
+    rb_handle: RigidBodyHandle,
+    delta: Vector3<f32>,
+) -> Result<Isometry<Real>, String> {
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    let translation = rigid_body.translation() + delta;
+    rigid_body.set_translation(translation, true);
+    Ok(*rigid_body.position())
+}
+
+#[reducer]
+pub fn teleport_relative(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    dx: f32,
+    dy: f32,
+    dz: f32,
+) -> Result<(), String> {
+    info!("teleport_relative called for entity {}: ({}, {}, {})", entity_id, dx, dy, dz);
+    let delta = Vec3 { x: dx, y: dy, z: dz }.to_finite_vector("Offset")?;
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let position = offset_body(state, entity_id, rb_handle, delta)?;
+
+    // Publish right away, and move the previous pose too so clients snap instead of
+    // interpolating across the jump
+    let transform = EntityTransform::from_pose(world_id, entity_id, &position);
+    ctx.db
+        .entity_transform_prev()
+        .entity_id()
+        .update(transform.clone().into());
+    ctx.db.entity_transform().entity_id().update(transform);
+    Ok(())
+}
+
+#[reducer]
+pub fn freeze_entity(ctx: &ReducerContext, entity_id: u32) -> Result<(), String> {
+    info!("freeze_entity called for entity {}", entity_id);
+    if ctx.db.frozen_state().entity_id().find(entity_id).is_some() {
+        return Err(format!("Entity {} is already frozen", entity_id));
+    }
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    if !rigid_body.is_dynamic() {
+        return Err(format!("Entity {} is not a dynamic body", entity_id));
+    }
+    let (linvel, angvel) = freeze_body(rigid_body);
+    let frozen = FrozenState {
+        entity_id,
+        linvel: linvel.into(),
+        angvel: angvel.into(),
+        frozen_at: ctx.timestamp,
+    };
+    stop_body(ctx, world_id, entity_id, rigid_body);
+    ctx.db
+        .frozen_state()
+        .try_insert(frozen)
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[reducer]
+pub fn unfreeze_entity(ctx: &ReducerContext, entity_id: u32) -> Result<(), String> {
+    info!("unfreeze_entity called for entity {}", entity_id);
+    let frozen = ctx
+        .db
+        .frozen_state()
+        .entity_id()
+        .find(entity_id)
+        .ok_or_else(|| format!("Entity {} is not frozen", entity_id))?;
+    let linvel = frozen.linvel.to_finite_vector("Stored linear velocity")?;
+    let angvel = frozen.angvel.to_finite_vector("Stored angular velocity")?;
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    thaw_body(rigid_body, linvel, angvel);
+    ctx.db.frozen_state().entity_id().delete(entity_id);
+    Ok(())
+}
+
+/// Makes a dynamic body kinematic where it is and returns the (linear, angular)
+/// velocities it had. Kinematic bodies hold their pose and ignore gravity, forces and
+/// contacts, while still pushing dynamic bodies out of the way.
+pub(crate) fn freeze_body(rigid_body: &mut RigidBody) -> (Vector3<f32>, Vector3<f32>) {
+    let velocities = (*rigid_body.linvel(), *rigid_body.angvel());
+    rigid_body.set_body_type(RigidBodyType::KinematicPositionBased, true);
+    rigid_body.set_linvel(Vector3::zeros(), true);
+    rigid_body.set_angvel(Vector3::zeros(), true);
+    rigid_body.set_next_kinematic_position(*rigid_body.position());
+    velocities
+}
+
+/// Undoes `freeze_body`: dynamic again, moving with the stored velocities.
+pub(crate) fn thaw_body(rigid_body: &mut RigidBody, linvel: Vector3<f32>, angvel: Vector3<f32>) {
+    rigid_body.set_body_type(RigidBodyType::Dynamic, true);
+    rigid_body.set_linvel(linvel, true);
+    rigid_body.set_angvel(angvel, true);
+}
+
+#[reducer]
+pub fn pause_entity(ctx: &ReducerContext, entity_id: u32) -> Result<(), String> {
+    info!("pause_entity called for entity {}", entity_id);
+    freeze_entity(ctx, entity_id)
+}
+
+#[reducer]
+pub fn resume_entity(ctx: &ReducerContext, entity_id: u32) -> Result<(), String> {
+    info!("resume_entity called for entity {}", entity_id);
+    unfreeze_entity(ctx, entity_id)
+}
+
+#[reducer]
+pub fn set_angular_velocity(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    ax: f32,
+    ay: f32,
+    az: f32,
+) -> Result<(), String> {
+    info!("set_angular_velocity called for entity {}: ({}, {}, {})", entity_id, ax, ay, az);
+    let angvel = Vec3 { x: ax, y: ay, z: az }.to_finite_vector("Angular velocity")?;
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    rigid_body.set_angvel(angvel, true);
+    Ok(())
+}
+
+#[reducer]
+pub fn apply_impulse_at_point(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    ix: f32,
+    iy: f32,
+    iz: f32,
+    px: f32,
+    py: f32,
+    pz: f32,
+) -> Result<(), String> {
+    info!(
+        "apply_impulse_at_point called for entity {}: ({}, {}, {}) at ({}, {}, {})",
+        entity_id, ix, iy, iz, px, py, pz
+    );
+    let impulse = Vec3 { x: ix, y: iy, z: iz }.to_finite_vector("Impulse")?;
+    let point = Vec3 { x: px, y: py, z: pz }.to_finite_vector("Point")?;
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    // `point` is in world space; hitting away from the center of mass also adds spin
+    rigid_body.apply_impulse_at_point(impulse, Point::from(point), true);
+    let call = LoggedCall::ApplyImpulseAtPoint(ImpulseCall {
+        entity_id,
+        impulse: Vec3 { x: ix, y: iy, z: iz },
+        point: Vec3 { x: px, y: py, z: pz },
+    });
+    log_call(ctx, state, call, Vec::new())
+}
+
+#[reducer]
+pub fn apply_force(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    fx: f32,
+    fy: f32,
+    fz: f32,
+) -> Result<(), String> {
+    info!("apply_force called for entity {}: ({}, {}, {})", entity_id, fx, fy, fz);
+    let force = Vec3 { x: fx, y: fy, z: fz }.to_finite_vector("Force")?;
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    // Accumulates until the next tick, which consumes and clears it:
+    // a sustained force has to be re-applied every tick
+    rigid_body.add_force(force, true);
+    let call = LoggedCall::ApplyForce(ForceCall {
+        entity_id,
+        vector: Vec3 { x: fx, y: fy, z: fz },
+    });
+    log_call(ctx, state, call, Vec::new())
+}
+
+#[reducer]
+pub fn apply_torque(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    tx: f32,
+    ty: f32,
+    tz: f32,
+) -> Result<(), String> {
+    info!("apply_torque called for entity {}: ({}, {}, {})", entity_id, tx, ty, tz);
+    let torque = Vec3 { x: tx, y: ty, z: tz }.to_finite_vector("Torque")?;
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    // Cleared after the next tick, like `apply_force`
+    rigid_body.add_torque(torque, true);
+    let call = LoggedCall::ApplyTorque(ForceCall {
+        entity_id,
+        vector: Vec3 { x: tx, y: ty, z: tz },
+    });
+    log_call(ctx, state, call, Vec::new())
+}
+
+/// Pushes every dynamic entity body within `radius` of `center` away from it at its
+/// nearest surface point, with linear falloff. Returns how many bodies were pushed.
+pub(crate) fn apply_shockwave(
+    state: &mut PhysicsState,
+    center: &Point<Real>,
+    radius: f32,
+    strength: f32,
+) -> usize {
+    // The point of each body's surface nearest the blast, over all of its colliders
+    let mut nearest: HashMap<RigidBodyHandle, (Point<Real>, f32)> = HashMap::new();
+    for (co_handle, collider) in state.collider_set.iter() {
+        if collider.is_sensor() || !state.collider_to_entity_id.contains_key(&co_handle) {
+            continue;
+        }
+        let Some(rb_handle) = collider.parent() else {
+            continue;
+        };
+        let projection = collider.shape().project_point(collider.position(), center, true);
+        let distance = if projection.is_inside {
+            0.0
+        } else {
+            (projection.point - *center).norm()
+        };
+        let closer = !matches!(nearest.get(&rb_handle), Some((_, d)) if *d <= distance);
+        if distance <= radius && closer {
+            nearest.insert(rb_handle, (projection.point, distance));
+        }
+    }
+
+    let mut pushed = 0;
+    for (rb_handle, (point, distance)) in nearest {
+        let Some(rigid_body) = state.rigid_body_set.get_mut(rb_handle) else {
+            continue;
+        };
+        if !rigid_body.is_dynamic() {
+            continue;
+        }
+        // Pushing along the blast ray at the surface point makes off-center bodies tumble.
+        // A blast inside a body has no ray, so that body is pushed away from it instead.
+        let direction = (point - *center)
+            .try_normalize(1.0e-6)
+            .or_else(|| (*rigid_body.center_of_mass() - *center).try_normalize(1.0e-6))
+            .unwrap_or_else(Vector3::y);
+        let falloff = 1.0 - distance / radius;
+        rigid_body.apply_impulse_at_point(direction * (strength * falloff), point, true);
+        pushed += 1;
+    }
+    pushed
+}
+
+#[reducer]
+pub fn shockwave(
+    ctx: &ReducerContext,
+    world_id: u32,
+    x: f32,
+    y: f32,
+    z: f32,
+    radius: f32,
+    strength: f32,
+) -> Result<(), String> {
+    info!(
+        "shockwave called for world {}: ({}, {}, {}), radius {}, strength {}",
+        world_id, x, y, z, radius, strength
+    );
+    let center = Point::from(Vec3 { x, y, z }.to_finite_vector("Shockwave center")?);
+    if !(radius.is_finite() && radius > 0.0) {
+        return Err("Radius must be > 0".to_string());
+    }
+    if !(strength.is_finite() && strength >= 0.0) {
+        return Err("Strength must be >= 0".to_string());
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    let pushed = apply_shockwave(state, &center, radius, strength);
+    info!("  -> Pushed {} bodies", pushed);
+    let call = LoggedCall::Shockwave(ShockwaveCall {
+        center: Vec3 { x, y, z },
+        radius,
+        strength,
+    });
+    log_call(ctx, state, call, Vec::new())
+}
+
+#[reducer]
+pub fn set_dominance_group(ctx: &ReducerContext, entity_id: u32, group: i8) -> Result<(), String> {
+    info!("set_dominance_group called for entity {}: {}", entity_id, group);
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    rigid_body.set_dominance_group(group);
+    rigid_body.wake_up(true);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tick::{clear_user_forces, moved_entity_bodies};
+    use rapier3d::na::UnitQuaternion;
+
+    #[test]
+    fn paused_body_stays_in_place_while_others_fall() {
+        let mut state = PhysicsState::new(0);
+        let handles: Vec<RigidBodyHandle> = (0..3)
+            .map(|i| state.add_test_ball(Vector3::new(i as f32 * 5.0, 10.0, 0.0)))
+            .collect();
+        for _ in 0..5 {
+            state.step_once();
+        }
+        let paused_at = *state.rigid_body_set[handles[1]].translation();
+        let (linvel, angvel) = freeze_body(&mut state.rigid_body_set[handles[1]]);
+        assert!(linvel.y < 0.0, "the body was falling when paused");
+
+        let others_before: Vec<f32> = [handles[0], handles[2]]
+            .iter()
+            .map(|handle| state.rigid_body_set[*handle].translation().y)
+            .collect();
+        for _ in 0..20 {
+            state.step_once();
+        }
+        assert_eq!(*state.rigid_body_set[handles[1]].translation(), paused_at);
+        for (handle, before) in [handles[0], handles[2]].iter().zip(others_before) {
+            assert!(state.rigid_body_set[*handle].translation().y < before);
+        }
+
+        // Resuming continues the fall with the stored velocity
+        thaw_body(&mut state.rigid_body_set[handles[1]], linvel, angvel);
+        assert_eq!(*state.rigid_body_set[handles[1]].linvel(), linvel);
+        state.step_once();
+        assert!(state.rigid_body_set[handles[1]].translation().y < paused_at.y);
+    }
+
+    #[test]
+    fn ccd_stops_fast_bodies_at_thin_walls() {
+        for ccd_enabled in [false, true] {
+            let mut state = PhysicsState::new(0);
+            state.collider_set.insert(
+                ColliderBuilder::cuboid(0.05, 5.0, 5.0)
+                    .translation(Vector3::new(10.0, 50.0, 0.0))
+                    .build(),
+            );
+            let handle = state.add_test_ball(Vector3::new(0.0, 50.0, 0.0));
+            let rigid_body = &mut state.rigid_body_set[handle];
+            // About 30m per step, so discrete detection never sees the ball touch the wall
+            rigid_body.set_linvel(Vector3::new(2000.0, 0.0, 0.0), true);
+            rigid_body.enable_ccd(ccd_enabled);
+            state.step_once();
+            let x = state.rigid_body_set[handle].translation().x;
+            assert_eq!(
+                x < 10.0,
+                ccd_enabled,
+                "ccd {}: ball ended at x = {}",
+                ccd_enabled,
+                x
+            );
+        }
+    }
+
+    /// Adds a kinematic unit-ball character at `position` and refreshes the query pipeline.
+    fn add_test_character(
+        state: &mut PhysicsState,
+        position: Vector3<f32>,
+    ) -> (RigidBodyHandle, ColliderHandle) {
+        let rb_handle = state.rigid_body_set.insert(
+            RigidBodyBuilder::kinematic_position_based()
+                .translation(position)
+                .build(),
+        );
+        let co_handle = state.collider_set.insert_with_parent(
+            ColliderBuilder::ball(1.0).build(),
+            rb_handle,
+            &mut state.rigid_body_set,
+        );
+        state
+            .query_pipeline
+            .update(&state.rigid_body_set, &state.collider_set);
+        (rb_handle, co_handle)
+    }
+
+    #[test]
+    fn character_moving_down_onto_the_ground_is_grounded() {
+        let mut state = PhysicsState::new(0);
+        // Bottom of the ball a little above the top of the ground
+        let (rb_handle, co_handle) = add_test_character(&mut state, Vector3::new(0.0, 1.3, 0.0));
+        let (pose, movement) = move_character_body(
+            &mut state,
+            rb_handle,
+            co_handle,
+            Vector3::new(0.0, -1.0, 0.0),
+        )
+        .unwrap();
+        assert!(movement.grounded);
+        // Stopped on the ground instead of sinking the full metre
+        assert!(pose.translation.vector.y > 1.0);
+    }
+
+    #[test]
+    fn character_walking_into_a_wall_is_clamped_not_rejected() {
+        let mut state = PhysicsState::new(0);
+        // Wall face at x = 2.5, so the unit ball can advance about 1.5m
+        state.collider_set.insert(
+            ColliderBuilder::cuboid(0.5, 5.0, 5.0)
+                .translation(Vector3::new(3.0, 5.0, 0.0))
+                .build(),
+        );
+        let (rb_handle, co_handle) = add_test_character(&mut state, Vector3::new(0.0, 3.0, 0.0));
+        let (pose, movement) = move_character_body(
+            &mut state,
+            rb_handle,
+            co_handle,
+            Vector3::new(5.0, 0.0, 0.0),
+        )
+        .unwrap();
+        assert!(movement.translation.x > 1.0);
+        assert!(movement.translation.x <= 1.5);
+        assert_eq!(pose.translation.vector.x, movement.translation.x);
+        assert_eq!(
+            state.rigid_body_set[rb_handle]
+                .next_position()
+                .translation
+                .vector,
+            pose.translation.vector
+        );
+    }
+
+    /// A 1m box resting on the ground, settled for a few steps.
+    fn resting_box(state: &mut PhysicsState) -> RigidBodyHandle {
+        let rb_handle = state.rigid_body_set.insert(
+            RigidBodyBuilder::dynamic()
+                .translation(Vector3::new(0.0, 0.6, 0.0))
+                .build(),
+        );
+        state.collider_set.insert_with_parent(
+            ColliderBuilder::cuboid(0.5, 0.5, 0.5).build(),
+            rb_handle,
+            &mut state.rigid_body_set,
+        );
+        for _ in 0..10 {
+            state.step_once();
+        }
+        rb_handle
+    }
+
+    #[test]
+    fn off_center_impulse_makes_a_box_spin() {
+        let mut state = PhysicsState::new(0);
+        let centered = resting_box(&mut state);
+        let body = &mut state.rigid_body_set[centered];
+        let center = Point::from(*body.translation());
+        body.apply_impulse_at_point(Vector3::new(2.0, 0.0, 0.0), center, true);
+        assert!(body.angvel().norm() < 1.0e-3);
+
+        let mut state = PhysicsState::new(0);
+        let hit = resting_box(&mut state);
+        let body = &mut state.rigid_body_set[hit];
+        // Strike the top edge sideways, so the box tips about Z
+        let edge = Point::from(body.translation() + Vector3::new(0.0, 0.5, 0.0));
+        body.apply_impulse_at_point(Vector3::new(2.0, 0.0, 0.0), edge, true);
+        assert!(body.angvel().z < -1.0, "angvel {:?}", body.angvel());
+        state.step_once();
+        assert!(state.rigid_body_set[hit].rotation().angle() > 0.0);
+    }
+
+    #[test]
+    fn body_made_fixed_mid_fall_stays_put_and_stops_being_published() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 10.0, 0.0));
+        state.handle_to_entity_id.insert(rb_handle, 1);
+        for _ in 0..20 {
+            state.step_once();
+        }
+        let published = |state: &PhysicsState| {
+            moved_entity_bodies(state)
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(published(&state), [1]);
+
+        switch_body_type(&mut state.rigid_body_set[rb_handle], RigidBodyType::Fixed);
+        assert_eq!(*state.rigid_body_set[rb_handle].linvel(), Vector3::zeros());
+        let placed_at = *state.rigid_body_set[rb_handle].translation();
+        for _ in 0..20 {
+            state.step_once();
+        }
+        assert_eq!(*state.rigid_body_set[rb_handle].translation(), placed_at);
+        assert!(published(&state).is_empty());
+
+        // Thawed back, it starts falling from rest
+        switch_body_type(&mut state.rigid_body_set[rb_handle], RigidBodyType::Dynamic);
+        state.step_once();
+        assert!(state.rigid_body_set[rb_handle].translation().y < placed_at.y);
+        assert_eq!(published(&state), [1]);
+    }
+
+    #[test]
+    fn ccd_holds_the_thin_ground_at_every_substep_count() {
+        for max_ccd_substeps in [1, 4, 16] {
+            let mut state = PhysicsState::new(0);
+            state.integration_parameters.max_ccd_substeps = max_ccd_substeps;
+            // A burst of fast spheres fired straight down at the 0.2m thick ground
+            let handles: Vec<RigidBodyHandle> = (0..5)
+                .map(|i| {
+                    let handle = state.add_test_ball(Vector3::new(i as f32 * 3.0, 30.0, 0.0));
+                    let rigid_body = &mut state.rigid_body_set[handle];
+                    rigid_body.set_linvel(Vector3::new(0.0, -1500.0 - 100.0 * i as f32, 0.0), true);
+                    rigid_body.enable_ccd(true);
+                    handle
+                })
+                .collect();
+            for _ in 0..30 {
+                state.step_once();
+            }
+            for handle in handles {
+                let y = state.rigid_body_set[handle].translation().y;
+                assert!(
+                    y > 0.0,
+                    "{} substeps: ball ended at y = {}",
+                    max_ccd_substeps,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn frozen_body_ignores_forces_and_hits_then_thaws_with_its_exact_velocity() {
+        let mut state = PhysicsState::new(0);
+        let frozen = state.add_test_ball(Vector3::new(0.0, 5.0, 0.0));
+        let velocity = (Vector3::new(1.5, -2.0, 0.25), Vector3::new(0.0, 3.0, -1.0));
+        state.rigid_body_set[frozen].set_linvel(velocity.0, true);
+        state.rigid_body_set[frozen].set_angvel(velocity.1, true);
+        let stashed = freeze_body(&mut state.rigid_body_set[frozen]);
+        assert_eq!(stashed, velocity);
+        let pose = *state.rigid_body_set[frozen].position();
+
+        // A ball dropped onto it and a force on it change nothing
+        let dropped = state.add_test_ball(Vector3::new(0.0, 9.0, 0.0));
+        for _ in 0..60 {
+            state.rigid_body_set[frozen].add_force(Vector3::new(500.0, 0.0, 0.0), true);
+            state.step_once();
+            clear_user_forces(&mut state.rigid_body_set);
+        }
+        assert_eq!(*state.rigid_body_set[frozen].position(), pose);
+        assert!((state.rigid_body_set[dropped].translation().y - 7.0).abs() < 0.1);
+
+        thaw_body(&mut state.rigid_body_set[frozen], stashed.0, stashed.1);
+        let body = &state.rigid_body_set[frozen];
+        assert!(body.is_dynamic());
+        assert_eq!((*body.linvel(), *body.angvel()), velocity);
+    }
+
+    #[test]
+    fn shockwave_tumbles_a_row_of_boxes_within_its_radius() {
+        let mut state = PhysicsState::new(0);
+        let boxes: Vec<RigidBodyHandle> = [2.0, 4.0, 6.0, 20.0]
+            .into_iter()
+            .zip(1..)
+            .map(|(x, entity_id)| {
+                let rb_handle = state.rigid_body_set.insert(
+                    RigidBodyBuilder::dynamic()
+                        .translation(Vector3::new(x, 0.6, 0.0))
+                        .build(),
+                );
+                let co_handle = state.collider_set.insert_with_parent(
+                    ColliderBuilder::cuboid(0.5, 0.5, 0.5).build(),
+                    rb_handle,
+                    &mut state.rigid_body_set,
+                );
+                state.collider_to_entity_id.insert(co_handle, entity_id);
+                rb_handle
+            })
+            .collect();
+        state.step_once();
+
+        // Level with the boxes' top edges, so each is struck above its center of mass
+        let pushed = apply_shockwave(&mut state, &Point::new(0.0, 1.1, 0.0), 10.0, 10.0);
+        assert_eq!(pushed, 3);
+        for rb_handle in &boxes[..3] {
+            let body = &state.rigid_body_set[*rb_handle];
+            assert!(
+                body.linvel().x > 0.0,
+                "not pushed away: {:?}",
+                body.linvel()
+            );
+            assert!(
+                body.angvel().z < -0.1,
+                "not tipped over: {:?}",
+                body.angvel()
+            );
+        }
+        // Falloff: nearer boxes get the harder push
+        let speed = |rb_handle: RigidBodyHandle| state.rigid_body_set[rb_handle].linvel().x;
+        assert!(speed(boxes[0]) > speed(boxes[1]) && speed(boxes[1]) > speed(boxes[2]));
+        assert!(speed(boxes[3]).abs() < 1e-3);
+    }
+
+    #[test]
+    fn offset_moves_along_world_axes_and_keeps_rotation_and_velocity() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(1.0, 5.0, 0.0));
+        let rotation = UnitQuaternion::from_euler_angles(0.0, 1.0, 0.0);
+        let rigid_body = &mut state.rigid_body_set[rb_handle];
+        rigid_body.set_rotation(rotation, true);
+        rigid_body.set_linvel(Vector3::new(2.0, 0.0, 0.0), true);
+
+        let position = offset_body(&mut state, 1, rb_handle, Vector3::new(3.0, 0.0, -1.0)).unwrap();
+        assert_eq!(position.translation.vector, Vector3::new(4.0, 5.0, -1.0));
+        assert_eq!(position.rotation, rotation);
+        assert_eq!(
+            *state.rigid_body_set[rb_handle].linvel(),
+            Vector3::new(2.0, 0.0, 0.0)
+        );
+
+        // The next step starts from the shifted pose rather than snapping back
+        state.step_once();
+        let after = state.rigid_body_set[rb_handle].translation();
+        assert!(after.x > 4.0 && after.x < 4.1, "x {}", after.x);
+        assert!((after.z + 1.0).abs() < 1e-6);
+
+        let missing = offset_body(&mut state, 9, RigidBodyHandle::invalid(), Vector3::zeros());
+        assert!(missing.is_err());
+    }
+}
@@ -0,0 +1,1213 @@
+//! Per-entity collider reducers: materials, layers, events, shape and contact hooks.
+
+use log::info;
+use rapier3d::na::Vector3;
+use rapier3d::prelude::*;
+use spacetimedb::{reducer, ReducerContext, Table};
+
+use crate::state::*;
+use crate::tables::*;
+use crate::types::*;
+
+// --- Reducers ---
+
+#[reducer]
+pub fn attach_collider(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    shape: ShapeDesc,
+    local_x: f32,
+    local_y: f32,
+    local_z: f32,
+) -> Result<(), String> {
+    info!("attach_collider called for entity {}: {:?}", entity_id, shape.kind);
+    if ![local_x, local_y, local_z].iter().all(|v| v.is_finite()) {
+        return Err("Collider offset must be finite".to_string());
+    }
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let co_handle = attach_to_body(
+        state,
+        entity_id,
+        rb_handle,
+        &shape,
+        Vector3::new(local_x, local_y, local_z),
+    )?;
+    // The new collider reports collisions like the body's others
+    sync_collision_events(ctx, state, entity_id, rb_handle)?;
+
+    let id = ctx
+        .db
+        .entity_collider()
+        .iter()
+        .map(|row| row.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let (co_idx, co_gen) = co_handle.into_raw_parts();
+    ctx.db
+        .entity_collider()
+        .try_insert(EntityCollider {
+            id,
+            entity_id,
+            shape,
+            local_x,
+            local_y,
+            local_z,
+            co_handle_index: co_idx,
+            co_handle_generation: co_gen,
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Adds a collider of `shape` to an entity's body at `offset` from the body origin and maps
+/// it to the entity. The body's mass properties include the new part.
+pub(crate) fn attach_to_body(
+    state: &mut PhysicsState,
+    entity_id: u32,
+    rb_handle: RigidBodyHandle,
+    shape: &ShapeDesc,
+    offset: Vector3<f32>,
+) -> Result<ColliderHandle, String> {
+    let mut collider = shape.collider_builder()?.translation(offset).build();
+    enable_contact_force_events(&mut collider, state.contact_force_threshold);
+    if !state.rigid_body_set.contains(rb_handle) {
+        return Err(format!("Rigid body for entity {} not found", entity_id));
+    }
+    let co_handle = state
+        .collider_set
+        .insert_with_parent(collider, rb_handle, &mut state.rigid_body_set);
+    state.collider_to_entity_id.insert(co_handle, entity_id);
+    Ok(co_handle)
+}
+
+/// Number of collision layers, one per interaction group bit.
+const MAX_COLLISION_LAYERS: u32 = 32;
+
+#[reducer]
+pub fn define_layer(ctx: &ReducerContext, layer: u32, name: String) -> Result<(), String> {
+    info!("define_layer called: {} = {:?}", layer, name);
+    if layer >= MAX_COLLISION_LAYERS {
+        return Err(format!("Layer must be below {}", MAX_COLLISION_LAYERS));
+    }
+    if name.is_empty() {
+        return Err("Layer name must not be empty".to_string());
+    }
+    if let Some(other) = ctx
+        .db
+        .collision_layer()
+        .iter()
+        .find(|row| row.name == name && row.layer != layer)
+    {
+        return Err(format!("Layer name {:?} is already used by layer {}", name, other.layer));
+    }
+    let row = CollisionLayer { layer, name };
+    if ctx.db.collision_layer().layer().find(layer).is_some() {
+        ctx.db.collision_layer().layer().update(row);
+    } else {
+        ctx.db
+            .collision_layer()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Combines registered layers into an interaction group bitmask.
+fn layer_bits(ctx: &ReducerContext, layers: &[u32]) -> Result<Group, String> {
+    if let Some(layer) = layers
+        .iter()
+        .find(|layer| ctx.db.collision_layer().layer().find(**layer).is_none())
+    {
+        return Err(format!("Collision layer {} is not defined", layer));
+    }
+    Ok(layers_to_group(layers))
+}
+
+/// One interaction group bit per layer (layers are below `MAX_COLLISION_LAYERS`).
+fn layers_to_group(layers: &[u32]) -> Group {
+    layers
+        .iter()
+        .fold(Group::NONE, |bits, layer| bits | Group::from_bits_truncate(1 << layer))
+}
+
+#[reducer]
+pub fn set_layers(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    belongs_to: Vec<u32>,
+    collides_with: Vec<u32>,
+) -> Result<(), String> {
+    info!(
+        "set_layers called for entity {}: belongs to {:?}, collides with {:?}",
+        entity_id, belongs_to, collides_with
+    );
+    let groups = InteractionGroups::new(
+        layer_bits(ctx, &belongs_to)?,
+        layer_bits(ctx, &collides_with)?,
+    );
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    set_body_groups(state, entity_id, rb_handle, groups)?;
+    let row = EntityLayers {
+        entity_id,
+        belongs_to,
+        collides_with,
+    };
+    if ctx.db.entity_layers().entity_id().find(entity_id).is_some() {
+        ctx.db.entity_layers().entity_id().update(row);
+    } else {
+        ctx.db
+            .entity_layers()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn set_collision_events(ctx: &ReducerContext, entity_id: u32, enabled: bool) -> Result<(), String> {
+    info!("set_collision_events called for entity {}: {}", entity_id, enabled);
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    if enabled {
+        state.collision_logging.insert(entity_id);
+    } else {
+        state.collision_logging.remove(&entity_id);
+    }
+    // Keeps the collider flag on while a collision trigger still needs it
+    sync_collision_events(ctx, state, entity_id, rb_handle)
+}
+
+#[reducer]
+pub fn set_active_collision_types(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    types: CollisionTypes,
+) -> Result<(), String> {
+    info!("set_active_collision_types called for entity {}: {:?}", entity_id, types);
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    set_body_collision_types(state, entity_id, rb_handle, types.into())
+}
+
+#[reducer]
+pub fn set_friction(ctx: &ReducerContext, entity_id: u32, friction: f32) -> Result<(), String> {
+    info!("set_friction called for entity {}: {}", entity_id, friction);
+    if !(friction.is_finite() && friction >= 0.0) {
+        return Err("Friction must be >= 0".to_string());
+    }
+    let (world_id, _, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let collider = state
+        .collider_set
+        .get_mut(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+    collider.set_friction(friction);
+    ctx.db
+        .entity_material()
+        .entity_id()
+        .update(EntityMaterial::from_collider(entity_id, collider));
+    Ok(())
+}
+
+#[reducer]
+pub fn set_restitution(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    restitution: f32,
+) -> Result<(), String> {
+    info!("set_restitution called for entity {}: {}", entity_id, restitution);
+    if !(0.0..=1.0).contains(&restitution) {
+        return Err("Restitution must be between 0 and 1".to_string());
+    }
+    let (world_id, _, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let collider = state
+        .collider_set
+        .get_mut(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+    collider.set_restitution(restitution);
+    ctx.db
+        .entity_material()
+        .entity_id()
+        .update(EntityMaterial::from_collider(entity_id, collider));
+    Ok(())
+}
+
+#[reducer]
+pub fn set_combine_rules(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    friction_rule: CombineRule,
+    restitution_rule: CombineRule,
+) -> Result<(), String> {
+    info!(
+        "set_combine_rules called for entity {}: friction {:?}, restitution {:?}",
+        entity_id, friction_rule, restitution_rule
+    );
+    let (world_id, _, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let collider = state
+        .collider_set
+        .get_mut(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+    collider.set_friction_combine_rule(friction_rule.into());
+    collider.set_restitution_combine_rule(restitution_rule.into());
+    Ok(())
+}
+
+#[reducer]
+pub fn set_density(ctx: &ReducerContext, entity_id: u32, density: f32) -> Result<(), String> {
+    info!("set_density called for entity {}: {}", entity_id, density);
+    if !(density.is_finite() && density > 0.0) {
+        return Err("Density must be > 0".to_string());
+    }
+    let (world_id, rb_handle, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let collider = state
+        .collider_set
+        .get_mut(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+    // Replaces any explicit `SpawnOptions::mass`: the collider's mass follows the density again
+    collider.set_density(density);
+    if let Some(mut center_of_mass) = ctx.db.entity_center_of_mass().entity_id().find(entity_id) {
+        center_of_mass.density = density;
+        apply_center_of_mass(state, rb_handle, co_handle, &center_of_mass)?;
+        ctx.db.entity_center_of_mass().entity_id().update(center_of_mass);
+    }
+    let collider = state
+        .collider_set
+        .get(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+    ctx.db
+        .entity_material()
+        .entity_id()
+        .update(EntityMaterial::from_collider(entity_id, collider));
+    refresh_body_mass(state, entity_id, rb_handle)
+}
+
+/// Recomputes a body's mass from its colliders right away (instead of at the next step)
+/// and wakes it, so a changed density shows up in the same tick.
+fn refresh_body_mass(
+    state: &mut PhysicsState,
+    entity_id: u32,
+    rb_handle: RigidBodyHandle,
+) -> Result<(), String> {
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    rigid_body.recompute_mass_properties_from_colliders(&state.collider_set);
+    rigid_body.wake_up(true);
+    Ok(())
+}
+
+/// Moves the mass of the primary collider (at `center_of_mass.density`) into the body's
+/// additional mass properties, centered on the override, and sets the collider's own
+/// density to 0. Replaces any earlier override instead of adding to it, so it can be
+/// reapplied whenever the collider's density or shape changes.
+fn apply_center_of_mass(
+    state: &mut PhysicsState,
+    rb_handle: RigidBodyHandle,
+    co_handle: ColliderHandle,
+    center_of_mass: &EntityCenterOfMass,
+) -> Result<(), String> {
+    let center = center_of_mass.center.to_finite_vector("Center of mass")?;
+    let collider = state
+        .collider_set
+        .get_mut(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", center_of_mass.entity_id))?;
+    collider.set_density(center_of_mass.density);
+    let offset = collider
+        .position_wrt_parent()
+        .copied()
+        .unwrap_or_else(Isometry::identity);
+    // Same mass and inertia as the collider contributes, in body space, but centered on
+    // the override. Attached colliders keep their own mass where they are.
+    let mut mass_properties = collider.mass_properties().transform_by(&offset);
+    mass_properties.local_com = Point::from(center);
+    collider.set_density(0.0);
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", center_of_mass.entity_id))?;
+    rigid_body.set_additional_mass_properties(mass_properties, true);
+    rigid_body.recompute_mass_properties_from_colliders(&state.collider_set);
+    Ok(())
+}
+
+#[reducer]
+pub fn set_center_of_mass(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    cx: f32,
+    cy: f32,
+    cz: f32,
+) -> Result<(), String> {
+    info!("set_center_of_mass called for entity {}: ({}, {}, {})", entity_id, cx, cy, cz);
+    let center = Vec3 { x: cx, y: cy, z: cz };
+    center.to_finite_vector("Center of mass")?;
+    let (world_id, rb_handle, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    // Rapier derives the center of mass from the colliders, so the primary collider's
+    // mass moves into the body's additional mass properties. The first override records
+    // the collider's density (or, for an explicit `SpawnOptions::mass`, the density that
+    // gives that mass); later ones reuse it since the collider is at 0 by then.
+    let existing = ctx.db.entity_center_of_mass().entity_id().find(entity_id);
+    let density = match &existing {
+        Some(center_of_mass) => center_of_mass.density,
+        None => {
+            let collider = state
+                .collider_set
+                .get(co_handle)
+                .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+            let unit_mass = collider.shape().mass_properties(1.0).mass();
+            if unit_mass > 0.0 {
+                collider.mass() / unit_mass
+            } else {
+                0.0
+            }
+        }
+    };
+    let center_of_mass = EntityCenterOfMass {
+        entity_id,
+        center,
+        density,
+    };
+    apply_center_of_mass(state, rb_handle, co_handle, &center_of_mass)?;
+    // The collider's density is 0 now
+    let collider = state
+        .collider_set
+        .get(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+    ctx.db
+        .entity_material()
+        .entity_id()
+        .update(EntityMaterial::from_collider(entity_id, collider));
+    if existing.is_some() {
+        ctx.db.entity_center_of_mass().entity_id().update(center_of_mass);
+    } else {
+        ctx.db
+            .entity_center_of_mass()
+            .try_insert(center_of_mass)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Replaces the entity's collider with a copy using `shape`, attached to the same body,
+/// and returns the new handle.
+fn swap_collider_shape(
+    state: &mut PhysicsState,
+    entity_id: u32,
+    rb_handle: RigidBodyHandle,
+    co_handle: ColliderHandle,
+    shape: SharedShape,
+) -> Result<ColliderHandle, String> {
+    let PhysicsState {
+        rigid_body_set,
+        collider_set,
+        island_manager,
+        collider_to_entity_id,
+        hooks,
+        ..
+    } = &mut *state;
+    let old_collider = collider_set
+        .get(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+
+    // The copy keeps material, flags and hooks. Inserting with a parent reads the
+    // position as relative to the body, so reset it to the offset from the body.
+    let mut collider = old_collider.clone();
+    collider.set_shape(shape);
+    collider.set_position(
+        old_collider
+            .position_wrt_parent()
+            .copied()
+            .unwrap_or_else(Isometry::identity),
+    );
+    // Only the collider is swapped, so the body keeps its pose and velocity;
+    // its mass properties follow the new size
+    collider_set.remove(co_handle, island_manager, rigid_body_set, true);
+    let new_handle = collider_set.insert_with_parent(collider, rb_handle, rigid_body_set);
+    collider_to_entity_id.remove(&co_handle);
+    collider_to_entity_id.insert(new_handle, entity_id);
+    hooks.replace_collider(co_handle, new_handle);
+    Ok(new_handle)
+}
+
+#[reducer]
+pub fn scale_collider(ctx: &ReducerContext, entity_id: u32, factor: f32) -> Result<(), String> {
+    info!("scale_collider called for entity {}: {}", entity_id, factor);
+    if !(factor.is_finite() && factor > 0.0) {
+        return Err("Scale factor must be > 0".to_string());
+    }
+    let (world_id, rb_handle, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut entity_shape = ctx
+        .db
+        .entity_shape()
+        .entity_id()
+        .find(entity_id)
+        .ok_or_else(|| format!("Shape for entity {} not found", entity_id))?;
+    let scaled = entity_shape.shape.scaled(factor)?;
+    let shared_shape = scaled.collider_builder()?.shape;
+
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let new_handle = swap_collider_shape(state, entity_id, rb_handle, co_handle, shared_shape)?;
+    // The copy still has the override's zero density; put the mass back at the new size
+    if let Some(center_of_mass) = ctx.db.entity_center_of_mass().entity_id().find(entity_id) {
+        apply_center_of_mass(state, rb_handle, new_handle, &center_of_mass)?;
+    }
+
+    let mut physics = ctx
+        .db
+        .entity_physics()
+        .entity_id()
+        .find(entity_id)
+        .ok_or_else(|| format!("Entity {} has no physics row", entity_id))?;
+    let (co_idx, co_gen) = new_handle.into_raw_parts();
+    physics.co_handle_index = co_idx;
+    physics.co_handle_generation = co_gen;
+    ctx.db.entity_physics().entity_id().update(physics);
+    entity_shape.shape = scaled;
+    ctx.db.entity_shape().entity_id().update(entity_shape);
+    Ok(())
+}
+
+#[reducer]
+pub fn set_conveyor(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    vx: f32,
+    vy: f32,
+    vz: f32,
+) -> Result<(), String> {
+    info!("set_conveyor called for entity {}: ({}, {}, {})", entity_id, vx, vy, vz);
+    let velocity = Vec3 { x: vx, y: vy, z: vz }.to_finite_vector("Conveyor velocity")?;
+    let (world_id, _, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let collider = state
+        .collider_set
+        .get_mut(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+    // The surface itself stays put; only bodies resting on it get dragged along
+    if velocity == Vector3::zeros() {
+        state.hooks.conveyors.remove(&co_handle);
+    } else {
+        state.hooks.conveyors.insert(co_handle, velocity);
+    }
+    state.hooks.sync_active_hooks(co_handle, collider);
+    Ok(())
+}
+
+#[reducer]
+pub fn set_friction_axis(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    ax: f32,
+    ay: f32,
+    az: f32,
+    along_friction: f32,
+    across_friction: f32,
+) -> Result<(), String> {
+    info!(
+        "set_friction_axis called for entity {}: ({}, {}, {}), along {}, across {}",
+        entity_id, ax, ay, az, along_friction, across_friction
+    );
+    let axis = Vec3 { x: ax, y: ay, z: az };
+    let axis_vector = axis.to_finite_vector("Friction axis")?;
+    if ![along_friction, across_friction].iter().all(|f| f.is_finite() && *f >= 0.0) {
+        return Err("Friction must be >= 0".to_string());
+    }
+    let (world_id, _, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let collider = state
+        .collider_set
+        .get_mut(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+    // A zero axis turns the collider back to plain isotropic friction
+    ctx.db.entity_friction_axis().entity_id().delete(entity_id);
+    match axis_vector.try_normalize(1.0e-6) {
+        Some(axis_vector) => {
+            let friction_axis = FrictionAxis {
+                axis: axis_vector,
+                along: along_friction,
+                across: across_friction,
+            };
+            state.hooks.friction_axes.insert(co_handle, friction_axis);
+            ctx.db
+                .entity_friction_axis()
+                .try_insert(EntityFrictionAxis {
+                    entity_id,
+                    axis,
+                    along_friction,
+                    across_friction,
+                })
+                .map_err(|e| e.to_string())?;
+        }
+        None => {
+            state.hooks.friction_axes.remove(&co_handle);
+        }
+    }
+    state.hooks.sync_active_hooks(co_handle, collider);
+    Ok(())
+}
+
+#[reducer]
+pub fn set_one_way_platform(ctx: &ReducerContext, entity_id: u32, enabled: bool) -> Result<(), String> {
+    info!("set_one_way_platform called for entity {}: {}", entity_id, enabled);
+    let (world_id, _, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let collider = state
+        .collider_set
+        .get_mut(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+    if enabled {
+        state.hooks.one_way_platforms.insert(co_handle);
+    } else {
+        state.hooks.one_way_platforms.remove(&co_handle);
+    }
+    state.hooks.sync_active_hooks(co_handle, collider);
+    Ok(())
+}
+
+#[reducer]
+pub fn set_active_hooks(ctx: &ReducerContext, entity_id: u32, flags: HookFlags) -> Result<(), String> {
+    info!("set_active_hooks called for entity {}: {:?}", entity_id, flags);
+    let (world_id, _, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let collider = state
+        .collider_set
+        .get_mut(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+    let flags = ActiveHooks::from(flags);
+    if flags.is_empty() {
+        state.hooks.requested.remove(&co_handle);
+    } else {
+        state.hooks.requested.insert(co_handle, flags);
+    }
+    // Merged with what conveyors, one-way platforms and friction axes need
+    state.hooks.sync_active_hooks(co_handle, collider);
+    Ok(())
+}
+
+#[reducer]
+pub fn set_sensor(ctx: &ReducerContext, entity_id: u32, is_sensor: bool) -> Result<(), String> {
+    info!("set_sensor called for entity {}: {}", entity_id, is_sensor);
+    let (world_id, _, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    set_collider_sensor(state, entity_id, co_handle, is_sensor)
+}
+
+/// Turns a collider into a sensor or back into a solid, waking every body touching it.
+fn set_collider_sensor(
+    state: &mut PhysicsState,
+    entity_id: u32,
+    co_handle: ColliderHandle,
+    is_sensor: bool,
+) -> Result<(), String> {
+    let PhysicsState {
+        rigid_body_set,
+        collider_set,
+        narrow_phase,
+        ..
+    } = state;
+
+    let collider = collider_set
+        .get_mut(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+    collider.set_sensor(is_sensor);
+
+    // Sleeping bodies resting on (or overlapping) the collider wouldn't notice the change:
+    // they'd hover over a new sensor, or stay embedded in a newly solid collider
+    let touching = narrow_phase
+        .contact_pairs_with(co_handle)
+        .map(|pair| (pair.collider1, pair.collider2))
+        .chain(
+            narrow_phase
+                .intersection_pairs_with(co_handle)
+                .map(|(collider1, collider2, _)| (collider1, collider2)),
+        );
+    for (collider1, collider2) in touching {
+        for handle in [collider1, collider2] {
+            let parent = collider_set.get(handle).and_then(|collider| collider.parent());
+            if let Some(rigid_body) = parent.and_then(|parent| rigid_body_set.get_mut(parent)) {
+                rigid_body.wake_up(true);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn set_collider_enabled(ctx: &ReducerContext, entity_id: u32, enabled: bool) -> Result<(), String> {
+    info!("set_collider_enabled called for entity {}: {}", entity_id, enabled);
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    set_body_colliders_enabled(state, entity_id, rb_handle, enabled)
+}
+
+/// Enables or disables every collider on an entity's body and wakes it.
+fn set_body_colliders_enabled(
+    state: &mut PhysicsState,
+    entity_id: u32,
+    rb_handle: RigidBodyHandle,
+    enabled: bool,
+) -> Result<(), String> {
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    // The body keeps integrating (and its transform keeps updating); it just stops colliding.
+    // Every attached collider is toggled so compound bodies go fully no-clip.
+    rigid_body.wake_up(true);
+    for co_handle in rigid_body.colliders() {
+        if let Some(collider) = state.collider_set.get_mut(*co_handle) {
+            collider.set_enabled(enabled);
+        }
+    }
+    Ok(())
+}
+
+/// Puts every collider of the body into `groups`, for both collision detection and the solver.
+fn set_body_groups(
+    state: &mut PhysicsState,
+    entity_id: u32,
+    rb_handle: RigidBodyHandle,
+    groups: InteractionGroups,
+) -> Result<(), String> {
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    // Two colliders interact only if each one's layers are in the other's `collides_with`.
+    // Solver contacts and sensor events both respect the same groups.
+    rigid_body.wake_up(true);
+    for co_handle in rigid_body.colliders() {
+        if let Some(collider) = state.collider_set.get_mut(*co_handle) {
+            collider.set_collision_groups(groups);
+            collider.set_solver_groups(groups);
+        }
+    }
+    Ok(())
+}
+
+/// Sets which body-type pairs every collider of the body interacts with.
+fn set_body_collision_types(
+    state: &mut PhysicsState,
+    entity_id: u32,
+    rb_handle: RigidBodyHandle,
+    types: ActiveCollisionTypes,
+) -> Result<(), String> {
+    let rigid_body = state
+        .rigid_body_set
+        .get_mut(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    // A pair is checked if either collider enables it, so one sensor set to
+    // `kinematic_fixed` is enough to see kinematic bodies
+    rigid_body.wake_up(true);
+    for co_handle in rigid_body.colliders() {
+        if let Some(collider) = state.collider_set.get_mut(*co_handle) {
+            collider.set_active_collision_types(types.into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tick::apply_fluid_zones;
+
+    /// Drops a ball onto another resting on the ground and returns the fastest speed at
+    /// which it flies back up.
+    fn rebound_speed(resting_restitution: f32) -> f32 {
+        let mut state = PhysicsState::new(0);
+        let resting = state.add_test_ball(Vector3::new(0.0, 1.1, 0.0));
+        let co_handle = state.rigid_body_set[resting].colliders()[0];
+        state.collider_set[co_handle].set_restitution(resting_restitution);
+        let dropped = state.add_test_ball(Vector3::new(0.0, 6.0, 0.0));
+        let mut rebound: f32 = 0.0;
+        for _ in 0..90 {
+            state.step_once();
+            rebound = rebound.max(state.rigid_body_set[dropped].linvel().y);
+        }
+        rebound
+    }
+
+    #[test]
+    fn raising_restitution_makes_a_dropped_ball_bounce_higher() {
+        let dull = rebound_speed(0.0);
+        let bouncy = rebound_speed(1.0);
+        assert!(bouncy > dull + 1.0, "rebound {} vs {}", bouncy, dull);
+    }
+
+    #[test]
+    fn attached_collider_props_the_body_up_and_catches_falling_balls() {
+        let mut state = PhysicsState::new(0);
+        let body = state.add_test_ball(Vector3::new(0.0, 4.0, 0.0));
+        // A wide slab under the ball, so the body stands on the slab rather than the ball
+        let slab = ShapeDesc {
+            kind: ShapeKind::Cuboid,
+            radius: 0.0,
+            half_x: 1.5,
+            half_y: 0.5,
+            half_z: 1.5,
+        };
+        let slab_handle =
+            attach_to_body(&mut state, 1, body, &slab, Vector3::new(0.0, -2.0, 0.0)).unwrap();
+        assert_eq!(state.collider_to_entity_id.get(&slab_handle), Some(&1));
+        assert_eq!(state.rigid_body_set[body].colliders().len(), 2);
+        for _ in 0..120 {
+            state.step_once();
+        }
+        // Ground top 0.1 + slab half-height 0.5 + slab offset 2.0
+        let standing_y = state.rigid_body_set[body].translation().y;
+        assert!(
+            (standing_y - 2.6).abs() < 0.05,
+            "body stands at {}",
+            standing_y
+        );
+
+        // The ball part still collides: a ball dropped on it stops on top
+        let dropped = state.add_test_ball(Vector3::new(0.0, 8.0, 0.0));
+        for _ in 0..120 {
+            state.step_once();
+        }
+        assert!(state.rigid_body_set[dropped].translation().y > standing_y + 1.5);
+    }
+
+    #[test]
+    fn disabled_ball_falls_through_the_ground_while_others_land() {
+        let mut state = PhysicsState::new(0);
+        let ghost = state.add_test_ball(Vector3::new(0.0, 3.0, 0.0));
+        let solid = state.add_test_ball(Vector3::new(3.0, 3.0, 0.0));
+        set_body_colliders_enabled(&mut state, 1, ghost, false).unwrap();
+        for _ in 0..120 {
+            state.step_once();
+        }
+
+        let ghost_body = &state.rigid_body_set[ghost];
+        assert!(
+            ghost_body.translation().y < -5.0,
+            "ghost at {:?}",
+            ghost_body.translation()
+        );
+        assert!(ghost_body.linvel().y < -10.0);
+        assert!((state.rigid_body_set[solid].translation().y - 0.6).abs() < 0.05);
+
+        set_body_colliders_enabled(&mut state, 1, ghost, true).unwrap();
+        let co_handle = state.rigid_body_set[ghost].colliders()[0];
+        assert!(state.collider_set[co_handle].is_enabled());
+    }
+
+    #[test]
+    fn ball_falls_through_a_floor_turned_sensor() {
+        let mut state = PhysicsState::new(0);
+        let floor = state.collider_set.insert(
+            ColliderBuilder::cuboid(3.0, 0.1, 3.0)
+                .translation(Vector3::new(0.0, 3.0, 0.0))
+                .build(),
+        );
+        let ball = state.add_test_ball(Vector3::new(0.0, 4.2, 0.0));
+        for _ in 0..240 {
+            state.step_once();
+        }
+        assert!((state.rigid_body_set[ball].translation().y - 4.1).abs() < 0.05);
+
+        // Even a ball that fell asleep on the floor drops once it becomes a sensor
+        set_collider_sensor(&mut state, 1, floor, true).unwrap();
+        assert!(!state.rigid_body_set[ball].is_sleeping());
+        for _ in 0..120 {
+            state.step_once();
+        }
+        let landed_y = state.rigid_body_set[ball].translation().y;
+        assert!((landed_y - 1.1).abs() < 0.05, "ball at {}", landed_y);
+    }
+
+    /// Releases a unit ball of `density` at rest 5m below the surface of a deep pool and
+    /// returns how fast it sinks after one second.
+    fn sink_speed(density: f32) -> f32 {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 15.0, 0.0));
+        let co_handle = state.rigid_body_set[rb_handle].colliders()[0];
+        state.collider_set[co_handle].set_density(density);
+        refresh_body_mass(&mut state, 1, rb_handle).unwrap();
+        let expected_mass = density * 4.0 / 3.0 * std::f32::consts::PI;
+        assert!((state.rigid_body_set[rb_handle].mass() - expected_mass).abs() < 1.0e-3);
+
+        let pools = [FluidZone {
+            id: 1,
+            world_id: 0,
+            center: Vec3 {
+                x: 0.0,
+                y: 10.0,
+                z: 0.0,
+            },
+            half_extents: Vec3 {
+                x: 10.0,
+                y: 10.0,
+                z: 10.0,
+            },
+            density: 1.0,
+            drag: 2.0,
+        }];
+        for _ in 0..60 {
+            state.rigid_body_set[rb_handle].reset_forces(false);
+            apply_fluid_zones(&mut state.rigid_body_set, &state.collider_set, &pools);
+            state.step_once();
+        }
+        -state.rigid_body_set[rb_handle].linvel().y
+    }
+
+    #[test]
+    fn denser_ball_sinks_faster_through_a_fluid_zone() {
+        let light = sink_speed(1.5);
+        let heavy = sink_speed(4.0);
+        assert!(light > 0.0);
+        assert!(heavy > light + 1.0, "sinks at {} vs {}", heavy, light);
+    }
+
+    /// Drops a 0.5×2×0.5 post tipped 45° onto the ground, optionally with its center of
+    /// mass moved to `center`, and returns its final tilt and mass.
+    fn tipped_post(center: Option<Vector3<f32>>) -> (f32, f32) {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.rigid_body_set.insert(
+            RigidBodyBuilder::dynamic()
+                .translation(Vector3::new(0.0, 1.5, 0.0))
+                .rotation(Vector3::new(0.0, 0.0, std::f32::consts::FRAC_PI_4))
+                .build(),
+        );
+        let co_handle = state.collider_set.insert_with_parent(
+            ColliderBuilder::cuboid(0.25, 1.0, 0.25).build(),
+            rb_handle,
+            &mut state.rigid_body_set,
+        );
+        if let Some(center) = center {
+            let center_of_mass = EntityCenterOfMass {
+                entity_id: 1,
+                center: Vec3 {
+                    x: center.x,
+                    y: center.y,
+                    z: center.z,
+                },
+                density: 1.0,
+            };
+            apply_center_of_mass(&mut state, rb_handle, co_handle, &center_of_mass).unwrap();
+        }
+        for _ in 0..480 {
+            state.step_once();
+        }
+        let body = &state.rigid_body_set[rb_handle];
+        (body.rotation().angle(), body.mass())
+    }
+
+    #[test]
+    fn low_center_of_mass_rights_a_tipped_post() {
+        let (toppled_tilt, mass) = tipped_post(None);
+        assert!(toppled_tilt > 1.4, "plain post ended at {}", toppled_tilt);
+        assert!((mass - 0.5).abs() < 1.0e-4);
+
+        // The collider's mass moves into the override instead of counting twice
+        let (righted_tilt, weeble_mass) = tipped_post(Some(Vector3::new(0.0, -0.9, 0.0)));
+        assert!(righted_tilt < 0.1, "weeble ended at {}", righted_tilt);
+        assert!((weeble_mass - 0.5).abs() < 1.0e-4, "mass {}", weeble_mass);
+    }
+
+    #[test]
+    fn material_row_mirrors_the_collider_through_setters_and_center_of_mass() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 1.1, 0.0));
+        let co_handle = state.rigid_body_set[rb_handle].colliders()[0];
+        let collider = &mut state.collider_set[co_handle];
+        collider.set_friction(0.3);
+        collider.set_restitution(0.9);
+        collider.set_density(2.5);
+        let material = EntityMaterial::from_collider(7, collider);
+        assert_eq!(
+            (
+                material.entity_id,
+                material.friction,
+                material.restitution,
+                material.density
+            ),
+            (7, 0.3, 0.9, 2.5)
+        );
+
+        // The override carries the mass, so the row must report the collider's density as 0
+        let center_of_mass = EntityCenterOfMass {
+            entity_id: 7,
+            center: Vec3 {
+                x: 0.0,
+                y: -0.5,
+                z: 0.0,
+            },
+            density: 2.5,
+        };
+        apply_center_of_mass(&mut state, rb_handle, co_handle, &center_of_mass).unwrap();
+        let material = EntityMaterial::from_collider(7, &state.collider_set[co_handle]);
+        assert_eq!((material.friction, material.density), (0.3, 0.0));
+    }
+
+    #[test]
+    fn halved_ball_drops_through_a_gap_it_rested_on() {
+        let mut state = PhysicsState::new(0);
+        // Two slabs at y = 5 leaving a 1.2 wide gap around x = 0
+        for x in [-5.6, 5.6] {
+            state.collider_set.insert(
+                ColliderBuilder::cuboid(5.0, 0.1, 5.0)
+                    .translation(Vector3::new(x, 5.0, 0.0))
+                    .build(),
+            );
+        }
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 7.0, 0.0));
+        let co_handle = state.rigid_body_set[rb_handle].colliders()[0];
+        state.collider_to_entity_id.insert(co_handle, 1);
+        for _ in 0..120 {
+            state.step_once();
+        }
+        assert!(state.rigid_body_set[rb_handle].translation().y > 5.0);
+
+        let pose = *state.rigid_body_set[rb_handle].position();
+        let velocity = *state.rigid_body_set[rb_handle].linvel();
+        let shape = ShapeDesc::ball(1.0).scaled(0.5).unwrap();
+        let new_handle = swap_collider_shape(
+            &mut state,
+            1,
+            rb_handle,
+            co_handle,
+            shape.collider_builder().unwrap().shape,
+        )
+        .unwrap();
+        assert_eq!(*state.rigid_body_set[rb_handle].position(), pose);
+        assert_eq!(*state.rigid_body_set[rb_handle].linvel(), velocity);
+        assert_eq!(state.collider_to_entity_id.get(&new_handle), Some(&1));
+        assert!(!state.collider_to_entity_id.contains_key(&co_handle));
+
+        for _ in 0..180 {
+            state.step_once();
+        }
+        // Resting on the ground below: half-radius above its top at y = 0.1
+        assert!((state.rigid_body_set[rb_handle].translation().y - 0.6).abs() < 0.05);
+    }
+
+    #[test]
+    fn players_and_enemies_collide_with_each_other_but_not_themselves() {
+        const PLAYER: u32 = 0;
+        const ENEMY: u32 = 1;
+        assert_eq!(
+            layers_to_group(&[PLAYER, ENEMY]),
+            Group::GROUP_1 | Group::GROUP_2
+        );
+        let player = InteractionGroups::new(layers_to_group(&[PLAYER]), layers_to_group(&[ENEMY]));
+        let enemy = InteractionGroups::new(layers_to_group(&[ENEMY]), layers_to_group(&[PLAYER]));
+
+        // A player dropped onto each kind of resting body; the ground keeps the default groups
+        let mut state = PhysicsState::new(0);
+        let drops = [(-5.0, player), (5.0, enemy)].map(|(x, below_groups)| {
+            let below = state.add_test_ball(Vector3::new(x, 1.1, 0.0));
+            let dropped = state.add_test_ball(Vector3::new(x, 4.0, 0.0));
+            set_body_groups(&mut state, 1, below, below_groups).unwrap();
+            set_body_groups(&mut state, 2, dropped, player).unwrap();
+            dropped
+        });
+        for _ in 0..120 {
+            state.step_once();
+        }
+
+        let height = |handle: RigidBodyHandle| state.rigid_body_set[handle].translation().y;
+        // Through the other player down to the ground, but stacked on top of the enemy
+        assert!(
+            (height(drops[0]) - 1.1).abs() < 0.1,
+            "on player at {}",
+            height(drops[0])
+        );
+        assert!(
+            (height(drops[1]) - 3.1).abs() < 0.1,
+            "on enemy at {}",
+            height(drops[1])
+        );
+    }
+
+    #[test]
+    fn fixed_sensor_sees_a_kinematic_body_only_once_kinematic_fixed_is_active() {
+        let mut state = PhysicsState::new(0);
+        let sensor_body = state.rigid_body_set.insert(
+            RigidBodyBuilder::fixed()
+                .translation(Vector3::new(0.0, 3.0, 0.0))
+                .build(),
+        );
+        let sensor = state.collider_set.insert_with_parent(
+            ColliderBuilder::cuboid(2.0, 2.0, 2.0).sensor(true).build(),
+            sensor_body,
+            &mut state.rigid_body_set,
+        );
+        let character = state.rigid_body_set.insert(
+            RigidBodyBuilder::kinematic_position_based()
+                .translation(Vector3::new(0.0, 3.0, 0.0))
+                .build(),
+        );
+        let character_collider = state.collider_set.insert_with_parent(
+            ColliderBuilder::capsule_y(0.5, 0.3).build(),
+            character,
+            &mut state.rigid_body_set,
+        );
+        state.step_once();
+        let overlapping = |state: &PhysicsState| {
+            state
+                .narrow_phase
+                .intersection_pair(sensor, character_collider)
+                == Some(true)
+        };
+        assert!(!overlapping(&state));
+
+        let types = CollisionTypes {
+            dynamic_dynamic: false,
+            dynamic_kinematic: false,
+            dynamic_fixed: true,
+            kinematic_kinematic: false,
+            kinematic_fixed: true,
+            fixed_fixed: false,
+        };
+        assert_eq!(
+            ActiveCollisionTypes::from(types),
+            ActiveCollisionTypes::DYNAMIC_FIXED | ActiveCollisionTypes::KINEMATIC_FIXED
+        );
+        // Enabling the pair on the sensor's side alone is enough
+        set_body_collision_types(&mut state, 1, sensor_body, types.into()).unwrap();
+        state.rigid_body_set[character].set_next_kinematic_translation(Vector3::new(0.1, 3.0, 0.0));
+        state.step_once();
+        assert!(overlapping(&state));
+    }
+
+    #[test]
+    fn box_on_a_conveyor_drifts_along_the_belt() {
+        let mut state = PhysicsState::new(0);
+        let ground = state.collider_set.iter().next().unwrap().0;
+        state
+            .hooks
+            .conveyors
+            .insert(ground, Vector3::new(2.0, 0.0, 0.0));
+        state
+            .hooks
+            .sync_active_hooks(ground, &mut state.collider_set[ground]);
+        let rb_handle = state.rigid_body_set.insert(
+            RigidBodyBuilder::dynamic()
+                .translation(Vector3::new(0.0, 0.6, 0.0))
+                .build(),
+        );
+        state.collider_set.insert_with_parent(
+            ColliderBuilder::cuboid(0.5, 0.5, 0.5).build(),
+            rb_handle,
+            &mut state.rigid_body_set,
+        );
+        for _ in 0..60 {
+            state.step_once();
+        }
+
+        let body = &state.rigid_body_set[rb_handle];
+        assert!(
+            body.translation().x > 1.0,
+            "box at {:?}",
+            body.translation()
+        );
+        assert!(
+            (body.linvel().x - 2.0).abs() < 0.2,
+            "box moves at {:?}",
+            body.linvel()
+        );
+        assert!(body.translation().z.abs() < 0.01);
+    }
+
+    #[test]
+    fn body_jumps_up_through_a_one_way_platform_and_lands_on_top() {
+        let mut state = PhysicsState::new(0);
+        let platform = state.collider_set.insert(
+            ColliderBuilder::cuboid(3.0, 0.1, 3.0)
+                .translation(Vector3::new(0.0, 4.0, 0.0))
+                .build(),
+        );
+        state.hooks.one_way_platforms.insert(platform);
+        state
+            .hooks
+            .sync_active_hooks(platform, &mut state.collider_set[platform]);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 1.1, 0.0));
+        state.rigid_body_set[rb_handle].set_linvel(Vector3::new(0.0, 12.0, 0.0), true);
+
+        let mut highest: f32 = 0.0;
+        for _ in 0..240 {
+            state.step_once();
+            highest = highest.max(state.rigid_body_set[rb_handle].translation().y);
+        }
+        // Platform top at 4.1 plus the unit radius
+        assert!(highest > 5.1, "ball only rose to {}", highest);
+        let resting_y = state.rigid_body_set[rb_handle].translation().y;
+        assert!(
+            (resting_y - 5.1).abs() < 0.05,
+            "ball rests at {}",
+            resting_y
+        );
+    }
+
+    #[test]
+    fn box_slides_freely_along_the_friction_axis_but_grips_across_it() {
+        let mut state = PhysicsState::new(0);
+        let ground = state.collider_set.iter().next().unwrap().0;
+        state.hooks.friction_axes.insert(
+            ground,
+            FrictionAxis {
+                axis: Vector3::x(),
+                along: 0.0,
+                across: 1.0,
+            },
+        );
+        state
+            .hooks
+            .sync_active_hooks(ground, &mut state.collider_set[ground]);
+        let mut pushed_box = |z: f32, velocity: Vector3<f32>| {
+            let rb_handle = state.rigid_body_set.insert(
+                RigidBodyBuilder::dynamic()
+                    .translation(Vector3::new(0.0, 0.6, z))
+                    .linvel(velocity)
+                    .build(),
+            );
+            state.collider_set.insert_with_parent(
+                ColliderBuilder::cuboid(0.5, 0.5, 0.5).build(),
+                rb_handle,
+                &mut state.rigid_body_set,
+            );
+            rb_handle
+        };
+        let along = pushed_box(-5.0, Vector3::new(3.0, 0.0, 0.0));
+        let across = pushed_box(5.0, Vector3::new(0.0, 0.0, 3.0));
+        for _ in 0..60 {
+            state.step_once();
+        }
+
+        // Frictionless: a second at 3 m/s. Full friction stops it within half a meter.
+        let along = state.rigid_body_set[along].translation();
+        assert!(along.x > 2.5, "along-axis box at {:?}", along);
+        let across = state.rigid_body_set[across].translation();
+        assert!(across.z - 5.0 < 0.6, "cross-axis box at {:?}", across);
+        assert!(across.x.abs() < 0.01);
+    }
+}
@@ -0,0 +1,299 @@
+//! Optional uniform grid over the published transforms, for proximity queries that
+//! never wait on `PHYSICS_STATE`.
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use rapier3d::na::Vector3;
+use spacetimedb::{reducer, ReducerContext, Table};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::tables::*;
+use crate::types::*;
+
+/// Entity origins bucketed into cubic cells of `cell_size`.
+pub(crate) struct SpatialGrid {
+    pub(crate) cell_size: f32,
+    pub(crate) cells: HashMap<(i32, i32, i32), Vec<(u32, Vector3<f32>)>>,
+}
+
+impl SpatialGrid {
+    pub(crate) fn new(cell_size: f32) -> Self {
+        SpatialGrid {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell_of(&self, point: &Vector3<f32>) -> (i32, i32, i32) {
+        let cell = point / self.cell_size;
+        (cell.x.floor() as i32, cell.y.floor() as i32, cell.z.floor() as i32)
+    }
+
+    /// Refills the cells from the world's `EntityTransform` rows.
+    pub(crate) fn rebuild(&mut self, ctx: &ReducerContext, world_id: u32) {
+        self.fill(ctx.db.entity_transform().world_id().filter(world_id));
+    }
+
+    /// Replaces the cells' contents with the given transforms.
+    pub(crate) fn fill(&mut self, transforms: impl IntoIterator<Item = EntityTransform>) {
+        self.cells.clear();
+        for transform in transforms {
+            let position = Vector3::new(transform.x as f32, transform.y as f32, transform.z as f32);
+            let cell = self.cell_of(&position);
+            self.cells
+                .entry(cell)
+                .or_default()
+                .push((transform.entity_id, position));
+        }
+    }
+
+    /// Entities whose origin lies inside the box, in no particular order.
+    pub(crate) fn query_box(
+        &self,
+        min: &Vector3<f32>,
+        max: &Vector3<f32>,
+    ) -> Result<Vec<(u32, Vector3<f32>)>, String> {
+        if min.iter().zip(max.iter()).any(|(lo, hi)| lo > hi) {
+            return Err("Box min must not exceed max".to_string());
+        }
+        let (lo, hi) = (self.cell_of(min), self.cell_of(max));
+        let inside = |p: &Vector3<f32>| (0..3).all(|i| min[i] <= p[i] && p[i] <= max[i]);
+        // At most 2^32 per axis (`cell_of` saturates), so the product can overflow u64
+        let span = |a: i32, b: i32| (b as i64 - a as i64 + 1) as u64;
+        let cell_count = span(lo.0, hi.0)
+            .checked_mul(span(lo.1, hi.1))
+            .and_then(|count| count.checked_mul(span(lo.2, hi.2)));
+        // A box spanning more cells than are occupied is cheaper to answer by visiting
+        // the occupied ones than by walking the empty range
+        if !matches!(cell_count, Some(count) if count <= self.cells.len() as u64) {
+            return Ok(self
+                .cells
+                .values()
+                .flatten()
+                .filter(|(_, p)| inside(p))
+                .copied()
+                .collect());
+        }
+        let mut found = Vec::new();
+        for x in lo.0..=hi.0 {
+            for y in lo.1..=hi.1 {
+                for z in lo.2..=hi.2 {
+                    if let Some(cell) = self.cells.get(&(x, y, z)) {
+                        found.extend(cell.iter().filter(|(_, p)| inside(p)).copied());
+                    }
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Entities whose origin lies within `r` of `center`, in no particular order.
+    pub(crate) fn query_sphere(&self, center: &Vector3<f32>, r: f32) -> Result<Vec<u32>, String> {
+        let radius = Vector3::repeat(r);
+        Ok(self
+            .query_box(&(center - radius), &(center + radius))?
+            .into_iter()
+            .filter(|(_, p)| (p - center).norm_squared() <= r * r)
+            .map(|(entity_id, _)| entity_id)
+            .collect())
+    }
+}
+
+/// Grids of the worlds that enabled one, see `set_grid_cell_size`. Guarded separately
+/// from the physics worlds.
+pub(crate) static SPATIAL_GRIDS: Lazy<Mutex<HashMap<u32, SpatialGrid>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Locks the grid map, recovering from poisoning like `lock_state`. The grids are
+/// rebuilt from the tables every tick, so a half-updated one heals itself.
+pub(crate) fn lock_grids() -> MutexGuard<'static, HashMap<u32, SpatialGrid>> {
+    SPATIAL_GRIDS.lock().unwrap_or_else(|poisoned| {
+        warn!("SPATIAL_GRIDS lock was poisoned by a panic; recovering");
+        SPATIAL_GRIDS.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// Rebuilds the world's grid if `set_grid_cell_size` configured one; worlds without a
+/// grid don't read any rows. Called at the end of each tick, after the physics lock is
+/// released.
+pub(crate) fn rebuild_grid_if_enabled(ctx: &ReducerContext, world_id: u32) {
+    let mut grids = lock_grids();
+    let Some(grid) = grids.get_mut(&world_id) else {
+        return;
+    };
+    grid.rebuild(ctx, world_id);
+}
+
+/// Replaces the world's `GridQueryResult` rows with `entity_ids`.
+fn write_grid_result(ctx: &ReducerContext, world_id: u32, mut entity_ids: Vec<u32>) -> Result<(), String> {
+    entity_ids.sort_unstable();
+    let stale: Vec<u32> = ctx
+        .db
+        .grid_query_result()
+        .world_id()
+        .filter(world_id)
+        .map(|row| row.id)
+        .collect();
+    for id in stale {
+        ctx.db.grid_query_result().id().delete(id);
+    }
+    let mut next_id = ctx
+        .db
+        .grid_query_result()
+        .iter()
+        .map(|row| row.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    for entity_id in entity_ids {
+        ctx.db
+            .grid_query_result()
+            .try_insert(GridQueryResult {
+                id: next_id,
+                world_id,
+                entity_id,
+                queried_at: ctx.timestamp,
+            })
+            .map_err(|e| e.to_string())?;
+        next_id += 1;
+    }
+    Ok(())
+}
+
+// --- Reducers ---
+
+#[reducer]
+pub fn set_grid_cell_size(ctx: &ReducerContext, world_id: u32, cell_size: Option<f32>) -> Result<(), String> {
+    info!("set_grid_cell_size called for world {}: {:?}", world_id, cell_size);
+    let mut grids = lock_grids();
+    let Some(cell_size) = cell_size else {
+        grids.remove(&world_id); // None turns the grid off
+        return Ok(());
+    };
+    if !(cell_size.is_finite() && cell_size > 0.0) {
+        return Err("Cell size must be > 0".to_string());
+    }
+    // Built right away so queries work before the next tick
+    let mut grid = SpatialGrid::new(cell_size);
+    grid.rebuild(ctx, world_id);
+    grids.insert(world_id, grid);
+    Ok(())
+}
+
+#[reducer]
+pub fn query_grid_sphere(ctx: &ReducerContext, world_id: u32, x: f32, y: f32, z: f32, r: f32) -> Result<(), String> {
+    info!("query_grid_sphere called for world {}: ({}, {}, {}) r {}", world_id, x, y, z, r);
+    let center = Vec3 { x, y, z }.to_finite_vector("Sphere center")?;
+    if !(r.is_finite() && r >= 0.0) {
+        return Err("Radius must be >= 0".to_string());
+    }
+    let entity_ids: Vec<u32> = {
+        let grids = lock_grids();
+        let grid = grids.get(&world_id).ok_or_else(|| {
+            format!("World {} has no spatial grid (see set_grid_cell_size)", world_id)
+        })?;
+        grid.query_sphere(&center, r)?
+    };
+    write_grid_result(ctx, world_id, entity_ids)
+}
+
+#[reducer]
+pub fn query_grid_box(ctx: &ReducerContext, world_id: u32, min: Vec3, max: Vec3) -> Result<(), String> {
+    info!("query_grid_box called for world {}: {:?} to {:?}", world_id, min, max);
+    let min = min.to_finite_vector("Box min")?;
+    let max = max.to_finite_vector("Box max")?;
+    let entity_ids: Vec<u32> = {
+        let grids = lock_grids();
+        let grid = grids.get(&world_id).ok_or_else(|| {
+            format!("World {} has no spatial grid (see set_grid_cell_size)", world_id)
+        })?;
+        grid.query_box(&min, &max)?
+            .into_iter()
+            .map(|(entity_id, _)| entity_id)
+            .collect()
+    };
+    write_grid_result(ctx, world_id, entity_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// 500 transforms scattered over a 40m cube around the origin, as (entity id, position).
+    fn scattered_grid(cell_size: f32) -> (SpatialGrid, Vec<(u32, Vector3<f32>)>) {
+        let mut rng = StdRng::seed_from_u64(7);
+        let points: Vec<(u32, Vector3<f32>)> = (1..=500)
+            .map(|entity_id| {
+                let position = Vector3::from_fn(|_, _| rng.gen_range(-20.0..20.0));
+                (entity_id, position)
+            })
+            .collect();
+        let mut grid = SpatialGrid::new(cell_size);
+        grid.fill(points.iter().map(|(entity_id, p)| EntityTransform {
+            entity_id: *entity_id,
+            x: p.x as f64,
+            y: p.y as f64,
+            z: p.z as f64,
+            ..Default::default()
+        }));
+        (grid, points)
+    }
+
+    fn sorted_ids(found: Vec<(u32, Vector3<f32>)>) -> Vec<u32> {
+        let mut ids: Vec<u32> = found.into_iter().map(|(entity_id, _)| entity_id).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn box_queries_match_a_brute_force_scan() {
+        let (grid, points) = scattered_grid(3.0);
+        let mut rng = StdRng::seed_from_u64(11);
+        for _ in 0..200 {
+            let a = Vector3::from_fn(|_, _| rng.gen_range(-25.0..25.0));
+            // Mostly small boxes that walk the cells, some big enough to scan the occupied ones
+            let size = if rng.gen_bool(0.8) { 6.0 } else { 60.0 };
+            let b = a + Vector3::from_fn(|_, _| rng.gen_range(0.0..size));
+            let expected: Vec<u32> = points
+                .iter()
+                .filter(|(_, p)| (0..3).all(|i| a[i] <= p[i] && p[i] <= b[i]))
+                .map(|(entity_id, _)| *entity_id)
+                .collect();
+            assert_eq!(sorted_ids(grid.query_box(&a, &b).unwrap()), expected);
+        }
+    }
+
+    #[test]
+    fn sphere_queries_match_a_brute_force_scan() {
+        let (grid, points) = scattered_grid(2.5);
+        let mut rng = StdRng::seed_from_u64(13);
+        for _ in 0..200 {
+            let center = Vector3::from_fn(|_, _| rng.gen_range(-25.0..25.0));
+            let r = rng.gen_range(0.0..12.0);
+            let expected: Vec<u32> = points
+                .iter()
+                .filter(|(_, p)| (p - center).norm_squared() <= r * r)
+                .map(|(entity_id, _)| *entity_id)
+                .collect();
+            let mut found = grid.query_sphere(&center, r).unwrap();
+            found.sort_unstable();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn inverted_and_unbounded_boxes_are_handled() {
+        let (grid, points) = scattered_grid(1.0);
+        let (lo, hi) = (Vector3::repeat(1.0), Vector3::repeat(-1.0));
+        assert!(grid.query_box(&lo, &hi).is_err());
+        // Saturated cell coordinates would overflow a cell count; every point is inside
+        let everything = grid
+            .query_box(&Vector3::repeat(f32::MIN), &Vector3::repeat(f32::MAX))
+            .unwrap();
+        assert_eq!(everything.len(), points.len());
+    }
+}
@@ -0,0 +1,650 @@
+//! Joints, multibody links and pulleys between entities.
+
+use log::info;
+use rapier3d::prelude::*;
+use spacetimedb::{reducer, ReducerContext, Table};
+
+use crate::state::*;
+use crate::tables::*;
+use crate::types::*;
+
+// --- Reducers ---
+
+#[reducer]
+pub fn create_spring_joint(
+    ctx: &ReducerContext,
+    entity_a: u32,
+    entity_b: u32,
+    rest_length: f32,
+    stiffness: f32,
+    damping: f32,
+) -> Result<(), String> {
+    info!(
+        "create_spring_joint called: {} <-> {} (rest {}, stiffness {}, damping {})",
+        entity_a, entity_b, rest_length, stiffness, damping
+    );
+    let joint = spring_joint(rest_length, stiffness, damping)?;
+    let (world_id, _, _) = get_entity_handles(ctx, entity_a)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let id = register_joint(ctx, state, entity_a, entity_b, JointKind::Spring, joint)?;
+    info!("  -> Created spring joint {}", id);
+    Ok(())
+}
+
+/// Validates the spring parameters and builds the joint. It is anchored at both body
+/// origins and pulls them back to `rest_length` apart.
+pub(crate) fn spring_joint(rest_length: f32, stiffness: f32, damping: f32) -> Result<GenericJoint, String> {
+    if !(rest_length.is_finite() && rest_length > 0.0) {
+        return Err("Rest length must be > 0".to_string());
+    }
+    if !(stiffness.is_finite() && stiffness > 0.0) {
+        return Err("Stiffness must be > 0".to_string());
+    }
+    if !(damping.is_finite() && damping >= 0.0) {
+        return Err("Damping must be >= 0".to_string());
+    }
+    Ok(SpringJointBuilder::new(rest_length, stiffness, damping).build().into())
+}
+
+/// Validates the length and builds a rigid rod keeping the body origins `length` apart.
+pub(crate) fn distance_joint(length: f32) -> Result<GenericJoint, String> {
+    if !(length.is_finite() && length > 0.0) {
+        return Err("Length must be > 0".to_string());
+    }
+    // A rope joint with equal min and max: coupling the linear axes makes the limit apply
+    // to the distance between the body origins, leaving rotation free on both ends
+    Ok(GenericJointBuilder::new(JointAxesMask::empty())
+        .coupled_axes(JointAxesMask::LIN_AXES)
+        .limits(JointAxis::LinX, [length, length])
+        .build())
+}
+
+#[reducer]
+pub fn create_distance_joint(
+    ctx: &ReducerContext,
+    entity_a: u32,
+    entity_b: u32,
+    length: f32,
+) -> Result<(), String> {
+    info!("create_distance_joint called: {} <-> {} (length {})", entity_a, entity_b, length);
+    let joint = distance_joint(length)?;
+    let (world_id, _, _) = get_entity_handles(ctx, entity_a)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let id = register_joint(ctx, state, entity_a, entity_b, JointKind::Distance, joint)?;
+    info!("  -> Created distance joint {}", id);
+    Ok(())
+}
+
+#[reducer]
+pub fn create_prismatic_joint(
+    ctx: &ReducerContext,
+    entity_a: u32,
+    entity_b: u32,
+    axis_x: f32,
+    axis_y: f32,
+    axis_z: f32,
+    min: Option<f32>,
+    max: Option<f32>,
+    motor: Option<JointMotorDesc>,
+) -> Result<(), String> {
+    info!(
+        "create_prismatic_joint called: {} <-> {} along ({}, {}, {})",
+        entity_a, entity_b, axis_x, axis_y, axis_z
+    );
+    let axis = Vec3 {
+        x: axis_x,
+        y: axis_y,
+        z: axis_z,
+    };
+    let joint = prismatic_joint(axis, min, max, motor)?;
+
+    let (world_id, _, _) = get_entity_handles(ctx, entity_a)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let id = register_joint(ctx, state, entity_a, entity_b, JointKind::Prismatic, joint)?;
+    info!("  -> Created prismatic joint {}", id);
+    Ok(())
+}
+
+/// Validates the axis, limits and motor and builds the joint. The axis is in each body's
+/// local frame, anchored at the body origins.
+pub(crate) fn prismatic_joint(
+    axis: Vec3,
+    min: Option<f32>,
+    max: Option<f32>,
+    motor: Option<JointMotorDesc>,
+) -> Result<GenericJoint, String> {
+    let axis = axis.to_finite_vector("Axis")?;
+    let axis = UnitVector::try_new(axis, 1.0e-6).ok_or_else(|| "Axis must be non-zero".to_string())?;
+
+    let mut joint = PrismaticJointBuilder::new(axis);
+    match (min, max) {
+        (Some(min), Some(max)) => {
+            if !(min.is_finite() && max.is_finite() && min <= max) {
+                return Err("Joint limits must be finite with min <= max".to_string());
+            }
+            joint = joint.limits([min, max]);
+        }
+        (None, None) => {}
+        _ => return Err("Joint limits need both min and max".to_string()),
+    }
+    if let Some(motor) = motor {
+        if !motor.target_velocity.is_finite() || !(motor.max_force.is_finite() && motor.max_force >= 0.0) {
+            return Err("Motor needs a finite target velocity and max force >= 0".to_string());
+        }
+        joint = joint
+            .motor_velocity(motor.target_velocity, 1.0)
+            .motor_max_force(motor.max_force);
+    }
+    Ok(joint.build().into())
+}
+
+#[reducer]
+pub fn remove_joint(ctx: &ReducerContext, joint_id: u32) -> Result<(), String> {
+    info!("remove_joint called for {}", joint_id);
+    let row = ctx
+        .db
+        .entity_joint()
+        .id()
+        .find(joint_id)
+        .ok_or_else(|| format!("Joint {} not found", joint_id))?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, row.world_id)?;
+    state.impulse_joint_set.remove(row.joint_handle(), true); // Wake both bodies so they react
+    ctx.db.entity_joint().id().delete(joint_id);
+    ctx.db.joint_list_result().joint_id().delete(joint_id);
+    Ok(())
+}
+
+/// Joint linking `child` to `parent` in their current poses, see `create_multibody_link`.
+pub(crate) fn multibody_link_joint(
+    parent: &Isometry<Real>,
+    child: &Isometry<Real>,
+    kind: LinkKind,
+    axis: Vector<Real>,
+) -> Result<GenericJoint, String> {
+    // The pivot sits halfway between the two bodies as they are now, so the chain keeps
+    // its current layout. The axis is in both bodies' local frames.
+    let pivot = Point::from((parent.translation.vector + child.translation.vector) / 2.0);
+    let anchor_parent = parent.inverse_transform_point(&pivot);
+    let anchor_child = child.inverse_transform_point(&pivot);
+    let relative_pose = parent.inverse() * child;
+    let joint: GenericJoint = match kind {
+        LinkKind::Revolute | LinkKind::Prismatic => {
+            let axis = UnitVector::try_new(axis, 1.0e-6)
+                .ok_or_else(|| "Axis must be non-zero".to_string())?;
+            if kind == LinkKind::Revolute {
+                RevoluteJointBuilder::new(axis)
+                    .local_anchor1(anchor_parent)
+                    .local_anchor2(anchor_child)
+                    .into()
+            } else {
+                PrismaticJointBuilder::new(axis)
+                    .local_anchor1(anchor_parent)
+                    .local_anchor2(anchor_child)
+                    .into()
+            }
+        }
+        LinkKind::Spherical => SphericalJointBuilder::new()
+            .local_anchor1(anchor_parent)
+            .local_anchor2(anchor_child)
+            .into(),
+        // Welded in the current relative orientation
+        LinkKind::Fixed => FixedJointBuilder::new()
+            .local_frame1(relative_pose)
+            .local_frame2(Isometry::identity())
+            .into(),
+    };
+    Ok(joint)
+}
+
+#[reducer]
+pub fn create_multibody_link(
+    ctx: &ReducerContext,
+    parent_entity: u32,
+    child_entity: u32,
+    kind: LinkKind,
+    axis: Vec3,
+) -> Result<(), String> {
+    info!(
+        "create_multibody_link called: {} -> {} ({:?} around {:?})",
+        parent_entity, child_entity, kind, axis
+    );
+    if parent_entity == child_entity {
+        return Err("Cannot join an entity to itself".to_string());
+    }
+    let axis = axis.to_finite_vector("Axis")?;
+    let (world_id, rb_parent, _) = get_entity_handles(ctx, parent_entity)?;
+    let (child_world, rb_child, _) = get_entity_handles(ctx, child_entity)?;
+    if world_id != child_world {
+        return Err(format!(
+            "Entities {} and {} are in different worlds",
+            parent_entity, child_entity
+        ));
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let (Some(parent), Some(child)) = (
+        state.rigid_body_set.get(rb_parent),
+        state.rigid_body_set.get(rb_child),
+    ) else {
+        return Err(format!(
+            "Rigid body for entity {} or {} not found",
+            parent_entity, child_entity
+        ));
+    };
+
+    let joint = multibody_link_joint(parent.position(), child.position(), kind, axis)?;
+    // Rapier refuses links that would give the child a second parent or close a loop
+    let joint_handle = state
+        .multibody_joint_set
+        .insert(rb_parent, rb_child, joint, true)
+        .ok_or_else(|| {
+            format!(
+                "Entity {} already has a parent or is an ancestor of {}",
+                child_entity, parent_entity
+            )
+        })?;
+
+    let id = ctx
+        .db
+        .multibody_link()
+        .iter()
+        .map(|row| row.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let (joint_idx, joint_gen) = joint_handle.into_raw_parts();
+    ctx.db
+        .multibody_link()
+        .try_insert(MultibodyLink {
+            id,
+            world_id,
+            parent_entity,
+            child_entity,
+            kind,
+            joint_handle_index: joint_idx,
+            joint_handle_generation: joint_gen,
+        })
+        .map_err(|e| e.to_string())?;
+    info!("  -> Created multibody link {}", id);
+    Ok(())
+}
+
+#[reducer]
+pub fn remove_multibody_link(ctx: &ReducerContext, link_id: u32) -> Result<(), String> {
+    info!("remove_multibody_link called for {}", link_id);
+    let row = ctx
+        .db
+        .multibody_link()
+        .id()
+        .find(link_id)
+        .ok_or_else(|| format!("Multibody link {} not found", link_id))?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, row.world_id)?;
+    let joint_handle =
+        MultibodyJointHandle::from_raw_parts(row.joint_handle_index, row.joint_handle_generation);
+    state.multibody_joint_set.remove(joint_handle, true);
+    ctx.db.multibody_link().id().delete(link_id);
+    Ok(())
+}
+
+#[reducer]
+pub fn list_joints(ctx: &ReducerContext, world_id: u32) -> Result<(), String> {
+    info!("list_joints called for world {}", world_id);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    let stale: Vec<u32> = ctx
+        .db
+        .joint_list_result()
+        .world_id()
+        .filter(world_id)
+        .map(|row| row.joint_id)
+        .collect();
+    for joint_id in stale {
+        ctx.db.joint_list_result().joint_id().delete(joint_id);
+    }
+
+    let joints: Vec<EntityJoint> = ctx
+        .db
+        .entity_joint()
+        .iter()
+        .filter(|joint| joint.world_id == world_id)
+        .collect();
+    for joint in joints {
+        // Rows whose joint rapier no longer has are dangling: drop them instead of listing
+        if state.impulse_joint_set.get(joint.joint_handle()).is_none() {
+            ctx.db.entity_joint().id().delete(joint.id);
+            continue;
+        }
+        ctx.db
+            .joint_list_result()
+            .try_insert(JointListResult {
+                joint_id: joint.id,
+                world_id,
+                kind: joint.kind,
+                entity_a: joint.entity_a,
+                entity_b: joint.entity_b,
+                queried_at: ctx.timestamp,
+            })
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn create_pulley(
+    ctx: &ReducerContext,
+    entity_a: u32,
+    anchor_a: Vec3,
+    entity_b: u32,
+    anchor_b: Vec3,
+) -> Result<(), String> {
+    info!(
+        "create_pulley called: {} over {:?} and {:?} to {}",
+        entity_a, anchor_a, anchor_b, entity_b
+    );
+    let anchor_a_vec = anchor_a.to_finite_vector("Anchor A")?;
+    let anchor_b_vec = anchor_b.to_finite_vector("Anchor B")?;
+    if entity_a == entity_b {
+        return Err("A pulley needs two different entities".to_string());
+    }
+    let (world_a, rb_a, _) = get_entity_handles(ctx, entity_a)?;
+    let (world_b, rb_b, _) = get_entity_handles(ctx, entity_b)?;
+    if world_a != world_b {
+        return Err(format!(
+            "Entities {} and {} are in different worlds",
+            entity_a, entity_b
+        ));
+    }
+
+    // The rope is exactly taut at creation
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_a)?;
+    let body_a = state
+        .rigid_body_set
+        .get(rb_a)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_a))?;
+    let body_b = state
+        .rigid_body_set
+        .get(rb_b)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_b))?;
+    let length = (anchor_a_vec - body_a.translation()).norm()
+        + (anchor_b_vec - body_b.translation()).norm();
+
+    let id = ctx
+        .db
+        .pulley_constraint()
+        .iter()
+        .map(|pulley| pulley.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    ctx.db
+        .pulley_constraint()
+        .try_insert(PulleyConstraint {
+            id,
+            world_id: world_a,
+            entity_a,
+            anchor_a,
+            entity_b,
+            anchor_b,
+            length,
+        })
+        .map_err(|e| e.to_string())?;
+    info!("  -> Created pulley {} with rope length {}", id, length);
+    Ok(())
+}
+
+#[reducer]
+pub fn remove_pulley(ctx: &ReducerContext, id: u32) -> Result<(), String> {
+    info!("remove_pulley called for {}", id);
+    if !ctx.db.pulley_constraint().id().delete(id) {
+        return Err(format!("Pulley {} not found", id));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rapier3d::na::Vector3;
+
+    /// Inserts a fixed, collider-less anchor body at `position`.
+    fn add_anchor(state: &mut PhysicsState, position: Vector3<f32>) -> RigidBodyHandle {
+        state
+            .rigid_body_set
+            .insert(RigidBodyBuilder::fixed().translation(position).build())
+    }
+
+    #[test]
+    fn hanging_spring_oscillates_then_settles_near_its_rest_length() {
+        let mut state = PhysicsState::new(0);
+        let anchor = add_anchor(&mut state, Vector3::new(0.0, 10.0, 0.0));
+        // Pulled a metre past the 3m rest length
+        let ball = state.add_test_ball(Vector3::new(0.0, 6.0, 0.0));
+        let joint = spring_joint(3.0, 400.0, 8.0).unwrap();
+        state.impulse_joint_set.insert(anchor, ball, joint, true);
+
+        let mut highest = f32::MIN;
+        for _ in 0..600 {
+            state.step_once();
+            highest = highest.max(state.rigid_body_set[ball].translation().y);
+        }
+        assert!(highest > 7.1, "never sprang back past rest: {}", highest);
+        let settled = state.rigid_body_set[ball].translation();
+        assert!((settled.y - 7.0).abs() < 0.15, "settled at {}", settled.y);
+        assert!(settled.x.abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn spring_parameters_are_validated() {
+        assert!(spring_joint(0.0, 10.0, 1.0).is_err());
+        assert!(spring_joint(1.0, -10.0, 1.0).is_err());
+        assert!(spring_joint(1.0, 10.0, f32::NAN).is_err());
+        assert!(spring_joint(1.0, 10.0, 0.0).is_ok());
+    }
+
+    const UP: Vec3 = Vec3 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+    };
+
+    #[test]
+    fn vertical_prismatic_joint_slides_only_within_its_limits() {
+        let mut state = PhysicsState::new(0);
+        let anchor = add_anchor(&mut state, Vector3::new(0.0, 10.0, 0.0));
+        let ball = state.add_test_ball(Vector3::new(0.0, 10.0, 0.0));
+        let joint = prismatic_joint(UP, Some(-2.0), Some(0.0), None).unwrap();
+        state.impulse_joint_set.insert(anchor, ball, joint, true);
+        // A sideways shove the joint has to cancel
+        state.rigid_body_set[ball].set_linvel(Vector3::new(3.0, 0.0, 3.0), true);
+
+        let mut lowest = f32::MAX;
+        for _ in 0..180 {
+            state.step_once();
+            let position = state.rigid_body_set[ball].translation();
+            lowest = lowest.min(position.y);
+            assert!(
+                position.x.abs() < 0.05 && position.z.abs() < 0.05,
+                "left the axis: {:?}",
+                position
+            );
+        }
+        assert!(lowest > 7.9, "slid past the limit to {}", lowest);
+        assert!((state.rigid_body_set[ball].translation().y - 8.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn prismatic_axis_and_limits_are_validated() {
+        let zero = Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        assert!(prismatic_joint(zero, None, None, None).is_err());
+        assert!(prismatic_joint(UP, Some(1.0), Some(-1.0), None).is_err());
+        assert!(prismatic_joint(UP, Some(1.0), None, None).is_err());
+        assert!(prismatic_joint(UP, None, None, None).is_ok());
+    }
+
+    #[test]
+    fn joint_rows_go_dangling_when_the_joint_or_a_body_is_removed() {
+        let mut state = PhysicsState::new(0);
+        let anchor = add_anchor(&mut state, Vector3::new(0.0, 10.0, 0.0));
+        let rows: Vec<EntityJoint> = (1..=3u32)
+            .map(|id| {
+                let ball = state.add_test_ball(Vector3::new(id as f32 * 3.0, 8.0, 0.0));
+                state.handle_to_entity_id.insert(ball, id + 1);
+                let joint = spring_joint(2.0, 100.0, 1.0).unwrap();
+                let (index, generation) = state
+                    .impulse_joint_set
+                    .insert(anchor, ball, joint, true)
+                    .into_raw_parts();
+                EntityJoint {
+                    id,
+                    world_id: 0,
+                    entity_a: 1,
+                    entity_b: id + 1,
+                    kind: JointKind::Spring,
+                    joint_handle_index: index,
+                    joint_handle_generation: generation,
+                }
+            })
+            .collect();
+
+        // Remove the second joint directly and the third along with its ball
+        state.impulse_joint_set.remove(rows[1].joint_handle(), true);
+        let third_ball = state
+            .impulse_joint_set
+            .get(rows[2].joint_handle())
+            .unwrap()
+            .body2;
+        remove_body(&mut state, 4, third_ball);
+        state.step_once();
+
+        let listed: Vec<u32> = rows
+            .iter()
+            .filter(|row| state.impulse_joint_set.get(row.joint_handle()).is_some())
+            .map(|row| row.id)
+            .collect();
+        assert_eq!(listed, [1]);
+    }
+
+    #[test]
+    fn rod_keeps_two_tumbling_bodies_at_its_length() {
+        let mut state = PhysicsState::new(0);
+        let a = state.add_test_ball(Vector3::new(0.0, 30.0, 0.0));
+        let b = state.add_test_ball(Vector3::new(4.0, 30.0, 0.0));
+        state
+            .impulse_joint_set
+            .insert(a, b, distance_joint(4.0).unwrap(), true);
+        // Fling them apart and sideways, so the rod has to hold against both
+        state.rigid_body_set[a].set_linvel(Vector3::new(-3.0, 0.0, 2.0), true);
+        state.rigid_body_set[b].set_linvel(Vector3::new(3.0, 4.0, -2.0), true);
+
+        for _ in 0..90 {
+            state.step_once();
+            let gap = state.rigid_body_set[b].translation() - state.rigid_body_set[a].translation();
+            assert!((gap.norm() - 4.0).abs() < 0.1, "rod length {}", gap.norm());
+        }
+        // The pair spun around each other rather than staying on the X axis
+        let gap = state.rigid_body_set[b].translation() - state.rigid_body_set[a].translation();
+        assert!(gap.x < 3.9, "gap {:?}", gap);
+    }
+
+    #[test]
+    fn rod_length_must_be_positive() {
+        assert!(distance_joint(0.0).is_err());
+        assert!(distance_joint(f32::INFINITY).is_err());
+    }
+
+    #[test]
+    fn link_kinds_survive_a_joint_round_trip() {
+        let parent = Isometry::translation(0.0, 5.0, 0.0);
+        let child = Isometry::translation(1.0, 5.0, 0.0);
+        for kind in [
+            LinkKind::Revolute,
+            LinkKind::Prismatic,
+            LinkKind::Spherical,
+            LinkKind::Fixed,
+        ] {
+            let joint = multibody_link_joint(&parent, &child, kind, Vector3::z()).unwrap();
+            assert_eq!(LinkKind::from_joint(&joint), kind);
+        }
+        assert!(
+            multibody_link_joint(&parent, &child, LinkKind::Revolute, Vector3::zeros()).is_err()
+        );
+    }
+
+    /// Hangs a horizontal 5-link arm of small balls, the last one heavy, from a fixed base
+    /// with revolute joints and returns how far any joint's two anchors drift apart while
+    /// it swings.
+    fn arm_stretch(multibody: bool) -> f32 {
+        let mut state = PhysicsState::new(0);
+        let mut links = vec![add_anchor(&mut state, Vector3::new(0.0, 20.0, 0.0))];
+        for i in 1..=5 {
+            let rb_handle = state.rigid_body_set.insert(
+                RigidBodyBuilder::dynamic()
+                    .translation(Vector3::new(i as f32, 20.0, 0.0))
+                    .build(),
+            );
+            let density = if i == 5 { 200.0 } else { 1.0 };
+            state.collider_set.insert_with_parent(
+                ColliderBuilder::ball(0.2).density(density).build(),
+                rb_handle,
+                &mut state.rigid_body_set,
+            );
+            let parent = links[i - 1];
+            let joint = multibody_link_joint(
+                state.rigid_body_set[parent].position(),
+                state.rigid_body_set[rb_handle].position(),
+                LinkKind::Revolute,
+                Vector3::z(),
+            )
+            .unwrap();
+            if multibody {
+                state
+                    .multibody_joint_set
+                    .insert(parent, rb_handle, joint, true)
+                    .unwrap();
+            } else {
+                state
+                    .impulse_joint_set
+                    .insert(parent, rb_handle, joint, true);
+            }
+            links.push(rb_handle);
+        }
+
+        let mut stretch: f32 = 0.0;
+        for _ in 0..120 {
+            state.step_once();
+            for pair in links.windows(2) {
+                // Each pivot is halfway between the two links' starting positions
+                let on_parent =
+                    state.rigid_body_set[pair[0]].position() * Point::new(0.5, 0.0, 0.0);
+                let on_child =
+                    state.rigid_body_set[pair[1]].position() * Point::new(-0.5, 0.0, 0.0);
+                stretch = stretch.max((on_child - on_parent).norm());
+            }
+        }
+        stretch
+    }
+
+    #[test]
+    fn multibody_arm_stretches_less_than_the_impulse_joint_one() {
+        let multibody = arm_stretch(true);
+        let impulse = arm_stretch(false);
+        assert!(multibody < 1.0e-3, "multibody arm stretched {}", multibody);
+        assert!(
+            multibody < impulse,
+            "multibody {} vs impulse {}",
+            multibody,
+            impulse
+        );
+    }
+}
@@ -0,0 +1,1254 @@
+//! Read-only queries that write their answers to result tables.
+
+use log::info;
+use rapier3d::na::Vector3;
+use rapier3d::parry::query::{PointProjection, PointQuery};
+use rapier3d::prelude::*;
+use spacetimedb::{reducer, ReducerContext, Table};
+use std::collections::HashMap;
+
+use crate::state::*;
+use crate::tables::*;
+use crate::types::*;
+
+// --- Helper Functions ---
+
+/// Entities whose colliders are in actual contact with any of the entity's colliders
+/// (compound bodies have several). Colliders without an entity, like the ground, are
+/// reported as `None`.
+pub(crate) fn touching_entities(
+    state: &PhysicsState,
+    entity_id: u32,
+    rb_handle: RigidBodyHandle,
+) -> Result<Vec<Option<u32>>, String> {
+    let rigid_body = state
+        .rigid_body_set
+        .get(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+
+    let mut touching: Vec<Option<u32>> = Vec::new();
+    for co_handle in rigid_body.colliders() {
+        for pair in state.narrow_phase.contact_pairs_with(*co_handle) {
+            // Broad-phase overlap alone creates a pair; only count actual contact
+            if !pair.has_any_active_contact {
+                continue;
+            }
+            let other = if pair.collider1 == *co_handle {
+                pair.collider2
+            } else {
+                pair.collider1
+            };
+            let other_entity = state.collider_to_entity_id.get(&other).copied();
+            if other_entity != Some(entity_id) && !touching.contains(&other_entity) {
+                touching.push(other_entity);
+            }
+        }
+    }
+    Ok(touching)
+}
+
+/// The deepest contact between `entity_a`'s body and `entity_b` (`None` for the ground) as
+/// (distance, world point, normal pushing A away from B), if they are touching.
+pub(crate) fn deepest_contact(
+    state: &PhysicsState,
+    entity_a: u32,
+    rb_handle: RigidBodyHandle,
+    entity_b: Option<u32>,
+) -> Result<Option<(f32, Point<Real>, Vector3<f32>)>, String> {
+    let rigid_body = state
+        .rigid_body_set
+        .get(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_a))?;
+
+    // The most negative distance wins
+    let mut deepest: Option<(f32, Point<Real>, Vector3<f32>)> = None;
+    for co_handle in rigid_body.colliders() {
+        for pair in state.narrow_phase.contact_pairs_with(*co_handle) {
+            // Broad-phase overlap alone creates a pair without any manifold points
+            if !pair.has_any_active_contact {
+                continue;
+            }
+            let a_is_first = pair.collider1 == *co_handle;
+            let other = if a_is_first { pair.collider2 } else { pair.collider1 };
+            if state.collider_to_entity_id.get(&other).copied() != entity_b {
+                continue;
+            }
+            let Some(collider1) = state.collider_set.get(pair.collider1) else {
+                continue;
+            };
+            for manifold in &pair.manifolds {
+                // The manifold normal points from collider 1 to collider 2
+                let normal = if a_is_first {
+                    -manifold.data.normal
+                } else {
+                    manifold.data.normal
+                };
+                for point in &manifold.points {
+                    if !matches!(deepest, Some((dist, _, _)) if dist <= point.dist) {
+                        deepest = Some((point.dist, collider1.position() * point.local_p1, normal));
+                    }
+                }
+            }
+        }
+    }
+    Ok(deepest)
+}
+
+/// Whether a downward ray from the body origin hits another collider within `ray_length`,
+/// which therefore has to reach past the body's own bottom.
+pub(crate) fn ground_below(
+    state: &PhysicsState,
+    entity_id: u32,
+    rb_handle: RigidBodyHandle,
+    ray_length: f32,
+) -> Result<bool, String> {
+    let rigid_body = state
+        .rigid_body_set
+        .get(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    let ray = Ray::new(Point::from(*rigid_body.translation()), -Vector3::y());
+    let hit = state.query_pipeline.cast_ray(
+        &state.rigid_body_set,
+        &state.collider_set,
+        &ray,
+        ray_length,
+        true,
+        QueryFilter::default().exclude_rigid_body(rb_handle),
+    );
+    Ok(hit.is_some())
+}
+
+/// First collider hit by a ray from the body's origin along unit `direction`, as the hit
+/// entity (`None` for the ground) and its distance. The body's own colliders are skipped.
+pub(crate) fn first_hit_from_body(
+    state: &PhysicsState,
+    entity_id: u32,
+    rb_handle: RigidBodyHandle,
+    direction: Vector3<f32>,
+    max_toi: f32,
+) -> Result<Option<(Option<u32>, f32)>, String> {
+    let rigid_body = state
+        .rigid_body_set
+        .get(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+
+    // Starts inside the body, so all of its own colliders are excluded. Sensors are
+    // skipped as in `raycast_all`.
+    let ray = Ray::new(Point::from(*rigid_body.translation()), direction);
+    let hit = state.query_pipeline.cast_ray(
+        &state.rigid_body_set,
+        &state.collider_set,
+        &ray,
+        max_toi,
+        true,
+        QueryFilter::default()
+            .exclude_rigid_body(rb_handle)
+            .exclude_sensors(),
+    );
+    Ok(hit.map(|(co_handle, distance)| {
+        (state.collider_to_entity_id.get(&co_handle).copied(), distance)
+    }))
+}
+
+/// Every collider `ray` passes through within `max_toi`, nearest first, as the hit
+/// entity (`None` for the ground) and its distance. Expects a unit direction.
+pub(crate) fn ray_hits(
+    state: &PhysicsState,
+    ray: &Ray,
+    max_toi: f32,
+    exclude_ground: bool,
+) -> Vec<(Option<u32>, f32)> {
+    // Unit direction, so the time of impact is the distance along the ray.
+    // Sensors are skipped: trigger zones don't stop or reflect anything.
+    let mut hits: Vec<(Option<u32>, f32)> = Vec::new();
+    state.query_pipeline.intersections_with_ray(
+        &state.rigid_body_set,
+        &state.collider_set,
+        ray,
+        max_toi,
+        true,
+        QueryFilter::default().exclude_sensors(),
+        |co_handle, intersection| {
+            let entity_id = state.collider_to_entity_id.get(&co_handle).copied();
+            if entity_id.is_some() || !exclude_ground {
+                hits.push((entity_id, intersection.time_of_impact));
+            }
+            true // Keep going to collect every hit
+        },
+    );
+    hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+    hits
+}
+
+/// Whether the body's linear or angular speed exceeds `threshold`.
+pub(crate) fn is_body_moving(rigid_body: &RigidBody, threshold: f32) -> bool {
+    // A sleeping body can keep a tiny leftover velocity; it is at rest all the same
+    !rigid_body.is_sleeping()
+        && (rigid_body.linvel().norm() > threshold || rigid_body.angvel().norm() > threshold)
+}
+
+/// Ids of the entities tagged `tag` among `tags`, in ascending order.
+pub(crate) fn entities_with_tag(tags: impl IntoIterator<Item = EntityTag>, tag: u32) -> Vec<u32> {
+    let mut tagged: Vec<u32> = tags
+        .into_iter()
+        .filter(|row| row.tag == tag)
+        .map(|row| row.entity_id)
+        .collect();
+    tagged.sort_unstable();
+    tagged
+}
+
+/// Ids of the transform rows whose position lies within `min`..=`max`.
+pub(crate) fn entities_in_region(
+    transforms: impl IntoIterator<Item = EntityTransform>,
+    min: &Vector3<f32>,
+    max: &Vector3<f32>,
+) -> Vec<u32> {
+    transforms
+        .into_iter()
+        .filter(|t| {
+            let pos = [t.x as f32, t.y as f32, t.z as f32];
+            (0..3).all(|i| min[i] <= pos[i] && pos[i] <= max[i])
+        })
+        .map(|t| t.entity_id)
+        .collect()
+}
+
+// --- Reducers ---
+
+#[reducer]
+pub fn get_contacts(ctx: &ReducerContext, entity_id: u32) -> Result<(), String> {
+    info!("get_contacts called for entity {}", entity_id);
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let touching = touching_entities(state, entity_id, rb_handle)?;
+
+    // Replace the previous result for this entity
+    ctx.db.contact_result().entity_id().delete(entity_id);
+    let mut next_id = ctx
+        .db
+        .contact_result()
+        .iter()
+        .map(|row| row.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    for other_entity_id in touching {
+        ctx.db
+            .contact_result()
+            .try_insert(ContactResult {
+                id: next_id,
+                entity_id,
+                other_entity_id,
+                queried_at: ctx.timestamp,
+            })
+            .map_err(|e| e.to_string())?;
+        next_id += 1;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn get_contact_between(
+    ctx: &ReducerContext,
+    entity_a: u32,
+    entity_b: Option<u32>,
+) -> Result<(), String> {
+    info!("get_contact_between called for entity {} and {:?}", entity_a, entity_b);
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_a)?;
+    if let Some(entity_b) = entity_b {
+        let (world_b, _, _) = get_entity_handles(ctx, entity_b)?;
+        if world_b != world_id {
+            return Err(format!(
+                "Entities {} and {} are in different worlds",
+                entity_a, entity_b
+            ));
+        }
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let deepest = deepest_contact(state, entity_a, rb_handle, entity_b)?;
+
+    let zero = Vec3 { x: 0.0, y: 0.0, z: 0.0 };
+    let row = match deepest {
+        Some((dist, point, normal)) => ContactBetweenResult {
+            entity_a,
+            entity_b,
+            in_contact: true,
+            point: point.coords.into(),
+            normal: normal.into(),
+            depth: -dist,
+            queried_at: ctx.timestamp,
+        },
+        None => ContactBetweenResult {
+            entity_a,
+            entity_b,
+            in_contact: false,
+            point: zero,
+            normal: zero,
+            depth: 0.0,
+            queried_at: ctx.timestamp,
+        },
+    };
+    if ctx.db.contact_between_result().entity_a().find(entity_a).is_some() {
+        ctx.db.contact_between_result().entity_a().update(row);
+    } else {
+        ctx.db
+            .contact_between_result()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn is_grounded(ctx: &ReducerContext, entity_id: u32, ray_length: f32) -> Result<(), String> {
+    info!("is_grounded called for entity {} (ray length {})", entity_id, ray_length);
+    if !(ray_length.is_finite() && ray_length > 0.0) {
+        return Err("Ray length must be > 0".to_string());
+    }
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let grounded = ground_below(state, entity_id, rb_handle, ray_length)?;
+
+    let row = GroundedResult {
+        entity_id,
+        grounded,
+        queried_at: ctx.timestamp,
+    };
+    if ctx.db.grounded_result().entity_id().find(entity_id).is_some() {
+        ctx.db.grounded_result().entity_id().update(row);
+    } else {
+        ctx.db
+            .grounded_result()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn get_mass(ctx: &ReducerContext, entity_id: u32) -> Result<(), String> {
+    info!("get_mass called for entity {}", entity_id);
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let rigid_body = state
+        .rigid_body_set
+        .get(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+
+    let row = MassResult::from_body(entity_id, rigid_body, ctx.timestamp);
+    if ctx.db.mass_result().entity_id().find(entity_id).is_some() {
+        ctx.db.mass_result().entity_id().update(row);
+    } else {
+        ctx.db
+            .mass_result()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn is_moving(ctx: &ReducerContext, entity_id: u32, threshold: f32) -> Result<(), String> {
+    info!("is_moving called for entity {} (threshold {})", entity_id, threshold);
+    if !(threshold.is_finite() && threshold >= 0.0) {
+        return Err("Threshold must be >= 0".to_string());
+    }
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let rigid_body = state
+        .rigid_body_set
+        .get(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+
+    let moving = is_body_moving(rigid_body, threshold);
+    let row = MovingResult {
+        entity_id,
+        moving,
+        threshold,
+        queried_at: ctx.timestamp,
+    };
+    if ctx.db.moving_result().entity_id().find(entity_id).is_some() {
+        ctx.db.moving_result().entity_id().update(row);
+    } else {
+        ctx.db
+            .moving_result()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn get_physics_transform(ctx: &ReducerContext, entity_id: u32) -> Result<(), String> {
+    info!("get_physics_transform called for entity {}", entity_id);
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let rigid_body = state
+        .rigid_body_set
+        .get(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+
+    let row = PhysicsTransformResult::from_pose(world_id, entity_id, rigid_body.position(), ctx.timestamp);
+    if ctx.db.physics_transform_result().entity_id().find(entity_id).is_some() {
+        ctx.db.physics_transform_result().entity_id().update(row);
+    } else {
+        ctx.db
+            .physics_transform_result()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn entity_aabb(ctx: &ReducerContext, entity_id: u32) -> Result<(), String> {
+    info!("entity_aabb called for entity {}", entity_id);
+    let (world_id, _, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let collider = state
+        .collider_set
+        .get(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+
+    let row = EntityAabbResult::from_collider(world_id, entity_id, collider, ctx.timestamp);
+    if ctx.db.entity_aabb_result().entity_id().find(entity_id).is_some() {
+        ctx.db.entity_aabb_result().entity_id().update(row);
+    } else {
+        ctx.db
+            .entity_aabb_result()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn snapshot_transforms(ctx: &ReducerContext, world_id: u32) -> Result<(), String> {
+    info!("snapshot_transforms called for world {}", world_id);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    let row = TransformBlob::from_state(state, ctx.timestamp);
+    let entity_count = row.entity_count;
+    if ctx.db.transform_blob().world_id().find(world_id).is_some() {
+        ctx.db.transform_blob().world_id().update(row);
+    } else {
+        ctx.db
+            .transform_blob()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    info!("  -> Packed {} transforms", entity_count);
+    Ok(())
+}
+
+#[reducer]
+pub fn raycast_all(
+    ctx: &ReducerContext,
+    world_id: u32,
+    query_id: u32,
+    origin: Vec3,
+    direction: Vec3,
+    max_toi: f32,
+    exclude_ground: bool,
+) -> Result<(), String> {
+    info!(
+        "raycast_all called for world {} (query {}): from {:?} along {:?}, max {}",
+        world_id, query_id, origin, direction, max_toi
+    );
+    let origin = origin.to_finite_vector("Ray origin")?;
+    let direction = UnitVector::try_new(direction.to_finite_vector("Ray direction")?, 1.0e-6)
+        .ok_or_else(|| "Ray direction must be non-zero".to_string())?;
+    if !(max_toi.is_finite() && max_toi > 0.0) {
+        return Err("Max distance must be > 0".to_string());
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    let ray = Ray::new(Point::from(origin), direction.into_inner());
+    let hits = ray_hits(state, &ray, max_toi, exclude_ground);
+
+    let stale: Vec<u32> = ctx
+        .db
+        .raycast_hit()
+        .query_id()
+        .filter(query_id)
+        .map(|row| row.id)
+        .collect();
+    for id in stale {
+        ctx.db.raycast_hit().id().delete(id);
+    }
+    let mut next_id = ctx
+        .db
+        .raycast_hit()
+        .iter()
+        .map(|row| row.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    for (rank, (entity_id, distance)) in hits.into_iter().enumerate() {
+        ctx.db
+            .raycast_hit()
+            .try_insert(RaycastHit {
+                id: next_id,
+                query_id,
+                world_id,
+                rank: rank as u32,
+                entity_id,
+                distance,
+                queried_at: ctx.timestamp,
+            })
+            .map_err(|e| e.to_string())?;
+        next_id += 1;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn raycast_from_entity(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    dir_x: f32,
+    dir_y: f32,
+    dir_z: f32,
+    max_toi: f32,
+) -> Result<(), String> {
+    info!(
+        "raycast_from_entity called for entity {}: along ({}, {}, {}), max {}",
+        entity_id, dir_x, dir_y, dir_z, max_toi
+    );
+    let direction = Vec3 {
+        x: dir_x,
+        y: dir_y,
+        z: dir_z,
+    }
+    .to_finite_vector("Ray direction")?;
+    let direction = UnitVector::try_new(direction, 1.0e-6)
+        .ok_or_else(|| "Ray direction must be non-zero".to_string())?;
+    if !(max_toi.is_finite() && max_toi > 0.0) {
+        return Err("Max distance must be > 0".to_string());
+    }
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let hit = first_hit_from_body(state, entity_id, rb_handle, direction.into_inner(), max_toi)?;
+
+    let row = match hit {
+        Some((hit_entity, distance)) => EntityRaycastResult {
+            entity_id,
+            hit: true,
+            hit_entity,
+            distance,
+            queried_at: ctx.timestamp,
+        },
+        None => EntityRaycastResult {
+            entity_id,
+            hit: false,
+            hit_entity: None,
+            distance: 0.0,
+            queried_at: ctx.timestamp,
+        },
+    };
+    if ctx.db.entity_raycast_result().entity_id().find(entity_id).is_some() {
+        ctx.db.entity_raycast_result().entity_id().update(row);
+    } else {
+        ctx.db
+            .entity_raycast_result()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// What first blocks an entity's collider moving by `delta`, as the blocking entity
+/// (`None` for the ground) and the free fraction of `delta`; `None` if the path is clear.
+pub(crate) fn sweep_body(
+    state: &PhysicsState,
+    entity_id: u32,
+    rb_handle: RigidBodyHandle,
+    co_handle: ColliderHandle,
+    delta: Vector3<f32>,
+) -> Result<Option<(Option<u32>, f32)>, String> {
+    let collider = state
+        .collider_set
+        .get(co_handle)
+        .ok_or_else(|| format!("Collider for entity {} not found", entity_id))?;
+
+    // Casting with the offset as the velocity makes the time of impact the free fraction.
+    // Contacts the body already has don't block it, so it can slide along the floor.
+    let options = ShapeCastOptions {
+        max_time_of_impact: 1.0,
+        stop_at_penetration: false,
+        ..ShapeCastOptions::default()
+    };
+    let hit = state.query_pipeline.cast_shape(
+        &state.rigid_body_set,
+        &state.collider_set,
+        collider.position(),
+        &delta,
+        collider.shape(),
+        options,
+        QueryFilter::default()
+            .exclude_rigid_body(rb_handle)
+            .exclude_sensors(),
+    );
+    Ok(hit.map(|(hit_handle, hit)| {
+        (state.collider_to_entity_id.get(&hit_handle).copied(), hit.time_of_impact)
+    }))
+}
+
+#[reducer]
+pub fn sweep_entity(ctx: &ReducerContext, entity_id: u32, dx: f32, dy: f32, dz: f32) -> Result<(), String> {
+    info!("sweep_entity called for entity {}: ({}, {}, {})", entity_id, dx, dy, dz);
+    let delta = Vec3 { x: dx, y: dy, z: dz }.to_finite_vector("Offset")?;
+    let (world_id, rb_handle, co_handle) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let row = match sweep_body(state, entity_id, rb_handle, co_handle, delta)? {
+        Some((blocking_entity, fraction)) => SweepResult {
+            entity_id,
+            blocked: true,
+            fraction,
+            blocking_entity,
+            queried_at: ctx.timestamp,
+        },
+        None => SweepResult {
+            entity_id,
+            blocked: false,
+            fraction: 1.0,
+            blocking_entity: None,
+            queried_at: ctx.timestamp,
+        },
+    };
+    if ctx.db.sweep_result().entity_id().find(entity_id).is_some() {
+        ctx.db.sweep_result().entity_id().update(row);
+    } else {
+        ctx.db
+            .sweep_result()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Closest collider surface point to `point`, as the collider's entity (`None` for the
+/// ground), the projection and its distance. `None` if nothing lies within `max_distance`.
+pub(crate) fn closest_surface(
+    state: &PhysicsState,
+    point: &Point<Real>,
+    max_distance: Option<f32>,
+) -> Option<(Option<u32>, PointProjection, f32)> {
+    // Hollow shapes, so a point inside a collider projects onto its surface too
+    state
+        .query_pipeline
+        .project_point(
+            &state.rigid_body_set,
+            &state.collider_set,
+            point,
+            false,
+            QueryFilter::default().exclude_sensors(),
+        )
+        .map(|(co_handle, projection)| {
+            let entity_id = state.collider_to_entity_id.get(&co_handle).copied();
+            (entity_id, projection, (projection.point - point).norm())
+        })
+        .filter(|(_, _, distance)| !matches!(max_distance, Some(max) if *distance > max))
+}
+
+#[reducer]
+pub fn project_point(
+    ctx: &ReducerContext,
+    world_id: u32,
+    x: f32,
+    y: f32,
+    z: f32,
+    max_distance: Option<f32>,
+) -> Result<(), String> {
+    info!(
+        "project_point called for world {}: ({}, {}, {}), max {:?}",
+        world_id, x, y, z, max_distance
+    );
+    let point = Point::from(Vec3 { x, y, z }.to_finite_vector("Point")?);
+    if let Some(max_distance) = max_distance {
+        if !(max_distance.is_finite() && max_distance >= 0.0) {
+            return Err("Max distance must be >= 0".to_string());
+        }
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    let projection = closest_surface(state, &point, max_distance);
+
+    let zero = Vec3 { x: 0.0, y: 0.0, z: 0.0 };
+    let row = match projection {
+        Some((entity_id, projection, distance)) => PointProjectionResult {
+            world_id,
+            found: true,
+            point: projection.point.coords.into(),
+            is_inside: projection.is_inside,
+            distance,
+            entity_id,
+            queried_at: ctx.timestamp,
+        },
+        None => PointProjectionResult {
+            world_id,
+            found: false,
+            point: zero,
+            is_inside: false,
+            distance: 0.0,
+            entity_id: None,
+            queried_at: ctx.timestamp,
+        },
+    };
+    if ctx.db.point_projection_result().world_id().find(world_id).is_some() {
+        ctx.db.point_projection_result().world_id().update(row);
+    } else {
+        ctx.db
+            .point_projection_result()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn body_count(ctx: &ReducerContext, world_id: u32) -> Result<(), String> {
+    info!("body_count called for world {}", world_id);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let row = BodyCountResult::from_state(state, ctx.timestamp);
+    if ctx.db.body_count_result().world_id().find(world_id).is_some() {
+        ctx.db.body_count_result().world_id().update(row);
+    } else {
+        ctx.db
+            .body_count_result()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn total_kinetic_energy(ctx: &ReducerContext, world_id: u32) -> Result<(), String> {
+    info!("total_kinetic_energy called for world {}", world_id);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    let stale: Vec<u32> = ctx
+        .db
+        .entity_energy()
+        .world_id()
+        .filter(world_id)
+        .map(|row| row.entity_id)
+        .collect();
+    for entity_id in stale {
+        ctx.db.entity_energy().entity_id().delete(entity_id);
+    }
+
+    let mut total_kinetic_energy = 0.0;
+    for (handle, rigid_body) in state.rigid_body_set.iter() {
+        if !rigid_body.is_dynamic() {
+            continue;
+        }
+        total_kinetic_energy += rigid_body.kinetic_energy();
+        let Some(entity_id) = state.handle_to_entity_id.get(&handle) else {
+            continue;
+        };
+        ctx.db
+            .entity_energy()
+            .try_insert(EntityEnergy::from_body(world_id, *entity_id, rigid_body))
+            .map_err(|e| e.to_string())?;
+    }
+
+    let row = EnergyResult {
+        world_id,
+        total_kinetic_energy,
+        queried_at: ctx.timestamp,
+    };
+    if ctx.db.energy_result().world_id().find(world_id).is_some() {
+        ctx.db.energy_result().world_id().update(row);
+    } else {
+        ctx.db
+            .energy_result()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn list_entities_in_region(
+    ctx: &ReducerContext,
+    world_id: u32,
+    min: Vec3,
+    max: Vec3,
+) -> Result<(), String> {
+    info!("list_entities_in_region called for world {}: {:?} to {:?}", world_id, min, max);
+    let min = min.to_finite_vector("Region min")?;
+    let max = max.to_finite_vector("Region max")?;
+    if min.iter().zip(max.iter()).any(|(lo, hi)| lo > hi) {
+        return Err("Region min must not exceed max".to_string());
+    }
+
+    // Reads the published transforms only, so this never waits on the physics lock.
+    // Positions are as of the last tick (sleeping bodies keep their last written row).
+    let inside = entities_in_region(ctx.db.entity_transform().world_id().filter(world_id), &min, &max);
+
+    let stale: Vec<u32> = ctx
+        .db
+        .region_result()
+        .world_id()
+        .filter(world_id)
+        .map(|row| row.id)
+        .collect();
+    for id in stale {
+        ctx.db.region_result().id().delete(id);
+    }
+    let mut next_id = ctx
+        .db
+        .region_result()
+        .iter()
+        .map(|row| row.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    for entity_id in inside {
+        ctx.db
+            .region_result()
+            .try_insert(RegionResult {
+                id: next_id,
+                world_id,
+                entity_id,
+                queried_at: ctx.timestamp,
+            })
+            .map_err(|e| e.to_string())?;
+        next_id += 1;
+    }
+    Ok(())
+}
+
+/// Number of dynamic bodies in each occupied `cell_size` cube, keyed and sorted by
+/// cell coordinates (cell `(0, 0, 0)` spans `0..cell_size` on each axis).
+pub(crate) fn dynamic_body_histogram(state: &PhysicsState, cell_size: f32) -> Vec<((i32, i32, i32), u32)> {
+    let mut counts: HashMap<(i32, i32, i32), u32> = HashMap::new();
+    // Live positions of dynamic bodies only; fixed and kinematic ones cost the solver little
+    for (_, rigid_body) in state.rigid_body_set.iter().filter(|(_, rb)| rb.is_dynamic()) {
+        let cell = rigid_body.translation() / cell_size;
+        let key = (cell.x.floor() as i32, cell.y.floor() as i32, cell.z.floor() as i32);
+        *counts.entry(key).or_insert(0) += 1;
+    }
+    let mut cells: Vec<((i32, i32, i32), u32)> = counts.into_iter().collect();
+    cells.sort_unstable();
+    cells
+}
+
+#[reducer]
+pub fn region_histogram(ctx: &ReducerContext, world_id: u32, cell_size: f32) -> Result<(), String> {
+    info!("region_histogram called for world {}: cell size {}", world_id, cell_size);
+    if !(cell_size.is_finite() && cell_size > 0.0) {
+        return Err("Cell size must be > 0".to_string());
+    }
+    let cells = {
+        let mut worlds = lock_state();
+        let state = get_world(&mut worlds, world_id)?;
+        dynamic_body_histogram(state, cell_size)
+    };
+
+    let stale: Vec<u32> = ctx
+        .db
+        .region_histogram_cell()
+        .world_id()
+        .filter(world_id)
+        .map(|row| row.id)
+        .collect();
+    for id in stale {
+        ctx.db.region_histogram_cell().id().delete(id);
+    }
+    let mut next_id = ctx
+        .db
+        .region_histogram_cell()
+        .iter()
+        .map(|row| row.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    for ((cell_x, cell_y, cell_z), count) in cells {
+        ctx.db
+            .region_histogram_cell()
+            .try_insert(RegionHistogramCell {
+                id: next_id,
+                world_id,
+                cell_x,
+                cell_y,
+                cell_z,
+                count,
+                cell_size,
+                queried_at: ctx.timestamp,
+            })
+            .map_err(|e| e.to_string())?;
+        next_id += 1;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn query_by_tag(ctx: &ReducerContext, tag: u32) -> Result<(), String> {
+    info!("query_by_tag called for tag {}", tag);
+    let stale: Vec<u32> = ctx
+        .db
+        .tag_query_result()
+        .tag()
+        .filter(tag)
+        .map(|row| row.id)
+        .collect();
+    for id in stale {
+        ctx.db.tag_query_result().id().delete(id);
+    }
+
+    let tagged = entities_with_tag(ctx.db.entity_tag().tag().filter(tag), tag);
+    let mut next_id = ctx
+        .db
+        .tag_query_result()
+        .iter()
+        .map(|row| row.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    for entity_id in tagged {
+        ctx.db
+            .tag_query_result()
+            .try_insert(TagQueryResult {
+                id: next_id,
+                tag,
+                entity_id,
+                queried_at: ctx.timestamp,
+            })
+            .map_err(|e| e.to_string())?;
+        next_id += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resting_ball_reports_the_ground_but_not_a_nearby_ball() {
+        let mut state = PhysicsState::new(0);
+        let resting = state.add_test_ball(Vector3::new(0.0, 1.1, 0.0));
+        // Close enough for the broad phase to pair them, but not touching
+        let nearby = state.add_test_ball(Vector3::new(2.05, 1.1, 0.0));
+        state.handle_to_entity_id.insert(resting, 1);
+        state.handle_to_entity_id.insert(nearby, 2);
+        for (handle, entity_id) in [(resting, 1), (nearby, 2)] {
+            let co_handle = state.rigid_body_set[handle].colliders()[0];
+            state.collider_to_entity_id.insert(co_handle, entity_id);
+        }
+        for _ in 0..10 {
+            state.step_once();
+        }
+
+        assert_eq!(touching_entities(&state, 1, resting).unwrap(), vec![None]);
+    }
+
+    #[test]
+    fn resting_ball_is_grounded_and_a_high_flying_one_is_not() {
+        let mut state = PhysicsState::new(0);
+        let resting = state.add_test_ball(Vector3::new(0.0, 1.1, 0.0));
+        let flying = state.add_test_ball(Vector3::new(5.0, 30.0, 0.0));
+        state.step_once();
+
+        // Unit balls: the ray has to reach past the radius
+        assert!(ground_below(&state, 1, resting, 1.2).unwrap());
+        assert!(!ground_below(&state, 2, flying, 1.2).unwrap());
+        // Its own collider doesn't count even with a ray shorter than the radius
+        assert!(!ground_below(&state, 1, resting, 0.5).unwrap());
+    }
+
+    #[test]
+    fn region_listing_matches_a_brute_force_scan() {
+        // A 10×10×10 lattice of rows 1m apart, with ids following the lattice order
+        let transforms: Vec<EntityTransform> = (0..1000u32)
+            .map(|id| EntityTransform {
+                entity_id: id,
+                x: (id % 10) as f64,
+                y: (id / 10 % 10) as f64,
+                z: (id / 100) as f64,
+                rw: 1.0,
+                ..Default::default()
+            })
+            .collect();
+        let regions = [
+            (Vector3::new(2.0, 3.0, 4.0), Vector3::new(5.0, 3.0, 8.5)),
+            (
+                Vector3::new(-1.0, -1.0, -1.0),
+                Vector3::new(20.0, 20.0, 20.0),
+            ),
+            (Vector3::new(0.5, 0.5, 0.5), Vector3::new(1.0, 9.0, 9.0)),
+            (Vector3::new(0.2, 0.0, 0.0), Vector3::new(0.8, 9.0, 9.0)),
+        ];
+        for (min, max) in regions {
+            let mut expected = Vec::new();
+            for z in 0..10u32 {
+                for y in 0..10u32 {
+                    for x in 0..10u32 {
+                        let point = Vector3::new(x as f32, y as f32, z as f32);
+                        let inside_x = min.x <= point.x && point.x <= max.x;
+                        let inside_y = min.y <= point.y && point.y <= max.y;
+                        let inside_z = min.z <= point.z && point.z <= max.z;
+                        if inside_x && inside_y && inside_z {
+                            expected.push(x + y * 10 + z * 100);
+                        }
+                    }
+                }
+            }
+            assert_eq!(entities_in_region(transforms.clone(), &min, &max), expected);
+        }
+    }
+
+    #[test]
+    fn ray_reports_every_ball_in_line_nearest_first() {
+        let mut state = PhysicsState::new(0);
+        // Inserted out of order, so the result has to be sorted by distance
+        for (x, entity_id) in [(10.0, 3), (2.0, 1), (6.0, 2)] {
+            let rb_handle = state.add_test_ball(Vector3::new(x, 1.1, 0.0));
+            let co_handle = state.rigid_body_set[rb_handle].colliders()[0];
+            state.collider_to_entity_id.insert(co_handle, entity_id);
+        }
+        state.step_once(); // Updates the query pipeline
+        let ray = Ray::new(Point::new(-5.0, 1.1, 0.0), Vector3::x());
+
+        let hits = ray_hits(&state, &ray, 100.0, true);
+        let ids: Vec<Option<u32>> = hits.iter().map(|(entity_id, _)| *entity_id).collect();
+        assert_eq!(ids, vec![Some(1), Some(2), Some(3)]);
+        // Entering each unit ball one radius before its center
+        assert!((hits[0].1 - 6.0).abs() < 0.01);
+
+        // Straight down through the first ball: the ground behind it counts only when asked
+        let down = Ray::new(Point::new(2.0, 5.0, 0.0), -Vector3::y());
+        assert_eq!(ray_hits(&state, &down, 10.0, true).len(), 1);
+        assert_eq!(ray_hits(&state, &down, 10.0, false).last().unwrap().0, None);
+    }
+
+    #[test]
+    fn ball_on_the_ground_is_pushed_straight_up_and_not_by_its_neighbor() {
+        let mut state = PhysicsState::new(0);
+        let resting = state.add_test_ball(Vector3::new(0.0, 1.1, 0.0));
+        // Paired in the broad phase, but without an active contact
+        let nearby = state.add_test_ball(Vector3::new(2.05, 1.1, 0.0));
+        for (handle, entity_id) in [(resting, 1), (nearby, 2)] {
+            let co_handle = state.rigid_body_set[handle].colliders()[0];
+            state.collider_to_entity_id.insert(co_handle, entity_id);
+        }
+        for _ in 0..10 {
+            state.step_once();
+        }
+
+        let (_, point, normal) = deepest_contact(&state, 1, resting, None).unwrap().unwrap();
+        assert!(
+            (normal - Vector3::y()).norm() < 1.0e-3,
+            "normal {:?}",
+            normal
+        );
+        assert!(
+            (point.coords - Vector3::new(0.0, 0.1, 0.0)).norm() < 0.05,
+            "point {:?}",
+            point
+        );
+        assert_eq!(deepest_contact(&state, 1, resting, Some(2)).unwrap(), None);
+    }
+
+    #[test]
+    fn pushed_body_is_moving_and_a_settled_one_is_not() {
+        let mut state = PhysicsState::new(0);
+        let pushed = state.add_test_ball(Vector3::new(0.0, 1.1, 0.0));
+        let settled = state.add_test_ball(Vector3::new(5.0, 1.1, 0.0));
+        for _ in 0..180 {
+            state.step_once();
+        }
+        assert!(state.rigid_body_set[settled].is_sleeping());
+        state.rigid_body_set[pushed].apply_impulse(Vector3::new(10.0, 0.0, 0.0), true);
+        state.step_once();
+
+        assert!(is_body_moving(&state.rigid_body_set[pushed], 0.5));
+        assert!(!is_body_moving(&state.rigid_body_set[settled], 0.0));
+        // Only past the threshold
+        assert!(!is_body_moving(&state.rigid_body_set[pushed], 100.0));
+    }
+
+    #[test]
+    fn ray_from_a_floating_body_skips_itself_and_reports_what_is_below() {
+        let mut state = PhysicsState::new(0);
+        let floating = state.add_test_ball(Vector3::new(0.0, 5.0, 0.0));
+        let beside = state.add_test_ball(Vector3::new(5.0, 5.0, 0.0));
+        for (handle, entity_id) in [(floating, 1), (beside, 2)] {
+            let co_handle = state.rigid_body_set[handle].colliders()[0];
+            state.collider_to_entity_id.insert(co_handle, entity_id);
+        }
+        state
+            .query_pipeline
+            .update(&state.rigid_body_set, &state.collider_set);
+
+        // Down to the top of the ground rather than out of its own ball
+        let (hit_entity, distance) = first_hit_from_body(&state, 1, floating, -Vector3::y(), 10.0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(hit_entity, None);
+        assert!((distance - 4.9).abs() < 1.0e-3, "hit at {}", distance);
+
+        let (hit_entity, distance) = first_hit_from_body(&state, 1, floating, Vector3::x(), 10.0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(hit_entity, Some(2));
+        assert!((distance - 4.0).abs() < 1.0e-3, "hit at {}", distance);
+        assert_eq!(
+            first_hit_from_body(&state, 1, floating, Vector3::y(), 10.0).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn sweep_stops_at_the_wall_but_slides_along_the_floor() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 1.1, 0.0));
+        let co_handle = state.rigid_body_set[rb_handle].colliders()[0];
+        state.collider_to_entity_id.insert(co_handle, 1);
+        let wall = state.rigid_body_set.insert(
+            RigidBodyBuilder::fixed()
+                .translation(Vector3::new(5.0, 1.0, 0.0))
+                .build(),
+        );
+        let wall_collider = state.collider_set.insert_with_parent(
+            ColliderBuilder::cuboid(0.5, 1.0, 5.0).build(),
+            wall,
+            &mut state.rigid_body_set,
+        );
+        state.collider_to_entity_id.insert(wall_collider, 2);
+        state.step_once();
+
+        // The ball's surface reaches the wall face at x = 4.5 after 3.5 of the 10m
+        let (blocking_entity, fraction) = sweep_body(
+            &state,
+            1,
+            rb_handle,
+            co_handle,
+            Vector3::new(10.0, 0.0, 0.0),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(blocking_entity, Some(2));
+        assert!((fraction - 0.35).abs() < 1e-3, "fraction {}", fraction);
+
+        // Resting on the ground doesn't block moving away from the wall
+        let away = sweep_body(
+            &state,
+            1,
+            rb_handle,
+            co_handle,
+            Vector3::new(-10.0, 0.0, 0.0),
+        );
+        assert_eq!(away.unwrap(), None);
+        assert!(sweep_body(
+            &state,
+            1,
+            rb_handle,
+            ColliderHandle::invalid(),
+            Vector3::zeros()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn closest_surface_projects_from_outside_and_inside_within_the_limit() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.rigid_body_set.insert(
+            RigidBodyBuilder::fixed()
+                .translation(Vector3::new(0.0, 5.0, 0.0))
+                .build(),
+        );
+        let co_handle = state.collider_set.insert_with_parent(
+            ColliderBuilder::ball(1.0).build(),
+            rb_handle,
+            &mut state.rigid_body_set,
+        );
+        state.collider_to_entity_id.insert(co_handle, 3);
+        state.step_once();
+
+        let (entity_id, projection, distance) =
+            closest_surface(&state, &Point::new(3.0, 5.0, 0.0), None).unwrap();
+        assert_eq!(entity_id, Some(3));
+        assert!(!projection.is_inside);
+        assert!((projection.point - Point::new(1.0, 5.0, 0.0)).norm() < 1e-5);
+        assert!((distance - 2.0).abs() < 1e-5);
+
+        // From the center the nearest surface point is a radius away, flagged as inside
+        let (_, projection, distance) =
+            closest_surface(&state, &Point::new(0.0, 5.0, 0.0), None).unwrap();
+        assert!(projection.is_inside);
+        assert!((distance - 1.0).abs() < 1e-5);
+
+        // Just above the ground it wins over the ball, and a tight limit rules out both
+        let (entity_id, _, _) = closest_surface(&state, &Point::new(0.0, 0.3, 0.0), None).unwrap();
+        assert_eq!(entity_id, None);
+        assert!(closest_surface(&state, &Point::new(30.0, 8.0, 0.0), Some(1.0)).is_none());
+    }
+
+    #[test]
+    fn histogram_counts_dynamic_bodies_per_cell_including_negative_ones() {
+        let mut state = PhysicsState::new(0);
+        for position in [
+            Vector3::new(1.0, 5.0, 1.0),
+            Vector3::new(9.0, 5.0, 9.0),
+            Vector3::new(11.0, 5.0, 1.0),
+            Vector3::new(-0.5, 5.0, 3.0),
+            Vector3::new(-12.0, 5.0, -0.5),
+        ] {
+            state.add_test_ball(position);
+        }
+        // Fixed and kinematic bodies aren't counted
+        state.rigid_body_set.insert(
+            RigidBodyBuilder::fixed()
+                .translation(Vector3::new(1.0, 5.0, 1.0))
+                .build(),
+        );
+        state
+            .rigid_body_set
+            .insert(RigidBodyBuilder::kinematic_position_based().build());
+
+        let cells = dynamic_body_histogram(&state, 10.0);
+        assert_eq!(
+            cells,
+            vec![
+                ((-2, 0, -1), 1),
+                ((-1, 0, 0), 1),
+                ((0, 0, 0), 2),
+                ((1, 0, 0), 1)
+            ]
+        );
+        assert_eq!(cells.iter().map(|(_, count)| count).sum::<u32>(), 5);
+    }
+
+    #[test]
+    fn tag_query_returns_exactly_the_tagged_entities() {
+        // Ten entities alternating between two teams, plus untagged ids that never appear
+        let tags: Vec<EntityTag> = (1..=10u32)
+            .rev()
+            .map(|entity_id| EntityTag {
+                entity_id,
+                tag: entity_id % 2,
+            })
+            .collect();
+        assert_eq!(entities_with_tag(tags.clone(), 1), vec![1, 3, 5, 7, 9]);
+        assert_eq!(entities_with_tag(tags.clone(), 0), vec![2, 4, 6, 8, 10]);
+        assert!(entities_with_tag(tags, 2).is_empty());
+    }
+}
@@ -0,0 +1,472 @@
+//! Recording of state-mutating reducer calls and their replay into a fresh world.
+
+use log::info;
+use spacetimedb::{reducer, ReducerContext, Table};
+use std::collections::{HashMap, HashSet};
+
+use crate::bodies::{apply_force, apply_impulse_at_point, apply_torque, shockwave};
+use crate::spawn::*;
+use crate::state::*;
+use crate::tables::*;
+use crate::tick::process_physics_tick;
+use crate::types::*;
+
+// --- Helper Functions ---
+
+/// Appends `call` to `ReducerLog` if `state`'s world is recording; a no-op otherwise.
+pub(crate) fn log_call(
+    ctx: &ReducerContext,
+    state: &PhysicsState,
+    call: LoggedCall,
+    entity_ids: Vec<u32>,
+) -> Result<(), String> {
+    if !state.recording {
+        return Ok(());
+    }
+    let id = ctx
+        .db
+        .reducer_log()
+        .iter()
+        .map(|row| row.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    ctx.db
+        .reducer_log()
+        .try_insert(ReducerLog {
+            id,
+            world_id: state.world_id,
+            tick: state.tick,
+            call,
+            entity_ids,
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Maps a recorded entity id to the entity spawned in its place during a replay.
+fn replayed_entity(id_map: &HashMap<u32, u32>, entity_id: u32) -> Result<u32, String> {
+    id_map
+        .get(&entity_id)
+        .copied()
+        .ok_or_else(|| format!("Entity {} was not spawned within the replayed ticks", entity_id))
+}
+
+/// Fails if a call refers to an entity that no earlier call in `calls` spawned, e.g. one
+/// from an unlogged spawn path such as `spawn_shape` or spawned before the replayed ticks.
+fn check_replayable(calls: &[ReducerLog]) -> Result<(), String> {
+    let mut spawned = HashSet::new();
+    for row in calls {
+        let target = match &row.call {
+            LoggedCall::ApplyImpulseAtPoint(call) => Some(call.entity_id),
+            LoggedCall::ApplyForce(call) | LoggedCall::ApplyTorque(call) => Some(call.entity_id),
+            LoggedCall::Spawn(_) | LoggedCall::SpawnExplodingSpheres(_) | LoggedCall::Shockwave(_) => None,
+        };
+        if let Some(entity_id) = target.filter(|entity_id| !spawned.contains(entity_id)) {
+            return Err(format!(
+                "Entity {} was not spawned by a logged call within the replayed ticks",
+                entity_id
+            ));
+        }
+        spawned.extend(row.entity_ids.iter().copied());
+    }
+    Ok(())
+}
+
+/// Runs the logged `calls` (sorted, all within `from_tick..=to_tick`) tick by tick:
+/// `apply` repeats a call given the recorded-to-replayed id map and returns the entities
+/// it spawned, and `step` runs one tick after each tick but the last. Returns how many
+/// calls were applied.
+fn replay_calls(
+    calls: &[ReducerLog],
+    from_tick: u64,
+    to_tick: u64,
+    mut apply: impl FnMut(&LoggedCall, &HashMap<u32, u32>) -> Result<Vec<u32>, String>,
+    mut step: impl FnMut() -> Result<(), String>,
+) -> Result<usize, String> {
+    let mut id_map: HashMap<u32, u32> = HashMap::new();
+    let mut next_call = 0;
+    for tick in from_tick..=to_tick {
+        while let Some(row) = calls.get(next_call).filter(|row| row.tick == tick) {
+            next_call += 1;
+            let spawned = apply(&row.call, &id_map)?;
+            id_map.extend(row.entity_ids.iter().copied().zip(spawned));
+        }
+        // Calls logged at the last tick are applied but not stepped, like in the recording
+        if tick < to_tick {
+            step()?;
+        }
+    }
+    Ok(next_call)
+}
+
+// --- Reducers ---
+
+#[reducer]
+pub fn set_recording(_ctx: &ReducerContext, world_id: u32, enabled: bool) -> Result<(), String> {
+    info!("set_recording called for world {}: {}", world_id, enabled);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    state.recording = enabled;
+    Ok(())
+}
+
+#[reducer]
+pub fn clear_reducer_log(ctx: &ReducerContext, world_id: u32) -> Result<(), String> {
+    info!("clear_reducer_log called for world {}", world_id);
+    let ids: Vec<u64> = ctx
+        .db
+        .reducer_log()
+        .world_id()
+        .filter(world_id)
+        .map(|row| row.id)
+        .collect();
+    for id in ids {
+        ctx.db.reducer_log().id().delete(id);
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn replay(
+    ctx: &ReducerContext,
+    world_id: u32,
+    target_world_id: u32,
+    from_tick: u64,
+    to_tick: u64,
+) -> Result<(), String> {
+    info!(
+        "replay called: world {} ticks {}..={} into world {}",
+        world_id, from_tick, to_tick, target_world_id
+    );
+    if from_tick > to_tick {
+        return Err("from_tick must not exceed to_tick".to_string());
+    }
+    let mut calls: Vec<ReducerLog> = ctx
+        .db
+        .reducer_log()
+        .world_id()
+        .filter(world_id)
+        .filter(|row| (from_tick..=to_tick).contains(&row.tick))
+        .collect();
+    calls.sort_by_key(|row| row.id);
+    check_replayable(&calls)?;
+
+    {
+        let mut worlds = lock_state();
+        // Replays step one `dt` per tick, so they only match a deterministic recording
+        insert_world(ctx, &mut worlds, target_world_id)?;
+        get_world(&mut worlds, target_world_id)?.deterministic = true;
+    }
+
+    // The reducers below lock the worlds themselves, so no lock is held across them
+    let apply = |call: &LoggedCall, id_map: &HashMap<u32, u32>| -> Result<Vec<u32>, String> {
+        match call {
+            LoggedCall::Spawn(call) => {
+                // Entity ids are max + 1, so the spawn takes the next free id
+                let entity_id = get_next_entity_id(ctx)?;
+                spawn_with_options(
+                    ctx,
+                    target_world_id,
+                    call.x,
+                    call.y,
+                    call.z,
+                    call.options.clone(),
+                )?;
+                Ok(vec![entity_id])
+            }
+            LoggedCall::SpawnExplodingSpheres(seed) => {
+                let mut worlds = lock_state();
+                let state = get_world(&mut worlds, target_world_id)?;
+                check_body_limit(state, 100)?;
+                spawn_exploding_spheres_seeded(ctx, state, *seed)
+            }
+            LoggedCall::ApplyImpulseAtPoint(call) => {
+                let (impulse, point) = (call.impulse, call.point);
+                apply_impulse_at_point(
+                    ctx,
+                    replayed_entity(id_map, call.entity_id)?,
+                    impulse.x,
+                    impulse.y,
+                    impulse.z,
+                    point.x,
+                    point.y,
+                    point.z,
+                )?;
+                Ok(Vec::new())
+            }
+            LoggedCall::ApplyForce(call) => {
+                let force = call.vector;
+                let entity_id = replayed_entity(id_map, call.entity_id)?;
+                apply_force(ctx, entity_id, force.x, force.y, force.z)?;
+                Ok(Vec::new())
+            }
+            LoggedCall::ApplyTorque(call) => {
+                let torque = call.vector;
+                let entity_id = replayed_entity(id_map, call.entity_id)?;
+                apply_torque(ctx, entity_id, torque.x, torque.y, torque.z)?;
+                Ok(Vec::new())
+            }
+            LoggedCall::Shockwave(call) => {
+                let center = call.center;
+                shockwave(
+                    ctx,
+                    target_world_id,
+                    center.x,
+                    center.y,
+                    center.z,
+                    call.radius,
+                    call.strength,
+                )?;
+                Ok(Vec::new())
+            }
+        }
+    };
+    let step = || {
+        let timer = ctx
+            .db
+            .physics_tick_timer()
+            .id()
+            .find(target_world_id as u64)
+            .ok_or_else(|| format!("No tick timer for world {}", target_world_id))?;
+        process_physics_tick(ctx, timer)
+    };
+    // A failed reducer rolls back the target world's rows but not the in-memory map, so
+    // drop the half-replayed world too; the same target can then be retried
+    let replayed = match replay_calls(&calls, from_tick, to_tick, apply, step) {
+        Ok(replayed) => replayed,
+        Err(e) => {
+            lock_state().remove(&target_world_id);
+            return Err(e);
+        }
+    };
+    info!("  -> Replayed {} calls over {} ticks", replayed, to_tick - from_tick);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tick::{step_world, take_substeps, EventCollector, GRAVITY};
+    use rapier3d::na::Vector3;
+    use rapier3d::prelude::*;
+    use std::cell::RefCell;
+
+    /// Runs a world with a seeded burst and a mid-run impulse, ticking at 16ms plus
+    /// `jitter` (µs), and returns every sphere's final pose.
+    fn recorded_run(deterministic: bool, jitter: &[i64]) -> Vec<Isometry<Real>> {
+        let mut state = PhysicsState::new(0);
+        state.deterministic = deterministic;
+        let spheres: Vec<RigidBodyHandle> = exploding_sphere_parts(deterministic_seed(0, 0, 0))
+            .unwrap()
+            .into_iter()
+            .map(|(rigid_body, collider, _)| {
+                let rb_handle = state.rigid_body_set.insert(rigid_body);
+                state.collider_set.insert_with_parent(
+                    collider,
+                    rb_handle,
+                    &mut state.rigid_body_set,
+                );
+                rb_handle
+            })
+            .collect();
+        let gravity = Vector3::new(0.0, -GRAVITY, 0.0);
+        for tick in 0..90 {
+            let now = tick * 16_000 + jitter[tick as usize % jitter.len()];
+            let substeps = take_substeps(&mut state, now);
+            if tick == 30 {
+                let body = &mut state.rigid_body_set[spheres[0]];
+                let point = Point::from(body.translation() + Vector3::new(0.0, 0.2, 0.0));
+                body.apply_impulse_at_point(Vector3::new(1.0, 2.0, 0.0), point, true);
+            }
+            step_world(&mut state, substeps, &gravity, &EventCollector::default());
+        }
+        spheres
+            .iter()
+            .map(|handle| *state.rigid_body_set[*handle].position())
+            .collect()
+    }
+
+    #[test]
+    fn deterministic_run_repeats_exactly_despite_tick_jitter() {
+        // Late and early ticks around the impulse (tick 30) and at the end (tick 89)
+        let jitter = [0, -5_000, 3_000, 11_000, 2_000, -6_000, 7_000];
+        let steady = recorded_run(true, &[0]);
+        assert_eq!(recorded_run(true, &jitter), steady);
+        // The same jitter shifts a free-running world's steps, so it does matter
+        assert_eq!(recorded_run(false, &[0]), steady);
+        assert_ne!(recorded_run(false, &jitter), steady);
+    }
+
+    #[test]
+    fn replayed_ids_map_to_the_respawned_entities() {
+        let id_map = HashMap::from([(4, 17), (5, 18)]);
+        assert_eq!(replayed_entity(&id_map, 5), Ok(18));
+        assert!(replayed_entity(&id_map, 6).is_err());
+    }
+
+    /// A world whose entities are numbered from `first_id` in spawn order.
+    struct TestWorld {
+        state: PhysicsState,
+        bodies: Vec<RigidBodyHandle>,
+        first_id: u32,
+    }
+
+    impl TestWorld {
+        fn new(first_id: u32) -> Self {
+            let mut state = PhysicsState::new(0);
+            state.deterministic = true;
+            TestWorld {
+                state,
+                bodies: Vec::new(),
+                first_id,
+            }
+        }
+
+        /// What the logged reducers do to the physics world, minus the tables.
+        fn run(
+            &mut self,
+            call: &LoggedCall,
+            id_map: &HashMap<u32, u32>,
+        ) -> Result<Vec<u32>, String> {
+            match call {
+                LoggedCall::SpawnExplodingSpheres(seed) => {
+                    let mut spawned = Vec::new();
+                    for (rigid_body, collider, _) in exploding_sphere_parts(*seed)? {
+                        let rb_handle = self.state.rigid_body_set.insert(rigid_body);
+                        self.state.collider_set.insert_with_parent(
+                            collider,
+                            rb_handle,
+                            &mut self.state.rigid_body_set,
+                        );
+                        spawned.push(self.first_id + self.bodies.len() as u32);
+                        self.bodies.push(rb_handle);
+                    }
+                    Ok(spawned)
+                }
+                LoggedCall::ApplyImpulseAtPoint(call) => {
+                    let entity_id = replayed_entity(id_map, call.entity_id)?;
+                    let rb_handle = self.bodies[(entity_id - self.first_id) as usize];
+                    let (impulse, point) = (call.impulse, call.point);
+                    self.state.rigid_body_set[rb_handle].apply_impulse_at_point(
+                        Vector3::new(impulse.x, impulse.y, impulse.z),
+                        Point::new(point.x, point.y, point.z),
+                        true,
+                    );
+                    Ok(Vec::new())
+                }
+                _ => unreachable!("not used in these tests"),
+            }
+        }
+
+        fn step(&mut self) {
+            step_world(
+                &mut self.state,
+                1,
+                &Vector3::new(0.0, -GRAVITY, 0.0),
+                &EventCollector::default(),
+            );
+        }
+
+        fn poses(&self) -> Vec<Isometry<Real>> {
+            self.bodies
+                .iter()
+                .map(|handle| *self.state.rigid_body_set[*handle].position())
+                .collect()
+        }
+    }
+
+    fn logged(id: u64, tick: u64, call: LoggedCall, entity_ids: Vec<u32>) -> ReducerLog {
+        ReducerLog {
+            id,
+            world_id: 0,
+            tick,
+            call,
+            entity_ids,
+        }
+    }
+
+    #[test]
+    fn replaying_a_recorded_scenario_reproduces_its_final_transforms() {
+        // Record: a burst at tick 0 and an off-center impulse on one sphere at tick 20
+        let mut recorded = TestWorld::new(1);
+        let identity: HashMap<u32, u32> = (1..=100).map(|id| (id, id)).collect();
+        let mut calls = Vec::new();
+        for tick in 0..=40u64 {
+            if tick == 0 {
+                let call = LoggedCall::SpawnExplodingSpheres(deterministic_seed(0, 0, 1));
+                let spawned = recorded.run(&call, &identity).unwrap();
+                calls.push(logged(1, tick, call, spawned));
+            }
+            if tick == 20 {
+                let call = LoggedCall::ApplyImpulseAtPoint(ImpulseCall {
+                    entity_id: 5,
+                    impulse: Vec3 {
+                        x: 3.0,
+                        y: 1.0,
+                        z: 0.0,
+                    },
+                    point: Vec3 {
+                        x: 0.0,
+                        y: 12.0,
+                        z: 0.0,
+                    },
+                });
+                recorded.run(&call, &identity).unwrap();
+                calls.push(logged(2, tick, call, Vec::new()));
+            }
+            if tick < 40 {
+                recorded.step();
+            }
+        }
+
+        // Replay into a world whose entity ids are numbered differently
+        check_replayable(&calls).unwrap();
+        let replayed = RefCell::new(TestWorld::new(1001));
+        let applied = replay_calls(
+            &calls,
+            0,
+            40,
+            |call, id_map| replayed.borrow_mut().run(call, id_map),
+            || {
+                replayed.borrow_mut().step();
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(applied, 2);
+        assert_eq!(replayed.borrow().poses(), recorded.poses());
+    }
+
+    #[test]
+    fn calls_on_entities_spawned_outside_the_log_are_rejected_up_front() {
+        let impulse = |entity_id| {
+            LoggedCall::ApplyForce(ForceCall {
+                entity_id,
+                vector: Vec3 {
+                    x: 1.0,
+                    y: 0.0,
+                    z: 0.0,
+                },
+            })
+        };
+        let spawn = LoggedCall::SpawnExplodingSpheres(7);
+        assert!(check_replayable(&[
+            logged(1, 0, spawn.clone(), vec![3, 4]),
+            logged(2, 1, impulse(4), Vec::new())
+        ])
+        .is_ok());
+        // E.g. a `spawn_shape` entity, which isn't logged
+        assert!(check_replayable(&[
+            logged(1, 0, spawn.clone(), vec![3, 4]),
+            logged(2, 1, impulse(9), Vec::new())
+        ])
+        .is_err());
+        // Used before the logged spawn
+        assert!(check_replayable(&[
+            logged(1, 0, impulse(3), Vec::new()),
+            logged(2, 0, spawn, vec![3])
+        ])
+        .is_err());
+    }
+}
@@ -0,0 +1,535 @@
+//! Binary world snapshots (`snapshot_world` / `import_world`).
+
+use log::info;
+use rapier3d::na::Vector3;
+use rapier3d::prelude::BroadPhaseMultiSap;
+use rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+use spacetimedb::{reducer, ReducerContext, Table};
+use std::collections::{HashMap, HashSet};
+
+use crate::state::*;
+use crate::tables::*;
+use crate::types::*;
+
+/// Bumped whenever the layout of `WorldSnapshotRef`/`WorldSnapshotData` changes.
+/// v2 added the island manager and broad/narrow phases: bodies and colliders keep indices
+/// into those structures, so restoring the sets alone leaves them inconsistent.
+/// v3 added the contact hooks, collision logging opt-ins and `EntityRows`.
+const SNAPSHOT_VERSION: u8 = 3;
+
+/// Per-entity table rows that hold state the physics sets don't, so an import can
+/// restore them. Rebuildable rows (transforms, shapes, joints, ...) aren't stored.
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+struct EntityRows {
+    tag: Option<u32>,
+    /// `(belongs_to, collides_with)` layer ids.
+    layers: Option<(Vec<u32>, Vec<u32>)>,
+    /// `(axis, along_friction, across_friction)`.
+    friction_axis: Option<(Vec3, f32, f32)>,
+    /// `(center, density)` of a `set_center_of_mass` override.
+    center_of_mass: Option<(Vec3, f32)>,
+    lifetime_ticks: Option<u32>,
+    /// `(linvel, angvel)` stored by `freeze_entity`.
+    frozen_velocity: Option<(Vec3, Vec3)>,
+}
+
+impl EntityRows {
+    fn collect(ctx: &ReducerContext, entity_id: u32) -> Self {
+        EntityRows {
+            tag: ctx.db.entity_tag().entity_id().find(entity_id).map(|row| row.tag),
+            layers: ctx
+                .db
+                .entity_layers()
+                .entity_id()
+                .find(entity_id)
+                .map(|row| (row.belongs_to, row.collides_with)),
+            friction_axis: ctx
+                .db
+                .entity_friction_axis()
+                .entity_id()
+                .find(entity_id)
+                .map(|row| (row.axis, row.along_friction, row.across_friction)),
+            center_of_mass: ctx
+                .db
+                .entity_center_of_mass()
+                .entity_id()
+                .find(entity_id)
+                .map(|row| (row.center, row.density)),
+            lifetime_ticks: ctx
+                .db
+                .entity_lifetime()
+                .entity_id()
+                .find(entity_id)
+                .map(|row| row.remaining_ticks),
+            frozen_velocity: ctx
+                .db
+                .frozen_state()
+                .entity_id()
+                .find(entity_id)
+                .map(|row| (row.linvel, row.angvel)),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == EntityRows::default()
+    }
+
+    /// Re-inserts the rows. Frozen entities count as frozen since the import.
+    fn insert(self, ctx: &ReducerContext, world_id: u32, entity_id: u32) -> Result<(), String> {
+        if let Some(tag) = self.tag {
+            ctx.db
+                .entity_tag()
+                .try_insert(EntityTag { entity_id, tag })
+                .map_err(|e| e.to_string())?;
+        }
+        if let Some((belongs_to, collides_with)) = self.layers {
+            ctx.db
+                .entity_layers()
+                .try_insert(EntityLayers {
+                    entity_id,
+                    belongs_to,
+                    collides_with,
+                })
+                .map_err(|e| e.to_string())?;
+        }
+        if let Some((axis, along_friction, across_friction)) = self.friction_axis {
+            ctx.db
+                .entity_friction_axis()
+                .try_insert(EntityFrictionAxis {
+                    entity_id,
+                    axis,
+                    along_friction,
+                    across_friction,
+                })
+                .map_err(|e| e.to_string())?;
+        }
+        if let Some((center, density)) = self.center_of_mass {
+            ctx.db
+                .entity_center_of_mass()
+                .try_insert(EntityCenterOfMass {
+                    entity_id,
+                    center,
+                    density,
+                })
+                .map_err(|e| e.to_string())?;
+        }
+        if let Some(remaining_ticks) = self.lifetime_ticks {
+            ctx.db
+                .entity_lifetime()
+                .try_insert(EntityLifetime {
+                    entity_id,
+                    world_id,
+                    remaining_ticks,
+                })
+                .map_err(|e| e.to_string())?;
+        }
+        if let Some((linvel, angvel)) = self.frozen_velocity {
+            ctx.db
+                .frozen_state()
+                .try_insert(FrozenState {
+                    entity_id,
+                    linvel,
+                    angvel,
+                    frozen_at: ctx.timestamp,
+                })
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// Borrowed view of the parts of `PhysicsState` that make up a snapshot.
+#[derive(Serialize)]
+struct WorldSnapshotRef<'a> {
+    rigid_body_set: &'a RigidBodySet,
+    collider_set: &'a ColliderSet,
+    impulse_joint_set: &'a ImpulseJointSet,
+    multibody_joint_set: &'a MultibodyJointSet,
+    island_manager: &'a IslandManager,
+    broad_phase: &'a BroadPhaseMultiSap,
+    narrow_phase: &'a NarrowPhase,
+    handle_to_entity_id: &'a HashMap<RigidBodyHandle, u32>,
+    collider_to_entity_id: &'a HashMap<ColliderHandle, u32>,
+    hooks: &'a WorldHooks,
+    collision_logging: &'a HashSet<u32>,
+    entity_rows: HashMap<u32, EntityRows>,
+}
+
+/// Owned counterpart of `WorldSnapshotRef`; fields must stay in the same order.
+#[derive(Deserialize)]
+struct WorldSnapshotData {
+    rigid_body_set: RigidBodySet,
+    collider_set: ColliderSet,
+    impulse_joint_set: ImpulseJointSet,
+    multibody_joint_set: MultibodyJointSet,
+    island_manager: IslandManager,
+    broad_phase: BroadPhaseMultiSap,
+    narrow_phase: NarrowPhase,
+    handle_to_entity_id: HashMap<RigidBodyHandle, u32>,
+    collider_to_entity_id: HashMap<ColliderHandle, u32>,
+    hooks: WorldHooks,
+    collision_logging: HashSet<u32>,
+    entity_rows: HashMap<u32, EntityRows>,
+}
+
+impl<'a> WorldSnapshotRef<'a> {
+    fn new(state: &'a PhysicsState, entity_rows: HashMap<u32, EntityRows>) -> Self {
+        WorldSnapshotRef {
+            rigid_body_set: &state.rigid_body_set,
+            collider_set: &state.collider_set,
+            impulse_joint_set: &state.impulse_joint_set,
+            multibody_joint_set: &state.multibody_joint_set,
+            island_manager: &state.island_manager,
+            broad_phase: &state.broad_phase,
+            narrow_phase: &state.narrow_phase,
+            handle_to_entity_id: &state.handle_to_entity_id,
+            collider_to_entity_id: &state.collider_to_entity_id,
+            hooks: &state.hooks,
+            collision_logging: &state.collision_logging,
+            entity_rows,
+        }
+    }
+}
+
+impl WorldSnapshotData {
+    /// Replaces `state`'s physics with the snapshot's and returns the entity rows,
+    /// which the caller re-inserts once the entities exist again.
+    fn restore_into(self, state: &mut PhysicsState) -> HashMap<u32, EntityRows> {
+        state.rigid_body_set = self.rigid_body_set;
+        state.collider_set = self.collider_set;
+        state.impulse_joint_set = self.impulse_joint_set;
+        state.multibody_joint_set = self.multibody_joint_set;
+        state.island_manager = self.island_manager;
+        state.broad_phase = self.broad_phase;
+        state.narrow_phase = self.narrow_phase;
+        state.handle_to_entity_id = self.handle_to_entity_id;
+        state.collider_to_entity_id = self.collider_to_entity_id;
+        // The hooks' collider handles are valid again now that the collider set is restored
+        state.hooks = self.hooks;
+        state.collision_logging = self.collision_logging;
+        // Counted against the replaced bodies' handles
+        state.awake_ticks.clear();
+        // Queries may run before the next step refreshes the pipeline
+        state
+            .query_pipeline
+            .update(&state.rigid_body_set, &state.collider_set);
+        self.entity_rows
+    }
+}
+
+/// Version byte followed by the bincode-encoded snapshot.
+fn encode_snapshot(snapshot: &WorldSnapshotRef) -> Result<Vec<u8>, String> {
+    let mut data = vec![SNAPSHOT_VERSION];
+    bincode::serialize_into(&mut data, snapshot).map_err(|e| e.to_string())?;
+    Ok(data)
+}
+
+fn decode_snapshot(bytes: &[u8]) -> Result<WorldSnapshotData, String> {
+    let (version, payload) = bytes
+        .split_first()
+        .ok_or_else(|| "Snapshot is empty".to_string())?;
+    if *version != SNAPSHOT_VERSION {
+        return Err(format!(
+            "Unsupported snapshot version {} (expected {})",
+            version, SNAPSHOT_VERSION
+        ));
+    }
+    bincode::deserialize(payload).map_err(|e| e.to_string())
+}
+
+// --- Reducers ---
+
+#[reducer]
+pub fn snapshot_world(ctx: &ReducerContext, world_id: u32) -> Result<(), String> {
+    info!("Snapshotting physics world...");
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    let entity_rows = state
+        .handle_to_entity_id
+        .values()
+        .map(|entity_id| (*entity_id, EntityRows::collect(ctx, *entity_id)))
+        .filter(|(_, rows)| !rows.is_empty())
+        .collect();
+    let data = encode_snapshot(&WorldSnapshotRef::new(state, entity_rows))?;
+
+    info!("  -> Snapshot is {} bytes ({} bodies)", data.len(), state.rigid_body_set.len());
+    let row = WorldSnapshot {
+        world_id,
+        taken_at: ctx.timestamp,
+        data,
+    };
+    // One row per world: overwrite any previous snapshot
+    if ctx.db.world_snapshot().world_id().find(world_id).is_some() {
+        ctx.db.world_snapshot().world_id().update(row);
+    } else {
+        ctx.db
+            .world_snapshot()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn import_world(ctx: &ReducerContext, world_id: u32, bytes: Vec<u8>) -> Result<(), String> {
+    info!("Importing physics world from {} bytes...", bytes.len());
+    let snapshot = decode_snapshot(&bytes)?;
+
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let mut entity_rows = snapshot.restore_into(state);
+
+    // Drop the world's existing rows; the restored handle maps are now the source of truth
+    let old_ids: Vec<u32> = ctx
+        .db
+        .entity()
+        .world_id()
+        .filter(world_id)
+        .map(|entity| entity.id)
+        .collect();
+    for entity_id in &old_ids {
+        delete_entity_rows(ctx, *entity_id);
+    }
+
+    let mut next_collider_row_id = ctx
+        .db
+        .entity_collider()
+        .iter()
+        .map(|row| row.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    // Rebuild the tables from the restored handles.
+    // Re-inserting the `Entity` rows also moves the id counter (`get_next_entity_id` is
+    // max id + 1) past every restored id, so new spawns can't collide with them.
+    for (rb_handle, entity_id) in state.handle_to_entity_id.iter() {
+        let rigid_body = state
+            .rigid_body_set
+            .get(*rb_handle)
+            .ok_or_else(|| format!("Snapshot has no rigid body for entity {}", entity_id))?;
+        // The first attached collider is the primary one, the rest came from attach_collider
+        let (co_handle, extra_colliders) = rigid_body
+            .colliders()
+            .split_first()
+            .ok_or_else(|| format!("Snapshot has no collider for entity {}", entity_id))?;
+        let (rb_idx, rb_gen) = rb_handle.into_raw_parts();
+        let (co_idx, co_gen) = co_handle.into_raw_parts();
+
+        ctx.db
+            .entity()
+            .try_insert(Entity {
+                id: *entity_id,
+                world_id,
+            })
+            .map_err(|e| e.to_string())?;
+        ctx.db
+            .entity_physics()
+            .try_insert(EntityPhysics {
+                entity_id: *entity_id,
+                world_id,
+                rb_handle_index: rb_idx,
+                rb_handle_generation: rb_gen,
+                co_handle_index: co_idx,
+                co_handle_generation: co_gen,
+            })
+            .map_err(|e| e.to_string())?;
+        let transform = EntityTransform::from_pose(world_id, *entity_id, rigid_body.position());
+        ctx.db
+            .entity_transform()
+            .try_insert(transform.clone())
+            .map_err(|e| e.to_string())?;
+        ctx.db
+            .entity_transform_prev()
+            .try_insert(transform.into())
+            .map_err(|e| e.to_string())?;
+        ctx.db
+            .entity_velocity()
+            .try_insert(EntityVelocity::from_body(*entity_id, rigid_body))
+            .map_err(|e| e.to_string())?;
+        if let Some(collider) = state.collider_set.get(*co_handle) {
+            ctx.db
+                .entity_material()
+                .try_insert(EntityMaterial::from_collider(*entity_id, collider))
+                .map_err(|e| e.to_string())?;
+        }
+        let shape = state
+            .collider_set
+            .get(*co_handle)
+            .and_then(|collider| ShapeDesc::from_shape(collider.shape()));
+        if let Some(shape) = shape {
+            ctx.db
+                .entity_shape()
+                .try_insert(EntityShape {
+                    entity_id: *entity_id,
+                    shape,
+                })
+                .map_err(|e| e.to_string())?;
+        }
+        for extra_handle in extra_colliders {
+            let Some(collider) = state.collider_set.get(*extra_handle) else {
+                continue;
+            };
+            let Some(shape) = ShapeDesc::from_shape(collider.shape()) else {
+                continue;
+            };
+            let local = collider
+                .position_wrt_parent()
+                .map(|iso| iso.translation.vector)
+                .unwrap_or_else(Vector3::zeros);
+            let (extra_idx, extra_gen) = extra_handle.into_raw_parts();
+            ctx.db
+                .entity_collider()
+                .try_insert(EntityCollider {
+                    id: next_collider_row_id,
+                    entity_id: *entity_id,
+                    shape,
+                    local_x: local.x,
+                    local_y: local.y,
+                    local_z: local.z,
+                    co_handle_index: extra_idx,
+                    co_handle_generation: extra_gen,
+                })
+                .map_err(|e| e.to_string())?;
+            next_collider_row_id += 1;
+        }
+        if let Some(rows) = entity_rows.remove(entity_id) {
+            rows.insert(ctx, world_id, *entity_id)?;
+        }
+    }
+
+    let mut next_joint_row_id = ctx
+        .db
+        .entity_joint()
+        .iter()
+        .map(|row| row.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    for (joint_handle, joint) in state.impulse_joint_set.iter() {
+        let (Some(entity_a), Some(entity_b)) = (
+            state.handle_to_entity_id.get(&joint.body1),
+            state.handle_to_entity_id.get(&joint.body2),
+        ) else {
+            continue;
+        };
+        let (joint_idx, joint_gen) = joint_handle.into_raw_parts();
+        ctx.db
+            .entity_joint()
+            .try_insert(EntityJoint {
+                id: next_joint_row_id,
+                world_id,
+                entity_a: *entity_a,
+                entity_b: *entity_b,
+                kind: JointKind::from_joint(&joint.data),
+                joint_handle_index: joint_idx,
+                joint_handle_generation: joint_gen,
+            })
+            .map_err(|e| e.to_string())?;
+        next_joint_row_id += 1;
+    }
+
+    let mut next_link_row_id = ctx
+        .db
+        .multibody_link()
+        .iter()
+        .map(|row| row.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    for (joint_handle, _, multibody, link) in state.multibody_joint_set.iter() {
+        // Roots have no joint of their own
+        let Some(parent_link) = link.parent_id().and_then(|parent| multibody.link(parent)) else {
+            continue;
+        };
+        let (Some(parent_entity), Some(child_entity)) = (
+            state.handle_to_entity_id.get(&parent_link.rigid_body_handle()),
+            state.handle_to_entity_id.get(&link.rigid_body_handle()),
+        ) else {
+            continue;
+        };
+        let (joint_idx, joint_gen) = joint_handle.into_raw_parts();
+        ctx.db
+            .multibody_link()
+            .try_insert(MultibodyLink {
+                id: next_link_row_id,
+                world_id,
+                parent_entity: *parent_entity,
+                child_entity: *child_entity,
+                kind: LinkKind::from_joint(&link.joint.data),
+                joint_handle_index: joint_idx,
+                joint_handle_generation: joint_gen,
+            })
+            .map_err(|e| e.to_string())?;
+        next_link_row_id += 1;
+    }
+
+    info!(
+        "  -> Import complete. {} entities restored (replaced {}).",
+        state.handle_to_entity_id.len(),
+        old_ids.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_round_trip_keeps_hooks_and_entity_rows() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 5.0, 0.0));
+        let co_handle = state.rigid_body_set[rb_handle].colliders()[0];
+        state.hooks.conveyors.insert(co_handle, Vector3::x());
+        state.collision_logging.insert(7);
+        let rows = EntityRows {
+            tag: Some(3),
+            layers: Some((vec![1], vec![0, 2])),
+            lifetime_ticks: Some(40),
+            ..Default::default()
+        };
+        let snapshot = WorldSnapshotRef::new(&state, HashMap::from([(7, rows)]));
+
+        let data = encode_snapshot(&snapshot).unwrap();
+        let restored = decode_snapshot(&data).unwrap();
+        assert_eq!(restored.rigid_body_set.len(), state.rigid_body_set.len());
+        assert_eq!(restored.hooks.conveyors.get(&co_handle), Some(&Vector3::x()));
+        assert!(restored.collision_logging.contains(&7));
+        let restored_rows = &restored.entity_rows[&7];
+        assert_eq!(restored_rows.tag, Some(3));
+        assert_eq!(restored_rows.layers, Some((vec![1], vec![0, 2])));
+        assert_eq!(restored_rows.lifetime_ticks, Some(40));
+        assert_eq!(restored_rows.frozen_velocity, None);
+    }
+
+    #[test]
+    fn decode_rejects_other_versions() {
+        assert!(decode_snapshot(&[]).is_err());
+        assert!(decode_snapshot(&[SNAPSHOT_VERSION - 1]).is_err());
+    }
+
+    #[test]
+    fn restored_world_keeps_stepping_like_the_original() {
+        let mut original = PhysicsState::new(0);
+        original.add_test_ball(Vector3::new(0.0, 4.0, 0.0));
+        original.add_test_ball(Vector3::new(0.3, 7.0, 0.1));
+        for _ in 0..20 {
+            original.step_once();
+        }
+
+        let data = encode_snapshot(&WorldSnapshotRef::new(&original, HashMap::new())).unwrap();
+        let mut restored = PhysicsState::new(0);
+        decode_snapshot(&data).unwrap().restore_into(&mut restored);
+
+        for _ in 0..30 {
+            original.step_once();
+            restored.step_once();
+        }
+        for (handle, body) in original.rigid_body_set.iter() {
+            assert_eq!(restored.rigid_body_set[handle].position(), body.position());
+            assert_eq!(restored.rigid_body_set[handle].linvel(), body.linvel());
+        }
+    }
+}
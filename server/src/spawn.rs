@@ -0,0 +1,1132 @@
+//! Spawn reducers and the shared `register_body` helper.
+
+use log::info;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng}; // For random number generation
+use rapier3d::na::Vector3;
+use rapier3d::prelude::*;
+use spacetimedb::{reducer, ReducerContext, Table};
+
+use crate::replay::log_call;
+use crate::state::*;
+use crate::tables::*;
+use crate::types::*;
+
+// --- Helper Functions ---
+
+pub(crate) fn get_next_entity_id(ctx: &ReducerContext) -> Result<u32, String> {
+    let max_id = ctx
+        .db
+        .entity()
+        .iter()
+        .map(|entity| entity.id)
+        .max()
+        .unwrap_or(0);
+    Ok(max_id + 1)
+}
+
+/// Groups a flat `[x0, y0, z0, x1, ...]` array into points.
+pub(crate) fn points_from_flat(values: &[f32]) -> Result<Vec<Point<f32>>, String> {
+    if values.is_empty() || values.len() % 3 != 0 {
+        return Err("Point array length must be a non-zero multiple of 3".to_string());
+    }
+    if !values.iter().all(|v| v.is_finite()) {
+        return Err("Point coordinates must be finite".to_string());
+    }
+    Ok(values
+        .chunks_exact(3)
+        .map(|p| Point::new(p[0], p[1], p[2]))
+        .collect())
+}
+
+/// Builds a heightfield from row-major samples: rows run along Z, columns along X,
+/// centered on the body origin.
+pub(crate) fn heightfield_collider(
+    rows: u32,
+    cols: u32,
+    heights: &[f32],
+    scale: Vector3<f32>,
+) -> Result<Collider, String> {
+    if rows < 2 || cols < 2 {
+        return Err("Heightfield needs at least 2x2 samples".to_string());
+    }
+    if heights.len() != (rows as usize) * (cols as usize) {
+        return Err(format!(
+            "Expected {} heights for {}x{} samples, got {}",
+            rows * cols,
+            rows,
+            cols,
+            heights.len()
+        ));
+    }
+    if !heights.iter().all(|h| h.is_finite()) {
+        return Err("Heights must be finite".to_string());
+    }
+    if !scale.iter().all(|v| v.is_finite() && *v > 0.0) {
+        return Err("Heightfield scale must be > 0".to_string());
+    }
+    let height_matrix = rapier3d::na::DMatrix::from_row_slice(rows as usize, cols as usize, heights);
+    Ok(ColliderBuilder::heightfield(height_matrix, scale).build())
+}
+
+/// Builds a triangle mesh from flat vertex coordinates and vertex indices.
+pub(crate) fn trimesh_collider(vertices: &[f32], indices: &[u32]) -> Result<Collider, String> {
+    let points = points_from_flat(vertices)?;
+    if indices.is_empty() || indices.len() % 3 != 0 {
+        return Err("Index array length must be a non-zero multiple of 3".to_string());
+    }
+    if let Some(bad) = indices.iter().find(|i| **i as usize >= points.len()) {
+        return Err(format!("Index {} out of range for {} vertices", bad, points.len()));
+    }
+    let triangles: Vec<[u32; 3]> = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+    Ok(ColliderBuilder::trimesh(points, triangles).build())
+}
+
+/// Builds the convex hull of a flat point array, relative to the body origin.
+pub(crate) fn convex_hull_collider(points: &[f32]) -> Result<Collider, String> {
+    let points = points_from_flat(points)?;
+    // Degenerate input (too few, collinear or coplanar points) has no 3D hull
+    Ok(ColliderBuilder::convex_hull(&points)
+        .ok_or_else(|| "Could not compute a convex hull from the given points".to_string())?
+        .restitution(0.7)
+        .build())
+}
+
+/// Inserts a body and its collider into the world, records the handle mappings and
+/// creates the entity's `Entity`/`EntityPhysics`/`EntityTransform`/`EntityShape` rows.
+/// Returns the new entity id. Callers are expected to have run `check_body_limit`.
+pub(crate) fn register_body(
+    ctx: &ReducerContext,
+    state: &mut PhysicsState,
+    mut rigid_body: RigidBody,
+    mut collider: Collider,
+    shape: ShapeDesc,
+) -> Result<u32, String> {
+    let entity_id = get_next_entity_id(ctx)?;
+    let world_id = state.world_id;
+
+    // Destructure state to borrow fields mutably without conflict
+    let PhysicsState {
+        rigid_body_set,
+        collider_set,
+        handle_to_entity_id,
+        collider_to_entity_id,
+        sleep_thresholds,
+        contact_force_threshold,
+        .. // Ignore other fields
+    } = state;
+
+    enable_contact_force_events(&mut collider, *contact_force_threshold);
+    let pose = *rigid_body.position();
+    let velocity = EntityVelocity::from_body(entity_id, &rigid_body);
+    let material = EntityMaterial::from_collider(entity_id, &collider);
+    if let Some((linear, angular)) = *sleep_thresholds {
+        apply_sleep_thresholds(&mut rigid_body, linear, angular);
+    }
+    let rigid_body_handle = rigid_body_set.insert(rigid_body);
+    // Insert collider and attach it to the rigid body using destructured refs
+    let collider_handle =
+        collider_set.insert_with_parent(collider, rigid_body_handle, rigid_body_set);
+
+    // Associate the body and collider handles with the entity ID for lookups
+    handle_to_entity_id.insert(rigid_body_handle, entity_id);
+    collider_to_entity_id.insert(collider_handle, entity_id);
+
+    ctx.db
+        .entity()
+        .try_insert(Entity {
+            id: entity_id,
+            world_id,
+        })
+        .map_err(|e| e.to_string())?;
+    // Store raw parts of the handles
+    let (rb_idx, rb_gen) = rigid_body_handle.into_raw_parts();
+    let (co_idx, co_gen) = collider_handle.into_raw_parts();
+    ctx.db
+        .entity_physics()
+        .try_insert(EntityPhysics {
+            entity_id,
+            world_id,
+            rb_handle_index: rb_idx,
+            rb_handle_generation: rb_gen,
+            co_handle_index: co_idx,
+            co_handle_generation: co_gen,
+        })
+        .map_err(|e| e.to_string())?;
+    // Insert transform with the *actual* spawn pose used by physics
+    let transform = EntityTransform::from_pose(world_id, entity_id, &pose);
+    ctx.db
+        .entity_transform()
+        .try_insert(transform.clone())
+        .map_err(|e| e.to_string())?;
+    ctx.db
+        .entity_transform_prev()
+        .try_insert(transform.into())
+        .map_err(|e| e.to_string())?;
+    ctx.db
+        .entity_velocity()
+        .try_insert(velocity)
+        .map_err(|e| e.to_string())?;
+    ctx.db
+        .entity_shape()
+        .try_insert(EntityShape { entity_id, shape })
+        .map_err(|e| e.to_string())?;
+    ctx.db
+        .entity_material()
+        .try_insert(material)
+        .map_err(|e| e.to_string())?;
+    Ok(entity_id)
+}
+
+// --- Reducers ---
+
+#[reducer]
+pub fn spawn(ctx: &ReducerContext, world_id: u32, x: f64, y: f64, z: f64) -> Result<(), String> {
+    spawn_with_options(ctx, world_id, x, y, z, SpawnOptions::default())
+}
+
+#[reducer]
+pub fn spawn_with_options(
+    ctx: &ReducerContext,
+    world_id: u32,
+    x: f64,
+    y: f64,
+    z: f64,
+    options: SpawnOptions,
+) -> Result<(), String> {
+    info!("Spawn called with coords: x={}, y={}, z={}", x, y, z);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    check_body_limit(state, 1)?;
+
+    // Spawn the rigid body higher up (e.g., y=10.0) to allow falling
+    let spawn_y = 10.0;
+    // Removed spawning height log
+    let rigid_body = options
+        .apply_to_body(RigidBodyBuilder::dynamic())?
+        .translation(Vector3::new(x as f32, spawn_y as f32, z as f32)) // Use spawn_y
+        .build();
+    // Collider has restitution for bouncing
+    let shape = ShapeDesc::ball(1.0);
+    let collider = options
+        .apply_to_collider(shape.collider_builder()?.restitution(0.7))?
+        .build();
+
+    let entity_id = register_body(ctx, state, rigid_body, collider, shape)?;
+    options.apply_to_entity(ctx, state, entity_id)?;
+    let call = LoggedCall::Spawn(SpawnCall { x, y, z, options });
+    log_call(ctx, state, call, vec![entity_id])?;
+    info!("  -> Spawn successful for entity_id: {}", entity_id); // Keep success log
+    Ok(())
+}
+
+#[reducer]
+pub fn spawn_exploding_spheres(ctx: &ReducerContext, world_id: u32) -> Result<(), String> {
+    info!("Spawn exploding spheres called");
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    check_body_limit(state, 100)?;
+    // `ctx.rng()` is seeded from the call's timestamp, so deterministic worlds seed
+    // from the world, tick and call count instead to replay identically from scratch.
+    // The count keeps two bursts in the same tick from getting the same directions.
+    let seed = if state.deterministic {
+        deterministic_seed(world_id, state.tick, state.seeded_spawns)
+    } else {
+        ctx.rng().gen()
+    };
+    let entity_ids = spawn_exploding_spheres_seeded(ctx, state, seed)?;
+    log_call(ctx, state, LoggedCall::SpawnExplodingSpheres(seed), entity_ids)?;
+    info!("  -> Spawned 100 exploding spheres successfully");
+    Ok(())
+}
+
+/// Seed of a deterministic world's `call`-th burst in `tick`. The call count is spread
+/// over all bits so nearby counts don't give nearby seeds.
+pub(crate) fn deterministic_seed(world_id: u32, tick: u64, call: u64) -> u64 {
+    (((world_id as u64) << 32) ^ tick) ^ call.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+/// Spawns `spawn_exploding_spheres`' burst with directions drawn from `seed`.
+/// Returns the new entity ids. Callers are expected to have run `check_body_limit`.
+pub(crate) fn spawn_exploding_spheres_seeded(
+    ctx: &ReducerContext,
+    state: &mut PhysicsState,
+    seed: u64,
+) -> Result<Vec<u32>, String> {
+    // Counted on replay too, so a replayed world seeds later bursts like the original
+    state.seeded_spawns += 1;
+    let spheres = exploding_sphere_parts(seed)?;
+    let mut entity_ids = Vec::with_capacity(spheres.len());
+    for (i, (rigid_body, collider, shape)) in spheres.into_iter().enumerate() {
+        let entity_id = register_body(ctx, state, rigid_body, collider, shape)
+            .map_err(|e| format!("Failed to spawn sphere {}: {}", i, e))?;
+        entity_ids.push(entity_id);
+    }
+    Ok(entity_ids)
+}
+
+/// The 100 spheres of an exploding burst, flying apart in directions drawn from `seed`.
+pub(crate) fn exploding_sphere_parts(seed: u64) -> Result<Vec<(RigidBody, Collider, ShapeDesc)>, String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let explosion_speed = 20.0;
+    let mut spheres = Vec::with_capacity(100);
+
+    for _ in 0..100 {
+        // Generate random direction
+        let rand_x = rng.gen::<f32>() * 2.0 - 1.0;
+        let rand_y = rng.gen::<f32>() * 2.0 - 1.0;
+        let rand_z = rng.gen::<f32>() * 2.0 - 1.0;
+        // Use fully qualified path and new_normalize
+        let direction = rapier3d::na::Unit::new_normalize(Vector3::new(rand_x, rand_y, rand_z));
+            // .unwrap_or(Vector3::y_axis()); // new_normalize handles zero vectors
+
+        // Create rigid body at origin with initial velocity
+        let rigid_body = RigidBodyBuilder::dynamic()
+            .translation(Vector3::new(0.0, 1.0, 0.0)) // Start slightly above origin
+            .linvel(direction.into_inner() * explosion_speed)
+            .ccd_enabled(true) // Fast and small: would otherwise tunnel through the thin ground
+            .build();
+
+        // Collider with restitution
+        let shape = ShapeDesc::ball(0.2); // Smaller balls for explosion
+        let collider = shape
+            .collider_builder()?
+            .restitution(0.7)
+            .density(1.0) // Give them some mass
+            .build();
+        spheres.push((rigid_body, collider, shape));
+    }
+    Ok(spheres)
+}
+
+#[reducer]
+pub fn batch_spawn(
+    ctx: &ReducerContext,
+    world_id: u32,
+    xs: Vec<f64>,
+    ys: Vec<f64>,
+    zs: Vec<f64>,
+    radius: f32,
+) -> Result<(), String> {
+    info!("batch_spawn called with {} positions", xs.len());
+    let balls = batch_parts(&xs, &ys, &zs, radius)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    check_body_limit(state, balls.len())?;
+
+    // Reducers can't return values; clients see the new ids through the `Entity` table
+    let mut entity_ids = Vec::with_capacity(balls.len());
+    for (rigid_body, collider, shape) in balls {
+        entity_ids.push(register_body(ctx, state, rigid_body, collider, shape)?);
+    }
+    info!("  -> Batch spawned entities {:?}", entity_ids);
+    Ok(())
+}
+
+/// Builds one ball per coordinate triple, validating everything before any body exists.
+pub(crate) fn batch_parts(
+    xs: &[f64],
+    ys: &[f64],
+    zs: &[f64],
+    radius: f32,
+) -> Result<Vec<(RigidBody, Collider, ShapeDesc)>, String> {
+    if xs.is_empty() || xs.len() != ys.len() || xs.len() != zs.len() {
+        return Err("Coordinate arrays must be non-empty and of equal length".to_string());
+    }
+    if !xs.iter().chain(ys).chain(zs).all(|v| v.is_finite()) {
+        return Err("Coordinates must be finite".to_string());
+    }
+    let shape = ShapeDesc::ball(radius);
+    // Validates the radius before any body is created
+    shape.collider_builder()?;
+    xs.iter()
+        .zip(ys)
+        .zip(zs)
+        .map(|((x, y), z)| {
+            let rigid_body = RigidBodyBuilder::dynamic()
+                .translation(Vector3::new(*x as f32, *y as f32, *z as f32))
+                .build();
+            let collider = shape.collider_builder()?.restitution(0.7).build();
+            Ok((rigid_body, collider, shape.clone()))
+        })
+        .collect()
+}
+
+#[reducer]
+pub fn spawn_stack(
+    ctx: &ReducerContext,
+    world_id: u32,
+    base_x: f32,
+    base_y: f32,
+    base_z: f32,
+    count: u32,
+    spacing: f32,
+) -> Result<(), String> {
+    info!(
+        "spawn_stack called: {} boxes at ({}, {}, {}), spacing {}",
+        count, base_x, base_y, base_z, spacing
+    );
+    let boxes = stack_parts(Vector3::new(base_x, base_y, base_z), count, spacing)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    check_body_limit(state, boxes.len())?;
+
+    for (i, (rigid_body, collider, shape)) in boxes.into_iter().enumerate() {
+        register_body(ctx, state, rigid_body, collider, shape)
+            .map_err(|e| format!("Failed to spawn stack box {}: {}", i, e))?;
+    }
+    info!("  -> Spawned stack of {} boxes", count);
+    Ok(())
+}
+
+/// Bodies of a `spawn_stack` stack, bottom to top. Unit cubes, so a spacing of 1.0
+/// stacks them face to face.
+pub(crate) fn stack_parts(
+    base: Vector3<f32>,
+    count: u32,
+    spacing: f32,
+) -> Result<Vec<(RigidBody, Collider, ShapeDesc)>, String> {
+    if count == 0 {
+        return Err("Stack needs at least one box".to_string());
+    }
+    if !base.iter().all(|v| v.is_finite()) {
+        return Err("Stack base must be finite".to_string());
+    }
+    if !(spacing.is_finite() && spacing > 0.0) {
+        return Err("Spacing must be > 0".to_string());
+    }
+    let shape = ShapeDesc {
+        kind: ShapeKind::Cuboid,
+        radius: 0.0,
+        half_x: 0.5,
+        half_y: 0.5,
+        half_z: 0.5,
+    };
+    (0..count)
+        .map(|i| {
+            let rigid_body = RigidBodyBuilder::dynamic()
+                .translation(base + Vector3::new(0.0, i as f32 * spacing, 0.0))
+                .build();
+            let collider = shape.collider_builder()?.build();
+            Ok((rigid_body, collider, shape.clone()))
+        })
+        .collect()
+}
+
+/// Bodies of a `spawn_chain` chain, top to bottom (starting with the fixed anchor when
+/// `pinned`), and the joints linking each body to the next.
+pub(crate) fn chain_parts(
+    start: Vector3<f32>,
+    segments: u32,
+    segment_length: f32,
+    pinned: bool,
+) -> Result<(Vec<(RigidBody, Collider, ShapeDesc)>, Vec<GenericJoint>), String> {
+    if segments == 0 {
+        return Err("Chain needs at least one segment".to_string());
+    }
+    if !start.iter().all(|v| v.is_finite()) {
+        return Err("Chain start must be finite".to_string());
+    }
+    if !(segment_length.is_finite() && segment_length > 0.0) {
+        return Err("Segment length must be > 0".to_string());
+    }
+
+    // Segments hang straight down from the start point, each a Y-aligned capsule whose
+    // tips sit on the joint anchors
+    let half_length = segment_length / 2.0;
+    let radius = segment_length * 0.2;
+    let shape = ShapeDesc::capsule(half_length - radius, radius);
+    let top_anchor = Point::new(0.0, half_length, 0.0);
+    let bottom_anchor = Point::new(0.0, -half_length, 0.0);
+
+    let mut bodies = Vec::new();
+    let mut links = Vec::new();
+    let mut previous_anchor: Option<Point<Real>> = None;
+    if pinned {
+        let anchor_shape = ShapeDesc::ball(radius);
+        let anchor = RigidBodyBuilder::fixed().translation(start).build();
+        let collider = anchor_shape.collider_builder()?.build();
+        bodies.push((anchor, collider, anchor_shape));
+        previous_anchor = Some(Point::origin());
+    }
+    for i in 0..segments {
+        let center = start - Vector3::new(0.0, (i as f32 + 0.5) * segment_length, 0.0);
+        let rigid_body = RigidBodyBuilder::dynamic().translation(center).build();
+        let collider = shape.collider_builder()?.build();
+        bodies.push((rigid_body, collider, shape.clone()));
+
+        if let Some(previous_anchor) = previous_anchor {
+            // Linked neighbours overlap at the anchor, so they must not collide
+            let joint = SphericalJointBuilder::new()
+                .local_anchor1(previous_anchor)
+                .local_anchor2(top_anchor)
+                .contacts_enabled(false);
+            links.push(joint.build().into());
+        }
+        previous_anchor = Some(bottom_anchor);
+    }
+    Ok((bodies, links))
+}
+
+#[reducer]
+pub fn spawn_chain(
+    ctx: &ReducerContext,
+    world_id: u32,
+    start_x: f32,
+    start_y: f32,
+    start_z: f32,
+    segments: u32,
+    segment_length: f32,
+    pinned: bool,
+) -> Result<(), String> {
+    info!(
+        "spawn_chain called: {} segments of {} from ({}, {}, {}), pinned: {}",
+        segments, segment_length, start_x, start_y, start_z, pinned
+    );
+    let (bodies, links) = chain_parts(
+        Vector3::new(start_x, start_y, start_z),
+        segments,
+        segment_length,
+        pinned,
+    )?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    check_body_limit(state, bodies.len())?;
+
+    let mut entity_ids = Vec::with_capacity(bodies.len());
+    for (i, (rigid_body, collider, shape)) in bodies.into_iter().enumerate() {
+        let entity_id = register_body(ctx, state, rigid_body, collider, shape)
+            .map_err(|e| format!("Failed to spawn chain body {}: {}", i, e))?;
+        entity_ids.push(entity_id);
+    }
+    for (pair, joint) in entity_ids.windows(2).zip(links) {
+        register_joint(ctx, state, pair[0], pair[1], JointKind::Spherical, joint)?;
+    }
+    info!("  -> Spawned chain of {} segments", segments);
+    Ok(())
+}
+
+/// Bodies in a `spawn_ragdoll` ragdoll.
+const RAGDOLL_SEGMENTS: usize = 10;
+
+#[reducer]
+pub fn spawn_ragdoll(
+    ctx: &ReducerContext,
+    world_id: u32,
+    x: f32,
+    y: f32,
+    z: f32,
+) -> Result<(), String> {
+    info!("spawn_ragdoll called at ({}, {}, {})", x, y, z);
+    let (segments, joints) = ragdoll_parts(Vector3::new(x, y, z))?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    check_body_limit(state, segments.len())?;
+
+    let mut ids = Vec::with_capacity(segments.len());
+    for (i, (rigid_body, collider, shape)) in segments.into_iter().enumerate() {
+        let entity_id = register_body(ctx, state, rigid_body, collider, shape)
+            .map_err(|e| format!("Failed to spawn ragdoll segment {}: {}", i, e))?;
+        ids.push(entity_id);
+    }
+    for (parent, child, joint) in joints {
+        register_joint(ctx, state, ids[parent], ids[child], JointKind::Spherical, joint)?;
+    }
+    info!("  -> Spawned ragdoll with torso entity {}", ids[0]);
+    Ok(())
+}
+
+/// Torso, head and two-part limbs of a ragdoll centered on `origin`, plus the spherical
+/// joints between them as (parent index, child index, joint). The torso comes first.
+pub(crate) fn ragdoll_parts(
+    origin: Vector3<f32>,
+) -> Result<(Vec<(RigidBody, Collider, ShapeDesc)>, Vec<(usize, usize, GenericJoint)>), String> {
+    if !origin.iter().all(|v| v.is_finite()) {
+        return Err("Ragdoll position must be finite".to_string());
+    }
+    // Roughly human proportions around the torso center at `origin`, feet 1.42 below it.
+    // Limbs are Y-aligned capsules whose tips sit on the joints.
+    let torso = ShapeDesc {
+        kind: ShapeKind::Cuboid,
+        radius: 0.0,
+        half_x: 0.2,
+        half_y: 0.3,
+        half_z: 0.12,
+    };
+    let arm = ShapeDesc::capsule(0.15, 0.06); // 0.42 tip to tip
+    let leg = ShapeDesc::capsule(0.2, 0.08); // 0.56 tip to tip
+    // (shape, center, Some((parent index, joint position, angular limit in radians)))
+    let segments: [_; RAGDOLL_SEGMENTS] = [
+        (torso, Vector3::zeros(), None),
+        // Head on the neck
+        (
+            ShapeDesc::ball(0.12),
+            Vector3::new(0.0, 0.45, 0.0),
+            Some((0, Vector3::new(0.0, 0.33, 0.0), 0.6)),
+        ),
+        // Left upper arm on the shoulder, forearm on the elbow
+        (
+            arm.clone(),
+            Vector3::new(-0.28, 0.06, 0.0),
+            Some((0, Vector3::new(-0.28, 0.27, 0.0), 1.5)),
+        ),
+        (
+            arm.clone(),
+            Vector3::new(-0.28, -0.36, 0.0),
+            Some((2, Vector3::new(-0.28, -0.15, 0.0), 1.2)),
+        ),
+        // Right arm
+        (
+            arm.clone(),
+            Vector3::new(0.28, 0.06, 0.0),
+            Some((0, Vector3::new(0.28, 0.27, 0.0), 1.5)),
+        ),
+        (
+            arm,
+            Vector3::new(0.28, -0.36, 0.0),
+            Some((4, Vector3::new(0.28, -0.15, 0.0), 1.2)),
+        ),
+        // Left thigh on the hip, shin on the knee
+        (
+            leg.clone(),
+            Vector3::new(-0.1, -0.58, 0.0),
+            Some((0, Vector3::new(-0.1, -0.3, 0.0), 1.2)),
+        ),
+        (
+            leg.clone(),
+            Vector3::new(-0.1, -1.14, 0.0),
+            Some((6, Vector3::new(-0.1, -0.86, 0.0), 1.2)),
+        ),
+        // Right leg
+        (
+            leg.clone(),
+            Vector3::new(0.1, -0.58, 0.0),
+            Some((0, Vector3::new(0.1, -0.3, 0.0), 1.2)),
+        ),
+        (
+            leg,
+            Vector3::new(0.1, -1.14, 0.0),
+            Some((8, Vector3::new(0.1, -0.86, 0.0), 1.2)),
+        ),
+    ];
+
+    let centers: Vec<Vector3<f32>> = segments.iter().map(|(_, center, _)| *center).collect();
+    let mut bodies = Vec::with_capacity(RAGDOLL_SEGMENTS);
+    let mut joints = Vec::with_capacity(RAGDOLL_SEGMENTS - 1);
+    for (i, (shape, center, parent)) in segments.into_iter().enumerate() {
+        let rigid_body = RigidBodyBuilder::dynamic()
+            .translation(origin + center)
+            .build();
+        let collider = shape.collider_builder()?.build();
+        bodies.push((rigid_body, collider, shape));
+
+        if let Some((parent_index, joint_position, limit)) = parent {
+            let parent_center = centers[parent_index];
+            // Jointed segments touch at the joint, so they must not collide
+            let joint = SphericalJointBuilder::new()
+                .local_anchor1(Point::from(joint_position - parent_center))
+                .local_anchor2(Point::from(joint_position - center))
+                .limits(JointAxis::AngX, [-limit, limit])
+                .limits(JointAxis::AngY, [-limit, limit])
+                .limits(JointAxis::AngZ, [-limit, limit])
+                .contacts_enabled(false);
+            joints.push((parent_index, i, joint.build().into()));
+        }
+    }
+    Ok((bodies, joints))
+}
+
+#[reducer]
+pub fn spawn_shape(
+    ctx: &ReducerContext,
+    world_id: u32,
+    x: f32,
+    y: f32,
+    z: f32,
+    shape: ShapeDesc,
+    options: SpawnOptions,
+) -> Result<(), String> {
+    info!("spawn_shape called: {:?} at ({}, {}, {})", shape.kind, x, y, z);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    check_body_limit(state, 1)?;
+
+    let rigid_body = options
+        .apply_to_body(RigidBodyBuilder::dynamic())?
+        .translation(Vector3::new(x, y, z))
+        .build();
+    let collider = options
+        .apply_to_collider(shape.collider_builder()?.restitution(0.7))?
+        .build();
+
+    let entity_id = register_body(ctx, state, rigid_body, collider, shape)?;
+    options.apply_to_entity(ctx, state, entity_id)?;
+    info!("  -> Spawned shape entity {}", entity_id);
+    Ok(())
+}
+
+#[reducer]
+pub fn spawn_heightfield(
+    ctx: &ReducerContext,
+    world_id: u32,
+    rows: u32,
+    cols: u32,
+    heights: Vec<f32>,
+    scale_x: f32,
+    scale_y: f32,
+    scale_z: f32,
+) -> Result<(), String> {
+    info!("spawn_heightfield called: {}x{} samples", rows, cols);
+    let collider =
+        heightfield_collider(rows, cols, &heights, Vector3::new(scale_x, scale_y, scale_z))?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    check_body_limit(state, 1)?;
+
+    let rigid_body = RigidBodyBuilder::fixed().build();
+    let shape = ShapeDesc {
+        kind: ShapeKind::Heightfield,
+        radius: 0.0,
+        half_x: scale_x,
+        half_y: scale_y,
+        half_z: scale_z,
+    };
+
+    let entity_id = register_body(ctx, state, rigid_body, collider, shape)?;
+    info!("  -> Spawned heightfield entity {}", entity_id);
+    Ok(())
+}
+
+#[reducer]
+pub fn spawn_trimesh(
+    ctx: &ReducerContext,
+    world_id: u32,
+    vertices: Vec<f32>,
+    indices: Vec<u32>,
+) -> Result<(), String> {
+    info!(
+        "spawn_trimesh called: {} vertices, {} triangles",
+        vertices.len() / 3,
+        indices.len() / 3
+    );
+    let collider = trimesh_collider(&vertices, &indices)?;
+
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    check_body_limit(state, 1)?;
+
+    // Vertices are in world space, so the fixed body sits at the origin
+    let rigid_body = RigidBodyBuilder::fixed().build();
+
+    let entity_id = register_body(
+        ctx,
+        state,
+        rigid_body,
+        collider,
+        ShapeDesc::kind_only(ShapeKind::Trimesh),
+    )?;
+    info!("  -> Spawned trimesh entity {}", entity_id);
+    Ok(())
+}
+
+#[reducer]
+pub fn spawn_convex_hull(
+    ctx: &ReducerContext,
+    world_id: u32,
+    x: f32,
+    y: f32,
+    z: f32,
+    points: Vec<f32>,
+) -> Result<(), String> {
+    info!("spawn_convex_hull called at ({}, {}, {}) with {} points", x, y, z, points.len() / 3);
+    let collider = convex_hull_collider(&points)?;
+
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    check_body_limit(state, 1)?;
+
+    // Points are relative to the body origin
+    let rigid_body = RigidBodyBuilder::dynamic()
+        .translation(Vector3::new(x, y, z))
+        .build();
+
+    let entity_id = register_body(
+        ctx,
+        state,
+        rigid_body,
+        collider,
+        ShapeDesc::kind_only(ShapeKind::ConvexHull),
+    )?;
+    info!("  -> Spawned convex hull entity {}", entity_id);
+    Ok(())
+}
+
+#[reducer]
+pub fn spawn_trigger_zone(
+    ctx: &ReducerContext,
+    world_id: u32,
+    x: f32,
+    y: f32,
+    z: f32,
+    hx: f32,
+    hy: f32,
+    hz: f32,
+) -> Result<(), String> {
+    info!("spawn_trigger_zone called at ({}, {}, {}), half extents ({}, {}, {})", x, y, z, hx, hy, hz);
+    if ![x, y, z].iter().all(|v| v.is_finite()) {
+        return Err("Zone position must be finite".to_string());
+    }
+    let shape = ShapeDesc {
+        kind: ShapeKind::Cuboid,
+        radius: 0.0,
+        half_x: hx,
+        half_y: hy,
+        half_z: hz,
+    };
+    // Sensors report intersections without pushing anything
+    let collider = shape
+        .collider_builder()?
+        .sensor(true)
+        .active_events(ActiveEvents::COLLISION_EVENTS)
+        .build();
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    check_body_limit(state, 1)?;
+
+    let rigid_body = RigidBodyBuilder::fixed()
+        .translation(Vector3::new(x, y, z))
+        .build();
+    let zone_id = register_body(ctx, state, rigid_body, collider, shape)?;
+    ctx.db
+        .trigger_state()
+        .try_insert(TriggerState {
+            zone_id,
+            world_id,
+            entities_inside: Vec::new(),
+            enter_count: 0,
+        })
+        .map_err(|e| e.to_string())?;
+    info!("  -> Spawned trigger zone {}", zone_id);
+    Ok(())
+}
+
+#[reducer]
+pub fn spawn_character(
+    ctx: &ReducerContext,
+    world_id: u32,
+    x: f32,
+    y: f32,
+    z: f32,
+) -> Result<(), String> {
+    info!("spawn_character called at ({}, {}, {})", x, y, z);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    check_body_limit(state, 1)?;
+
+    // Kinematic so only `move_character` moves it; it still pushes dynamic bodies aside
+    let rigid_body = RigidBodyBuilder::kinematic_position_based()
+        .translation(Vector3::new(x, y, z))
+        .build();
+    let shape = ShapeDesc::capsule(0.5, 0.3);
+    let collider = shape.collider_builder()?.build();
+
+    let entity_id = register_body(ctx, state, rigid_body, collider, shape)?;
+    ctx.db
+        .entity_character_state()
+        .try_insert(EntityCharacterState {
+            entity_id,
+            grounded: false,
+            sliding_down_slope: false,
+        })
+        .map_err(|e| e.to_string())?;
+    info!("  -> Spawned character entity {}", entity_id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ball_rolls_down_a_sloped_heightfield() {
+        let mut state = PhysicsState::new(0);
+        // Two columns rising from 0 to 4 along X over 20m
+        let heightfield =
+            heightfield_collider(2, 2, &[0.0, 4.0, 0.0, 4.0], Vector3::new(20.0, 1.0, 20.0))
+                .unwrap();
+        state.collider_set.insert(heightfield);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 4.0, 0.0));
+        for _ in 0..90 {
+            state.step_once();
+        }
+        let position = state.rigid_body_set[rb_handle].translation();
+        assert!(position.x < -0.5, "ball ended at {:?}", position);
+        assert!(position.z.abs() < 0.1);
+    }
+
+    #[test]
+    fn heightfield_needs_one_height_per_sample() {
+        let scale = Vector3::new(1.0, 1.0, 1.0);
+        assert!(heightfield_collider(2, 3, &[0.0; 5], scale).is_err());
+        assert!(heightfield_collider(1, 4, &[0.0; 4], scale).is_err());
+        assert!(heightfield_collider(2, 2, &[0.0; 4], Vector3::new(1.0, 0.0, 1.0)).is_err());
+        assert!(heightfield_collider(2, 2, &[0.0; 4], scale).is_ok());
+    }
+
+    /// Corners of a unit tetrahedron lifted 5m above the ground, and its four faces.
+    const TETRAHEDRON_VERTICES: [f32; 12] =
+        [0.0, 5.0, 0.0, 1.0, 5.0, 0.0, 0.0, 6.0, 0.0, 0.0, 5.0, 1.0];
+    const TETRAHEDRON_INDICES: [u32; 12] = [0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3];
+
+    #[test]
+    fn ray_hits_the_slanted_face_of_a_trimesh_tetrahedron() {
+        let mut state = PhysicsState::new(0);
+        let co_handle = state
+            .collider_set
+            .insert(trimesh_collider(&TETRAHEDRON_VERTICES, &TETRAHEDRON_INDICES).unwrap());
+        state
+            .query_pipeline
+            .update(&state.rigid_body_set, &state.collider_set);
+
+        // Straight down onto the x + (y - 5) + z = 1 face
+        let ray = Ray::new(Point::new(0.2, 10.0, 0.2), Vector3::new(0.0, -1.0, 0.0));
+        let (hit, toi) = state
+            .query_pipeline
+            .cast_ray(
+                &state.rigid_body_set,
+                &state.collider_set,
+                &ray,
+                100.0,
+                true,
+                QueryFilter::default(),
+            )
+            .unwrap();
+        assert_eq!(hit, co_handle);
+        assert!((toi - 4.4).abs() < 1.0e-4, "hit at toi {}", toi);
+    }
+
+    #[test]
+    fn trimesh_indices_must_be_triangles_in_range() {
+        assert!(trimesh_collider(&TETRAHEDRON_VERTICES, &[0, 1]).is_err());
+        assert!(trimesh_collider(&TETRAHEDRON_VERTICES, &[0, 1, 4]).is_err());
+        assert!(trimesh_collider(&TETRAHEDRON_VERTICES[..11], &TETRAHEDRON_INDICES).is_err());
+    }
+
+    #[test]
+    fn cube_hull_comes_to_rest_on_the_ground() {
+        let corners: Vec<f32> = (0..8)
+            .flat_map(|i| {
+                let corner = |bit: i32| if i & bit == 0 { -0.5 } else { 0.5 };
+                [corner(1), corner(2), corner(4)]
+            })
+            .collect();
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.rigid_body_set.insert(
+            RigidBodyBuilder::dynamic()
+                .translation(Vector3::new(0.0, 1.0, 0.0))
+                .build(),
+        );
+        state.collider_set.insert_with_parent(
+            convex_hull_collider(&corners).unwrap(),
+            rb_handle,
+            &mut state.rigid_body_set,
+        );
+        for _ in 0..240 {
+            state.step_once();
+        }
+
+        // Flat on the ground: bottom face on its top at y = 0.1, not tipped over
+        let body = &state.rigid_body_set[rb_handle];
+        assert!(
+            (body.translation().y - 0.6).abs() < 0.02,
+            "rests at {}",
+            body.translation().y
+        );
+        assert!(body.rotation().angle() < 0.01);
+        assert!(body.linvel().norm() < 0.05);
+    }
+
+    #[test]
+    fn hull_points_must_be_finite_triples() {
+        assert!(convex_hull_collider(&[]).is_err());
+        assert!(convex_hull_collider(&[0.0, 1.0, 2.0, 3.0]).is_err());
+        assert!(convex_hull_collider(&[0.0, f32::NAN, 0.0]).is_err());
+    }
+
+    /// Inserts generated parts into the world, returning their body handles in order.
+    fn insert_parts(
+        state: &mut PhysicsState,
+        parts: Vec<(RigidBody, Collider, ShapeDesc)>,
+    ) -> Vec<RigidBodyHandle> {
+        parts
+            .into_iter()
+            .map(|(rigid_body, collider, _)| {
+                let rb_handle = state.rigid_body_set.insert(rigid_body);
+                state.collider_set.insert_with_parent(
+                    collider,
+                    rb_handle,
+                    &mut state.rigid_body_set,
+                );
+                rb_handle
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stack_of_ten_stays_stacked_after_settling() {
+        let mut state = PhysicsState::new(0);
+        let base = Vector3::new(2.0, 0.6, -3.0);
+        let boxes = insert_parts(&mut state, stack_parts(base, 10, 1.0).unwrap());
+        for _ in 0..300 {
+            state.step_once();
+        }
+
+        for (i, handle) in boxes.iter().enumerate() {
+            let position = state.rigid_body_set[*handle].translation();
+            assert!((position.x - base.x).abs() < 0.1 && (position.z - base.z).abs() < 0.1);
+            assert!(
+                (position.y - (base.y + i as f32)).abs() < 0.1,
+                "box {} at {:?}",
+                i,
+                position
+            );
+        }
+    }
+
+    #[test]
+    fn stack_needs_boxes_and_positive_spacing() {
+        assert!(stack_parts(Vector3::zeros(), 0, 1.0).is_err());
+        assert!(stack_parts(Vector3::zeros(), 3, 0.0).is_err());
+        assert!(stack_parts(Vector3::new(f32::NAN, 0.0, 0.0), 3, 1.0).is_err());
+    }
+
+    #[test]
+    fn pinned_chain_hangs_below_its_anchor() {
+        let mut state = PhysicsState::new(0);
+        let start = Vector3::new(0.0, 10.0, 0.0);
+        let (bodies, links) = chain_parts(start, 4, 1.0, true).unwrap();
+        assert_eq!((bodies.len(), links.len()), (5, 4));
+        let handles = insert_parts(&mut state, bodies);
+        for (pair, joint) in handles.windows(2).zip(links) {
+            state
+                .impulse_joint_set
+                .insert(pair[0], pair[1], joint, true);
+        }
+        // Knock the bottom segment sideways so the chain has to swing back
+        state.rigid_body_set[handles[4]].set_linvel(Vector3::new(2.0, 0.0, 0.0), true);
+        for _ in 0..300 {
+            state.step_once();
+        }
+
+        assert_eq!(*state.rigid_body_set[handles[0]].translation(), start);
+        let top = state.rigid_body_set[handles[1]].translation();
+        let bottom = state.rigid_body_set[handles[4]].translation();
+        assert!(bottom.y < top.y);
+        assert!(bottom.y < start.y - 2.5, "bottom segment at {:?}", bottom);
+        assert!(bottom.y > start.y - 4.2, "chain stretched to {:?}", bottom);
+    }
+
+    #[test]
+    fn batch_of_fifty_balls_lands_where_asked() {
+        let xs: Vec<f64> = (0..50).map(|i| (i % 10) as f64 * 2.0).collect();
+        let ys = vec![5.0; 50];
+        let zs: Vec<f64> = (0..50).map(|i| (i / 10) as f64 * 2.0).collect();
+        let mut state = PhysicsState::new(0);
+        let balls = insert_parts(&mut state, batch_parts(&xs, &ys, &zs, 0.5).unwrap());
+
+        assert_eq!(balls.len(), 50);
+        assert_eq!(state.rigid_body_set.len(), 50);
+        for (i, handle) in balls.iter().enumerate() {
+            let position = state.rigid_body_set[*handle].translation();
+            assert_eq!((position.x, position.z), (xs[i] as f32, zs[i] as f32));
+        }
+    }
+
+    #[test]
+    fn batch_arrays_must_match_and_be_finite() {
+        assert!(batch_parts(&[], &[], &[], 0.5).is_err());
+        assert!(batch_parts(&[0.0, 1.0], &[0.0], &[0.0, 1.0], 0.5).is_err());
+        assert!(batch_parts(&[0.0], &[f64::INFINITY], &[0.0], 0.5).is_err());
+        assert!(batch_parts(&[0.0], &[0.0], &[0.0], 0.0).is_err());
+    }
+
+    /// Runs a seeded burst for two seconds in a fresh world and returns every sphere's pose.
+    fn burst_poses(seed: u64) -> Vec<Isometry<Real>> {
+        let mut state = PhysicsState::new(0);
+        let spheres = insert_parts(&mut state, exploding_sphere_parts(seed).unwrap());
+        for _ in 0..120 {
+            state.step_once();
+        }
+        spheres
+            .iter()
+            .map(|handle| *state.rigid_body_set[*handle].position())
+            .collect()
+    }
+
+    #[test]
+    fn seeded_burst_replays_bit_for_bit() {
+        let seed = deterministic_seed(0, 0, 0);
+        let first = burst_poses(seed);
+        let second = burst_poses(seed);
+        assert_eq!(first.len(), 100);
+        assert_eq!(first, second);
+
+        // Another call in the same tick flies apart differently
+        assert_ne!(burst_poses(deterministic_seed(0, 0, 1)), first);
+    }
+
+    #[test]
+    fn dropped_ragdoll_comes_to_rest_in_one_piece() {
+        let mut state = PhysicsState::new(0);
+        let (segments, joints) = ragdoll_parts(Vector3::new(0.0, 4.0, 0.0)).unwrap();
+        assert_eq!(segments.len(), RAGDOLL_SEGMENTS);
+        assert_eq!(joints.len(), RAGDOLL_SEGMENTS - 1);
+        let handles = insert_parts(&mut state, segments);
+        for (parent, child, joint) in joints {
+            state
+                .impulse_joint_set
+                .insert(handles[parent], handles[child], joint, true);
+        }
+        for _ in 0..360 {
+            state.step_once();
+        }
+
+        let torso = *state.rigid_body_set[handles[0]].translation();
+        for handle in &handles {
+            let segment = &state.rigid_body_set[*handle];
+            // On the ground, within reach of the torso, and settled
+            assert!(segment.translation().y > 0.0 && segment.translation().y < 1.0);
+            assert!((segment.translation() - torso).norm() < 1.5);
+            assert!(
+                segment.linvel().norm() < 0.1,
+                "segment still moving at {}",
+                segment.linvel().norm()
+            );
+        }
+    }
+
+    #[test]
+    fn ragdoll_position_must_be_finite() {
+        assert!(ragdoll_parts(Vector3::new(0.0, f32::NAN, 0.0)).is_err());
+    }
+
+    #[test]
+    fn body_limit_rejects_spawns_past_the_cap_and_the_world_keeps_stepping() {
+        let mut state = PhysicsState::new(0);
+        state.max_bodies = 3;
+        state.add_test_ball(Vector3::new(0.0, 3.0, 0.0));
+        state.add_test_ball(Vector3::new(3.0, 3.0, 0.0));
+        assert!(check_body_limit(&state, 1).is_ok());
+        assert_eq!(
+            check_body_limit(&state, 2),
+            Err("body limit reached".to_string())
+        );
+
+        state.step_once();
+        assert_eq!(state.rigid_body_set.len(), 2);
+        assert!(state
+            .rigid_body_set
+            .iter()
+            .all(|(_, body)| body.translation().y < 3.0));
+    }
+}
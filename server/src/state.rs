@@ -0,0 +1,895 @@
+//! Simulation worlds and the helpers every reducer uses to reach them.
+
+use log::warn;
+use once_cell::sync::Lazy;
+use rapier3d::na::Vector3;
+use rapier3d::prelude::BroadPhaseMultiSap;
+use rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+use spacetimedb::{ReducerContext, ScheduleAt, Table};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::tables::*;
+use crate::tick::GRAVITY;
+use crate::types::{JointKind, Vec3};
+
+/// One independent simulation. Worlds are keyed by id in `PHYSICS_STATE` and each
+/// one is stepped by its own `PhysicsTickTimer` row.
+pub(crate) struct PhysicsState {
+    pub(crate) world_id: u32,
+    pub(crate) rigid_body_set: RigidBodySet,
+    pub(crate) collider_set: ColliderSet,
+    pub(crate) integration_parameters: IntegrationParameters,
+    pub(crate) physics_pipeline: PhysicsPipeline,
+    pub(crate) island_manager: IslandManager,
+    pub(crate) broad_phase: BroadPhaseMultiSap, // Use concrete type
+    pub(crate) narrow_phase: NarrowPhase,
+    pub(crate) impulse_joint_set: ImpulseJointSet,
+    pub(crate) multibody_joint_set: MultibodyJointSet,
+    pub(crate) ccd_solver: CCDSolver,
+    pub(crate) query_pipeline: QueryPipeline,
+    pub(crate) handle_to_entity_id: HashMap<RigidBodyHandle, u32>,
+    pub(crate) collider_to_entity_id: HashMap<ColliderHandle, u32>,
+    pub(crate) tick: u64, // Number of processed physics ticks
+    pub(crate) max_bodies: usize, // Spawns fail once rigid_body_set would grow past this
+    pub(crate) wind: Vector3<f32>, // Global force applied to every dynamic body each tick
+    pub(crate) sleep_thresholds: Option<(f32, f32)>, // (linear, angular) overrides; None keeps rapier's defaults
+    pub(crate) time_scale: f32, // Multiplier on dt for each step (slow motion < 1 < fast forward)
+    pub(crate) last_tick_micros: Option<i64>, // ctx.timestamp of the previous tick, for the accumulator
+    pub(crate) time_accumulator_micros: i64, // Real time not yet consumed by fixed-dt steps
+    pub(crate) max_velocity: Option<f32>, // Linear speed cap enforced after stepping
+    pub(crate) contact_force_threshold: f32, // Minimum total contact force that gets recorded
+    pub(crate) hooks: WorldHooks, // Per-collider contact tweaks passed to `step`
+    pub(crate) deterministic: bool, // Exactly one step per tick and seeded spawns, see `set_deterministic`
+    pub(crate) collision_logging: HashSet<u32>, // Entities opted in to `CollisionLog`, see `set_collision_events`
+    pub(crate) seeded_spawns: u64, // `spawn_exploding_spheres` bursts so far, mixed into deterministic seeds
+    pub(crate) kill_plane_y: Option<f64>, // Dynamic bodies below this height are despawned each tick
+    pub(crate) recording: bool, // Log state-mutating calls to `ReducerLog`, see `set_recording`
+    pub(crate) radial_gravity_center: Option<Vector3<f32>>, // Replaces uniform gravity when set
+    pub(crate) radial_gravity_strength: f32, // Acceleration toward `radial_gravity_center` (m/s²)
+    pub(crate) awake_ticks: HashMap<RigidBodyHandle, u32>, // Consecutive ticks each dynamic body has been awake, see `nudge_stuck_bodies`
+}
+
+/// Default for `set_contact_force_threshold`; high enough to ignore resting contacts.
+pub(crate) const DEFAULT_CONTACT_FORCE_THRESHOLD: f32 = 100.0;
+
+/// Contact modifications for tagged colliders. Colliders opt in through their
+/// `ActiveHooks` flags, so untagged contacts never reach these callbacks.
+#[derive(Default, Serialize, Deserialize)]
+pub(crate) struct WorldHooks {
+    /// Surface velocity (world space) of conveyor colliders.
+    pub(crate) conveyors: HashMap<ColliderHandle, Vector3<f32>>,
+    /// Platforms that bodies pass through from below but land on from above
+    /// (along the collider's local +Y).
+    pub(crate) one_way_platforms: HashSet<ColliderHandle>,
+    /// Direction-dependent friction of collider surfaces.
+    pub(crate) friction_axes: HashMap<ColliderHandle, FrictionAxis>,
+    /// Flags requested with `set_active_hooks` or `SpawnOptions::active_hooks`, kept on
+    /// top of the ones the registrations above need.
+    pub(crate) requested: HashMap<ColliderHandle, ActiveHooks>,
+}
+
+/// Friction that differs along a preferred surface axis and across it (skis, ice grooves).
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct FrictionAxis {
+    /// Preferred axis in the collider's local frame, normalized.
+    pub(crate) axis: Vector3<f32>,
+    pub(crate) along: f32,
+    pub(crate) across: f32,
+}
+
+/// Max angle (radians) between a contact normal and a one-way platform's up axis for
+/// the contact to be kept.
+pub(crate) const ONE_WAY_ALLOWED_ANGLE: f32 = 0.1;
+
+impl WorldHooks {
+    /// Forgets every hook attached to `co_handle`, e.g. when the collider is removed
+    /// (its handle may be reused by a later collider).
+    pub(crate) fn remove_collider(&mut self, co_handle: ColliderHandle) {
+        self.conveyors.remove(&co_handle);
+        self.one_way_platforms.remove(&co_handle);
+        self.friction_axes.remove(&co_handle);
+        self.requested.remove(&co_handle);
+    }
+
+    /// Moves every hook from `old` to `new`, e.g. when a collider is rebuilt.
+    pub(crate) fn replace_collider(&mut self, old: ColliderHandle, new: ColliderHandle) {
+        if let Some(velocity) = self.conveyors.remove(&old) {
+            self.conveyors.insert(new, velocity);
+        }
+        if self.one_way_platforms.remove(&old) {
+            self.one_way_platforms.insert(new);
+        }
+        if let Some(friction_axis) = self.friction_axes.remove(&old) {
+            self.friction_axes.insert(new, friction_axis);
+        }
+        if let Some(flags) = self.requested.remove(&old) {
+            self.requested.insert(new, flags);
+        }
+    }
+
+    /// Sets the collider's hook flags to what its registrations need plus the flags
+    /// requested for it, so neither side can switch off the other's.
+    pub(crate) fn sync_active_hooks(&self, co_handle: ColliderHandle, collider: &mut Collider) {
+        let mut active_hooks = self
+            .requested
+            .get(&co_handle)
+            .copied()
+            .unwrap_or(ActiveHooks::empty());
+        if self.conveyors.contains_key(&co_handle)
+            || self.one_way_platforms.contains(&co_handle)
+            || self.friction_axes.contains_key(&co_handle)
+        {
+            active_hooks |= ActiveHooks::MODIFY_SOLVER_CONTACTS;
+        }
+        collider.set_active_hooks(active_hooks);
+    }
+
+    /// Replaces the contact friction with an elliptic blend of the surface's along/across
+    /// coefficients, weighted by the direction the bodies slide relative to each other.
+    /// Bodies at rest get the lower of the two, so a push in the easy direction starts
+    /// them sliding; a sideways slide then meets the full cross friction.
+    fn apply_friction_axis(&self, context: &mut ContactModificationContext) {
+        let Some((co_handle, friction_axis)) = [context.collider1, context.collider2]
+            .into_iter()
+            .find_map(|handle| Some((handle, self.friction_axes.get(&handle)?)))
+        else {
+            return;
+        };
+        let Some(collider) = context.colliders.get(co_handle) else {
+            return;
+        };
+        let normal = *context.normal;
+        let axis = collider.position().rotation * friction_axis.axis;
+        // A surface normal parallel to the axis leaves no preferred direction on it
+        let Some(axis) = (axis - normal * axis.dot(&normal)).try_normalize(1.0e-6) else {
+            return;
+        };
+        let velocity_at = |handle: Option<RigidBodyHandle>, point: &Point<Real>| {
+            handle
+                .and_then(|handle| context.bodies.get(handle))
+                .map_or_else(Vector3::zeros, |body| body.velocity_at_point(point))
+        };
+        for solver_contact in context.solver_contacts.iter_mut() {
+            let point = solver_contact.point;
+            let slip =
+                velocity_at(context.rigid_body2, &point) - velocity_at(context.rigid_body1, &point);
+            let slip = slip - normal * slip.dot(&normal);
+            solver_contact.friction = match slip.try_normalize(1.0e-3) {
+                Some(direction) => {
+                    let cos_sq = direction.dot(&axis).powi(2);
+                    (friction_axis.along.powi(2) * cos_sq
+                        + friction_axis.across.powi(2) * (1.0 - cos_sq))
+                        .sqrt()
+                }
+                None => friction_axis.along.min(friction_axis.across),
+            };
+        }
+    }
+}
+
+impl PhysicsHooks for WorldHooks {
+    // The pair filters keep the trait's defaults (keep every pair); colliders flagged
+    // for them through `set_active_hooks` only pay for the callback.
+    fn modify_solver_contacts(&self, context: &mut ContactModificationContext) {
+        // Normals point from collider 1 to collider 2, so a platform on either side
+        // allows contacts pushing the other body along its local up axis
+        if self.one_way_platforms.contains(&context.collider1) {
+            context.update_as_oneway_platform(&Vector3::y(), ONE_WAY_ALLOWED_ANGLE);
+        } else if self.one_way_platforms.contains(&context.collider2) {
+            context.update_as_oneway_platform(&-Vector3::y(), ONE_WAY_ALLOWED_ANGLE);
+        }
+
+        self.apply_friction_axis(context);
+
+        // The solver drives body 2's tangential velocity towards body 1's plus
+        // `tangent_velocity`, so the sign depends on which side the conveyor is on
+        let surface_velocity = if let Some(velocity) = self.conveyors.get(&context.collider1) {
+            *velocity
+        } else if let Some(velocity) = self.conveyors.get(&context.collider2) {
+            -*velocity
+        } else {
+            return;
+        };
+        for solver_contact in context.solver_contacts.iter_mut() {
+            solver_contact.tangent_velocity = surface_velocity;
+        }
+    }
+}
+
+/// Default cap on live rigid bodies, see `set_max_bodies`.
+pub(crate) const DEFAULT_MAX_BODIES: usize = 20_000;
+
+/// Tick interval (and matching `dt`) of new worlds, see `set_tick_interval`.
+pub(crate) const DEFAULT_TICK_INTERVAL_MS: u64 = 16;
+
+/// World created by `init_physics`.
+pub(crate) const DEFAULT_WORLD_ID: u32 = 0;
+
+impl PhysicsState {
+    /// Fresh world with the default tuning and a ground plane.
+    pub(crate) fn new(world_id: u32) -> Self {
+        let mut state = PhysicsState {
+            world_id,
+            rigid_body_set: RigidBodySet::new(),
+            collider_set: ColliderSet::new(),
+            integration_parameters: IntegrationParameters::default(),
+            physics_pipeline: PhysicsPipeline::new(),
+            island_manager: IslandManager::new(),
+            broad_phase: BroadPhaseMultiSap::new(), // Initialize concrete type
+            narrow_phase: NarrowPhase::new(),
+            impulse_joint_set: ImpulseJointSet::new(),
+            multibody_joint_set: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            query_pipeline: QueryPipeline::new(),
+            handle_to_entity_id: HashMap::new(),
+            collider_to_entity_id: HashMap::new(),
+            tick: 0,
+            max_bodies: DEFAULT_MAX_BODIES,
+            wind: Vector3::zeros(),
+            sleep_thresholds: None,
+            time_scale: 1.0,
+            last_tick_micros: None,
+            time_accumulator_micros: 0,
+            max_velocity: None,
+            contact_force_threshold: DEFAULT_CONTACT_FORCE_THRESHOLD,
+            hooks: WorldHooks::default(),
+            deterministic: false,
+            collision_logging: HashSet::new(),
+            seeded_spawns: 0,
+            kill_plane_y: None,
+            recording: false,
+            radial_gravity_center: None,
+            radial_gravity_strength: GRAVITY,
+            awake_ticks: HashMap::new(),
+        };
+
+        // Explicitly set the integration timestep (dt) to match the tick interval
+        state.integration_parameters.dt = DEFAULT_TICK_INTERVAL_MS as f32 / 1000.0;
+        // Increase solver iterations
+        if let Some(iterations) = std::num::NonZeroUsize::new(10) {
+            state.integration_parameters.num_solver_iterations = iterations;
+        }
+        // Explicitly set damping_ratio to 0.0
+        state.integration_parameters.damping_ratio = 0.0;
+
+        let mut ground_collider = ColliderBuilder::cuboid(100.0, 0.1, 100.0).build();
+        enable_contact_force_events(&mut ground_collider, state.contact_force_threshold);
+        state.collider_set.insert(ground_collider);
+        state
+    }
+}
+
+/// All simulation worlds by id. Access it through `lock_state`.
+pub(crate) static PHYSICS_STATE: Lazy<Mutex<HashMap<u32, PhysicsState>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Locks the world map, recovering from poisoning. A reducer that panics while holding
+/// the lock would otherwise make every later reducer fail. The worlds may be left
+/// mid-update by the panic, but that beats bricking physics for good.
+pub(crate) fn lock_state() -> MutexGuard<'static, HashMap<u32, PhysicsState>> {
+    PHYSICS_STATE.lock().unwrap_or_else(|poisoned| {
+        warn!("PHYSICS_STATE lock was poisoned by a panic; recovering");
+        PHYSICS_STATE.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+// --- Helper Functions ---
+
+/// Makes the collider report contacts whose total force exceeds `threshold`.
+pub(crate) fn enable_contact_force_events(collider: &mut Collider, threshold: f32) {
+    collider.set_active_events(collider.active_events() | ActiveEvents::CONTACT_FORCE_EVENTS);
+    collider.set_contact_force_event_threshold(threshold);
+}
+
+/// Sets `COLLISION_EVENTS` on the entity's colliders if anything needs its collisions:
+/// `CollisionLog` (opted in with `set_collision_events`) or a collision trigger. The flag
+/// is derived from both, so turning one off leaves the other working. Sensors are
+/// skipped; trigger zones always need their events.
+pub(crate) fn sync_collision_events(
+    ctx: &ReducerContext,
+    state: &mut PhysicsState,
+    entity_id: u32,
+    rb_handle: RigidBodyHandle,
+) -> Result<(), String> {
+    let wanted = state.collision_logging.contains(&entity_id)
+        || ctx.db.collision_trigger().entity_id().filter(entity_id).next().is_some();
+    let rigid_body = state
+        .rigid_body_set
+        .get(rb_handle)
+        .ok_or_else(|| format!("Rigid body for entity {} not found", entity_id))?;
+    for co_handle in rigid_body.colliders() {
+        let Some(collider) = state.collider_set.get_mut(*co_handle) else {
+            continue;
+        };
+        if collider.is_sensor() {
+            continue;
+        }
+        let mut active_events = collider.active_events() - ActiveEvents::COLLISION_EVENTS;
+        if wanted {
+            active_events |= ActiveEvents::COLLISION_EVENTS;
+        }
+        collider.set_active_events(active_events);
+    }
+    Ok(())
+}
+
+/// Sets the velocities below which a body starts counting down to sleep.
+/// `linear` is normalized by the integration parameters' length unit.
+pub(crate) fn apply_sleep_thresholds(rigid_body: &mut RigidBody, linear: f32, angular: f32) {
+    let activation = rigid_body.activation_mut();
+    activation.normalized_linear_threshold = linear;
+    activation.angular_threshold = angular;
+}
+
+/// Looks up a world in the locked world map.
+pub(crate) fn get_world(
+    worlds: &mut HashMap<u32, PhysicsState>,
+    world_id: u32,
+) -> Result<&mut PhysicsState, String> {
+    worlds
+        .get_mut(&world_id)
+        .ok_or_else(|| format!("World {} does not exist", world_id))
+}
+
+/// Creates a fresh world and schedules its physics tick.
+pub(crate) fn insert_world(
+    ctx: &ReducerContext,
+    worlds: &mut HashMap<u32, PhysicsState>,
+    world_id: u32,
+) -> Result<(), String> {
+    if worlds.contains_key(&world_id) {
+        return Err(format!("World {} already exists", world_id));
+    }
+    schedule_tick(ctx, world_id, DEFAULT_TICK_INTERVAL_MS)?;
+    let state = PhysicsState::new(world_id);
+    publish_config(ctx, &state)?;
+    worlds.insert(world_id, state);
+    Ok(())
+}
+
+/// Writes the world's `PhysicsConfig` row from its current settings.
+pub(crate) fn publish_config(ctx: &ReducerContext, state: &PhysicsState) -> Result<(), String> {
+    let row = PhysicsConfig::from_state(state, ctx.timestamp);
+    if ctx.db.physics_config().world_id().find(state.world_id).is_some() {
+        ctx.db.physics_config().world_id().update(row);
+    } else {
+        ctx.db
+            .physics_config()
+            .try_insert(row)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Inserts the timer row that runs `process_physics_tick` for a world every `millis`.
+pub(crate) fn schedule_tick(ctx: &ReducerContext, world_id: u32, millis: u64) -> Result<(), String> {
+    ctx.db
+        .physics_tick_timer()
+        .try_insert(PhysicsTickTimer {
+            id: world_id as u64,
+            world_id,
+            scheduled_at: ScheduleAt::Interval(std::time::Duration::from_millis(millis).into()),
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Sets `dt` to one tick of `millis`, so each tick takes exactly one step, and drops
+/// time banked at the old rate.
+pub(crate) fn match_tick_interval(state: &mut PhysicsState, millis: u64) {
+    state.integration_parameters.dt = millis as f32 / 1000.0;
+    state.time_accumulator_micros = 0;
+}
+
+/// Fails if spawning `additional` bodies would push the world past `max_bodies`.
+/// Call before touching the physics sets: a failed reducer rolls back its table
+/// writes, but not changes to `PHYSICS_STATE`.
+pub(crate) fn check_body_limit(state: &PhysicsState, additional: usize) -> Result<(), String> {
+    if state.rigid_body_set.len() + additional > state.max_bodies {
+        return Err("body limit reached".to_string());
+    }
+    Ok(())
+}
+
+/// Looks up the world and stored Rapier handles for an entity.
+pub(crate) fn get_entity_handles(
+    ctx: &ReducerContext,
+    entity_id: u32,
+) -> Result<(u32, RigidBodyHandle, ColliderHandle), String> {
+    let entity_physics = ctx
+        .db
+        .entity_physics()
+        .entity_id()
+        .find(entity_id)
+        .ok_or_else(|| format!("No physics body for entity {}", entity_id))?;
+    let rb_handle = RigidBodyHandle::from_raw_parts(
+        entity_physics.rb_handle_index,
+        entity_physics.rb_handle_generation,
+    );
+    let co_handle = ColliderHandle::from_raw_parts(
+        entity_physics.co_handle_index,
+        entity_physics.co_handle_generation,
+    );
+    Ok((entity_physics.world_id, rb_handle, co_handle))
+}
+
+/// Deletes every table row belonging to an entity. Physics objects are left alone.
+pub(crate) fn delete_entity_rows(ctx: &ReducerContext, entity_id: u32) {
+    ctx.db.entity().id().delete(entity_id);
+    ctx.db.entity_physics().entity_id().delete(entity_id);
+    ctx.db.entity_transform().entity_id().delete(entity_id);
+    ctx.db.entity_transform_prev().entity_id().delete(entity_id);
+    ctx.db.entity_velocity().entity_id().delete(entity_id);
+    ctx.db.entity_character_state().entity_id().delete(entity_id);
+    ctx.db.entity_shape().entity_id().delete(entity_id);
+    ctx.db.entity_material().entity_id().delete(entity_id);
+    ctx.db.entity_tag().entity_id().delete(entity_id);
+    ctx.db.entity_friction_axis().entity_id().delete(entity_id);
+    ctx.db.entity_center_of_mass().entity_id().delete(entity_id);
+    ctx.db.entity_aabb_result().entity_id().delete(entity_id);
+    ctx.db.entity_layers().entity_id().delete(entity_id);
+    ctx.db.moving_result().entity_id().delete(entity_id);
+    ctx.db.mass_result().entity_id().delete(entity_id);
+    ctx.db.collision_trigger().entity_id().delete(entity_id);
+    ctx.db.entity_raycast_result().entity_id().delete(entity_id);
+    ctx.db.sweep_result().entity_id().delete(entity_id);
+    ctx.db.frozen_state().entity_id().delete(entity_id);
+    ctx.db.tag_query_result().entity_id().delete(entity_id);
+    ctx.db.entity_collider().entity_id().delete(entity_id);
+    ctx.db.contact_result().entity_id().delete(entity_id);
+    ctx.db.contact_between_result().entity_a().delete(entity_id);
+    ctx.db.grounded_result().entity_id().delete(entity_id);
+    ctx.db.entity_energy().entity_id().delete(entity_id);
+    ctx.db.physics_transform_result().entity_id().delete(entity_id);
+    let region_rows: Vec<u32> = ctx
+        .db
+        .region_result()
+        .iter()
+        .filter(|row| row.entity_id == entity_id)
+        .map(|row| row.id)
+        .collect();
+    for id in region_rows {
+        ctx.db.region_result().id().delete(id);
+    }
+    let grid_rows: Vec<u32> = ctx
+        .db
+        .grid_query_result()
+        .iter()
+        .filter(|row| row.entity_id == entity_id)
+        .map(|row| row.id)
+        .collect();
+    for id in grid_rows {
+        ctx.db.grid_query_result().id().delete(id);
+    }
+    ctx.db.entity_lifetime().entity_id().delete(entity_id);
+    ctx.db.entity_joint().entity_a().delete(entity_id);
+    ctx.db.entity_joint().entity_b().delete(entity_id);
+    ctx.db.multibody_link().parent_entity().delete(entity_id);
+    ctx.db.multibody_link().child_entity().delete(entity_id);
+    ctx.db.joint_list_result().entity_a().delete(entity_id);
+    ctx.db.joint_list_result().entity_b().delete(entity_id);
+    ctx.db.trigger_state().zone_id().delete(entity_id);
+    ctx.db.magnet_pair().entity_a().delete(entity_id);
+    ctx.db.magnet_pair().entity_b().delete(entity_id);
+    ctx.db.pulley_constraint().entity_a().delete(entity_id);
+    ctx.db.pulley_constraint().entity_b().delete(entity_id);
+    // A removed body never reports leaving the zones it was in
+    let zones: Vec<TriggerState> = ctx
+        .db
+        .trigger_state()
+        .iter()
+        .filter(|zone| zone.entities_inside.contains(&entity_id))
+        .collect();
+    for mut zone in zones {
+        zone.entities_inside.retain(|id| *id != entity_id);
+        ctx.db.trigger_state().zone_id().update(zone);
+    }
+}
+
+/// Removes an entity's body, every collider attached to it and any joints touching it,
+/// then deletes the entity's table rows.
+pub(crate) fn remove_entity(
+    ctx: &ReducerContext,
+    state: &mut PhysicsState,
+    entity_id: u32,
+) -> Result<(), String> {
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    if world_id != state.world_id {
+        return Err(format!("Entity {} is not in world {}", entity_id, state.world_id));
+    }
+    remove_body(state, entity_id, rb_handle);
+    delete_entity_rows(ctx, entity_id);
+    Ok(())
+}
+
+/// Takes an entity's body, colliders and joints out of the world along with its handle
+/// mappings and hook entries. The entity's rows are left to the caller.
+pub(crate) fn remove_body(state: &mut PhysicsState, entity_id: u32, rb_handle: RigidBodyHandle) {
+    // Destructure state for mutable access
+    let PhysicsState {
+        rigid_body_set,
+        collider_set,
+        island_manager,
+        impulse_joint_set,
+        multibody_joint_set,
+        handle_to_entity_id,
+        collider_to_entity_id,
+        hooks,
+        collision_logging,
+        ..
+    } = state;
+
+    if let Some(rigid_body) = rigid_body_set.get(rb_handle) {
+        for co_handle in rigid_body.colliders() {
+            collider_to_entity_id.remove(co_handle);
+            hooks.remove_collider(*co_handle);
+        }
+    }
+    collision_logging.remove(&entity_id);
+    rigid_body_set.remove(
+        rb_handle,
+        island_manager,
+        collider_set,
+        impulse_joint_set,
+        multibody_joint_set,
+        true, // Also remove the attached colliders
+    );
+    handle_to_entity_id.remove(&rb_handle);
+}
+
+/// Counts one more tick for every awake dynamic body and forgets the ones that slept
+/// or were removed. Kept in memory, so tracking costs no table writes per tick.
+pub(crate) fn count_awake_ticks(state: &mut PhysicsState) {
+    let PhysicsState {
+        rigid_body_set,
+        awake_ticks,
+        ..
+    } = state;
+    let awake = |rigid_body: &RigidBody| rigid_body.is_dynamic() && !rigid_body.is_sleeping();
+    awake_ticks.retain(|handle, _| rigid_body_set.get(*handle).is_some_and(awake));
+    for (handle, rigid_body) in rigid_body_set.iter() {
+        if awake(rigid_body) {
+            *awake_ticks.entry(handle).or_insert(0) += 1;
+        }
+    }
+}
+
+/// Puts to sleep every body awake for more than `min_ticks_awake` ticks that is barely
+/// moving. Returns how many it put to sleep.
+pub(crate) fn nudge_stuck(state: &mut PhysicsState, min_ticks_awake: u32) -> usize {
+    let long_awake: Vec<RigidBodyHandle> = state
+        .awake_ticks
+        .iter()
+        .filter(|(_, ticks)| **ticks > min_ticks_awake)
+        .map(|(handle, _)| *handle)
+        .collect();
+    let mut nudged = 0;
+    for rb_handle in long_awake {
+        if sleep_if_stuck(state, rb_handle) {
+            state.awake_ticks.remove(&rb_handle);
+            nudged += 1;
+        }
+    }
+    nudged
+}
+
+/// Linear (m/s) and angular (rad/s) speed under which `sleep_if_stuck` considers
+/// a body to be jittering in place rather than moving.
+const STUCK_SPEED: f32 = 0.1;
+
+/// Puts `rb_handle`'s body to sleep if it is barely moving; returns whether it did.
+pub(crate) fn sleep_if_stuck(state: &mut PhysicsState, rb_handle: RigidBodyHandle) -> bool {
+    let Some(rigid_body) = state.rigid_body_set.get_mut(rb_handle) else {
+        return false;
+    };
+    if rigid_body.linvel().norm() < STUCK_SPEED && rigid_body.angvel().norm() < STUCK_SPEED {
+        // Zeroes the velocities too; a real disturbance wakes the body again
+        rigid_body.sleep();
+        true
+    } else {
+        false
+    }
+}
+
+/// Entities whose dynamic body is currently below `threshold`, by live position.
+pub(crate) fn dynamic_entities_below(state: &PhysicsState, threshold: f64) -> Vec<u32> {
+    state
+        .handle_to_entity_id
+        .iter()
+        .filter(|(handle, _)| {
+            state.rigid_body_set.get(**handle).is_some_and(|rigid_body| {
+                rigid_body.is_dynamic() && (rigid_body.translation().y as f64) < threshold
+            })
+        })
+        .map(|(_, entity_id)| *entity_id)
+        .collect()
+}
+
+/// Joins the bodies of two entities in `state`'s world and records the `EntityJoint` row.
+/// Returns the joint row id.
+pub(crate) fn register_joint(
+    ctx: &ReducerContext,
+    state: &mut PhysicsState,
+    entity_a: u32,
+    entity_b: u32,
+    kind: JointKind,
+    joint: impl Into<GenericJoint>,
+) -> Result<u32, String> {
+    if entity_a == entity_b {
+        return Err("Cannot join an entity to itself".to_string());
+    }
+    let (world_a, rb_a, _) = get_entity_handles(ctx, entity_a)?;
+    let (world_b, rb_b, _) = get_entity_handles(ctx, entity_b)?;
+    if world_a != state.world_id || world_b != state.world_id {
+        return Err(format!(
+            "Entities {} and {} must both be in world {}",
+            entity_a, entity_b, state.world_id
+        ));
+    }
+    if !state.rigid_body_set.contains(rb_a) || !state.rigid_body_set.contains(rb_b) {
+        return Err(format!(
+            "Rigid body for entity {} or {} not found",
+            entity_a, entity_b
+        ));
+    }
+    let joint_handle = state.impulse_joint_set.insert(rb_a, rb_b, joint, true);
+
+    let id = ctx
+        .db
+        .entity_joint()
+        .iter()
+        .map(|row| row.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let (joint_idx, joint_gen) = joint_handle.into_raw_parts();
+    ctx.db
+        .entity_joint()
+        .try_insert(EntityJoint {
+            id,
+            world_id: state.world_id,
+            entity_a,
+            entity_b,
+            kind,
+            joint_handle_index: joint_idx,
+            joint_handle_generation: joint_gen,
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(id)
+}
+
+// --- Test Support ---
+
+#[cfg(test)]
+impl PhysicsState {
+    /// Adds a dynamic unit ball at `position`, without any entity rows.
+    pub(crate) fn add_test_ball(&mut self, position: Vector3<f32>) -> RigidBodyHandle {
+        let rb_handle = self
+            .rigid_body_set
+            .insert(RigidBodyBuilder::dynamic().translation(position).build());
+        self.collider_set.insert_with_parent(
+            ColliderBuilder::ball(1.0).build(),
+            rb_handle,
+            &mut self.rigid_body_set,
+        );
+        rb_handle
+    }
+
+    /// Runs one `dt` step under uniform gravity, like a tick without the table writes.
+    pub(crate) fn step_once(&mut self) {
+        let hooks = std::mem::take(&mut self.hooks);
+        self.step_with_hooks(&hooks);
+        self.hooks = hooks;
+    }
+
+    /// `step_once` with other hooks than the world's own.
+    pub(crate) fn step_with_hooks(&mut self, hooks: &dyn PhysicsHooks) {
+        self.physics_pipeline.step(
+            &Vector3::new(0.0, -GRAVITY, 0.0),
+            &self.integration_parameters,
+            &mut self.island_manager,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.rigid_body_set,
+            &mut self.collider_set,
+            &mut self.impulse_joint_set,
+            &mut self.multibody_joint_set,
+            &mut self.ccd_solver,
+            Some(&mut self.query_pipeline),
+            hooks,
+            &(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Keeps every contact, counting how often rapier asked.
+    #[derive(Default)]
+    struct CountingFilter {
+        calls: AtomicUsize,
+    }
+
+    impl PhysicsHooks for CountingFilter {
+        fn filter_contact_pair(&self, _context: &PairFilterContext) -> Option<SolverFlags> {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            Some(SolverFlags::COMPUTE_IMPULSES)
+        }
+    }
+
+    #[test]
+    fn contact_filter_hook_runs_only_for_flagged_colliders() {
+        let mut state = PhysicsState::new(0);
+        // Resting on the ground plane, so the pair has contacts every step
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 1.0, 0.0));
+        let co_handle = state.rigid_body_set[rb_handle].colliders()[0];
+        let filter = CountingFilter::default();
+        for _ in 0..5 {
+            state.step_with_hooks(&filter);
+        }
+        assert_eq!(filter.calls.load(Ordering::Relaxed), 0);
+
+        state
+            .hooks
+            .requested
+            .insert(co_handle, ActiveHooks::FILTER_CONTACT_PAIRS);
+        state
+            .hooks
+            .sync_active_hooks(co_handle, &mut state.collider_set[co_handle]);
+        for _ in 0..5 {
+            state.step_with_hooks(&filter);
+        }
+        assert!(filter.calls.load(Ordering::Relaxed) > 0);
+    }
+
+    #[test]
+    fn sync_active_hooks_keeps_requested_and_registered_flags() {
+        let mut hooks = WorldHooks::default();
+        let mut collider_set = ColliderSet::new();
+        let co_handle = collider_set.insert(ColliderBuilder::ball(1.0).build());
+        hooks
+            .requested
+            .insert(co_handle, ActiveHooks::FILTER_CONTACT_PAIRS);
+        hooks.conveyors.insert(co_handle, Vector3::x());
+        hooks.sync_active_hooks(co_handle, &mut collider_set[co_handle]);
+        assert_eq!(
+            collider_set[co_handle].active_hooks(),
+            ActiveHooks::FILTER_CONTACT_PAIRS | ActiveHooks::MODIFY_SOLVER_CONTACTS
+        );
+
+        // Turning the conveyor off leaves the requested flag alone
+        hooks.conveyors.remove(&co_handle);
+        hooks.sync_active_hooks(co_handle, &mut collider_set[co_handle]);
+        assert_eq!(
+            collider_set[co_handle].active_hooks(),
+            ActiveHooks::FILTER_CONTACT_PAIRS
+        );
+
+        hooks.remove_collider(co_handle);
+        hooks.sync_active_hooks(co_handle, &mut collider_set[co_handle]);
+        assert!(collider_set[co_handle].active_hooks().is_empty());
+    }
+
+    #[test]
+    fn lock_state_recovers_from_a_panicking_holder() {
+        // An id no other test uses, since the world map is shared across tests
+        const WORLD_ID: u32 = 9_330;
+        let panicked = std::thread::spawn(|| {
+            let mut worlds = lock_state();
+            worlds.insert(WORLD_ID, PhysicsState::new(WORLD_ID));
+            panic!("tick blew up while holding the lock");
+        })
+        .join()
+        .is_err();
+        assert!(panicked);
+
+        let mut worlds = lock_state();
+        assert!(!PHYSICS_STATE.is_poisoned());
+        let state = get_world(&mut worlds, WORLD_ID).unwrap();
+        assert!(check_body_limit(state, 1).is_ok());
+        state.add_test_ball(Vector3::new(0.0, 5.0, 0.0));
+        assert_eq!(state.rigid_body_set.len(), 1);
+        worlds.remove(&WORLD_ID);
+    }
+
+    #[test]
+    fn entity_with_a_ttl_of_three_is_removed_on_the_third_tick() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 5.0, 0.0));
+        let co_handle = state.rigid_body_set[rb_handle].colliders()[0];
+        state.handle_to_entity_id.insert(rb_handle, 1);
+        state.collider_to_entity_id.insert(co_handle, 1);
+        state.collision_logging.insert(1);
+        let mut lifetime = EntityLifetime {
+            entity_id: 1,
+            world_id: 0,
+            remaining_ticks: 3,
+        };
+
+        let mut removed_on = None;
+        for tick in 1..=4 {
+            state.step_once();
+            if removed_on.is_none() && lifetime.tick_down() {
+                remove_body(&mut state, 1, rb_handle);
+                removed_on = Some(tick);
+            }
+        }
+        assert_eq!(removed_on, Some(3));
+        assert!(state.rigid_body_set.is_empty());
+        assert!(state.collider_set.get(co_handle).is_none());
+        assert!(state.handle_to_entity_id.is_empty() && state.collider_to_entity_id.is_empty());
+        assert!(!state.collision_logging.contains(&1));
+    }
+
+    #[test]
+    fn zero_ttl_expires_right_away() {
+        let mut lifetime = EntityLifetime {
+            entity_id: 1,
+            world_id: 0,
+            remaining_ticks: 0,
+        };
+        assert!(lifetime.tick_down());
+    }
+
+    #[test]
+    fn new_world_steps_at_the_tick_rate_onto_its_ground_plane() {
+        let mut state = PhysicsState::new(4);
+        assert_eq!(state.world_id, 4);
+        assert_eq!(
+            state.integration_parameters.dt,
+            DEFAULT_TICK_INTERVAL_MS as f32 / 1000.0
+        );
+        // Only the ground: a collider without a body or an entity
+        assert_eq!(state.collider_set.len(), 1);
+        assert!(state.rigid_body_set.is_empty());
+
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 3.0, 0.0));
+        for _ in 0..120 {
+            state.step_once();
+        }
+        let resting_y = state.rigid_body_set[rb_handle].translation().y;
+        assert!(
+            (resting_y - 1.1).abs() < 0.05,
+            "ball rests at {}",
+            resting_y
+        );
+    }
+
+    #[test]
+    fn stuck_bodies_are_put_to_sleep_and_moving_ones_are_not() {
+        let mut state = PhysicsState::new(0);
+        let stuck = state.add_test_ball(Vector3::new(0.0, 1.1, 0.0));
+        let rolling = state.add_test_ball(Vector3::new(5.0, 1.1, 0.0));
+        for rb_handle in [stuck, rolling] {
+            // Never sleeps on its own, like a stack caught in a low-energy jitter
+            let activation = state.rigid_body_set[rb_handle].activation_mut();
+            activation.normalized_linear_threshold = -1.0;
+            activation.angular_threshold = -1.0;
+        }
+        for _ in 0..120 {
+            state.rigid_body_set[rolling].set_linvel(Vector3::new(2.0, 0.0, 0.0), true);
+            state.step_once();
+            count_awake_ticks(&mut state);
+        }
+        assert!(!state.rigid_body_set[stuck].is_sleeping());
+        assert_eq!(state.awake_ticks.get(&stuck), Some(&120));
+
+        // Not awake long enough yet, then long enough: only the still one is put to sleep
+        assert_eq!(nudge_stuck(&mut state, 120), 0);
+        assert_eq!(nudge_stuck(&mut state, 100), 1);
+        assert!(state.rigid_body_set[stuck].is_sleeping());
+        assert!(!state.rigid_body_set[rolling].is_sleeping());
+        assert!(!sleep_if_stuck(&mut state, rolling));
+
+        // The sleeper's streak is over, the roller's goes on
+        count_awake_ticks(&mut state);
+        assert_eq!(state.awake_ticks.get(&stuck), None);
+        assert_eq!(state.awake_ticks.get(&rolling), Some(&121));
+    }
+}
@@ -0,0 +1,1325 @@
+//! SpacetimeDB tables.
+
+use rapier3d::na::Vector3;
+use rapier3d::prelude::*;
+use spacetimedb::{table, ScheduleAt, Timestamp};
+
+use crate::state::PhysicsState;
+use crate::tick::{process_physics_tick, GRAVITY};
+use crate::triggers::handle_collision_trigger;
+use crate::types::*;
+
+#[table(name = entity, public)]
+#[derive(Default, Clone)]
+pub struct Entity {
+    #[primary_key]
+    pub id: u32,
+    #[index(btree)]
+    pub world_id: u32,
+}
+
+#[table(name = entity_physics)]
+#[derive(Clone)]
+pub struct EntityPhysics {
+    #[primary_key]
+    pub(crate) entity_id: u32,
+    pub(crate) world_id: u32,
+    pub(crate) rb_handle_index: u32,
+    pub(crate) rb_handle_generation: u32,
+    pub(crate) co_handle_index: u32,
+    pub(crate) co_handle_generation: u32,
+}
+
+#[table(name = entity_transform, public)]
+#[derive(Clone, Default)]
+pub struct EntityTransform {
+    #[primary_key]
+    pub(crate) entity_id: u32,
+    #[index(btree)]
+    pub world_id: u32,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    // Orientation as a unit quaternion
+    pub rx: f32,
+    pub ry: f32,
+    pub rz: f32,
+    pub rw: f32,
+}
+
+impl EntityTransform {
+    pub(crate) fn from_pose(world_id: u32, entity_id: u32, pose: &Isometry<Real>) -> Self {
+        let pos = pose.translation.vector;
+        let rot = pose.rotation;
+        EntityTransform {
+            entity_id,
+            world_id,
+            x: pos.x as f64,
+            y: pos.y as f64,
+            z: pos.z as f64,
+            rx: rot.i,
+            ry: rot.j,
+            rz: rot.k,
+            rw: rot.w,
+        }
+    }
+}
+
+/// Position at the start of the most recent tick (before stepping).
+/// Clients lerp from this row to `EntityTransform` using their render-time alpha.
+#[table(name = entity_transform_prev, public)]
+#[derive(Clone, Default)]
+pub struct EntityTransformPrev {
+    #[primary_key]
+    pub(crate) entity_id: u32,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub rx: f32,
+    pub ry: f32,
+    pub rz: f32,
+    pub rw: f32,
+}
+
+impl From<EntityTransform> for EntityTransformPrev {
+    fn from(t: EntityTransform) -> Self {
+        EntityTransformPrev {
+            entity_id: t.entity_id,
+            x: t.x,
+            y: t.y,
+            z: t.z,
+            rx: t.rx,
+            ry: t.ry,
+            rz: t.rz,
+            rw: t.rw,
+        }
+    }
+}
+
+/// Linear velocity of an entity's body as of the last tick, with its magnitude
+/// precomputed so clients doing LOD or audio don't need a square root per body.
+#[table(name = entity_velocity, public)]
+#[derive(Clone, Default)]
+pub struct EntityVelocity {
+    #[primary_key]
+    pub(crate) entity_id: u32,
+    pub vx: f32,
+    pub vy: f32,
+    pub vz: f32,
+    pub speed: f32,
+}
+
+impl EntityVelocity {
+    pub(crate) fn from_body(entity_id: u32, rigid_body: &RigidBody) -> Self {
+        let linvel = rigid_body.linvel();
+        EntityVelocity {
+            entity_id,
+            vx: linvel.x,
+            vy: linvel.y,
+            vz: linvel.z,
+            speed: linvel.norm(),
+        }
+    }
+}
+
+/// Material of an entity's primary collider, so clients can pick impact sounds or effects.
+/// Written at spawn and kept in sync by `set_friction`, `set_restitution`, `set_density`
+/// and `set_center_of_mass`.
+#[table(name = entity_material, public)]
+#[derive(Clone)]
+pub struct EntityMaterial {
+    #[primary_key]
+    pub entity_id: u32,
+    pub friction: f32,
+    pub restitution: f32,
+    pub density: f32,
+}
+
+impl EntityMaterial {
+    pub(crate) fn from_collider(entity_id: u32, collider: &Collider) -> Self {
+        EntityMaterial {
+            entity_id,
+            friction: collider.friction(),
+            restitution: collider.restitution(),
+            density: collider.density(),
+        }
+    }
+}
+
+/// Direction-dependent friction of an entity's primary collider, set with
+/// `set_friction_axis`. `axis` is in the collider's local frame.
+#[table(name = entity_friction_axis, public)]
+#[derive(Clone)]
+pub struct EntityFrictionAxis {
+    #[primary_key]
+    pub entity_id: u32,
+    pub axis: Vec3,
+    pub along_friction: f32,
+    pub across_friction: f32,
+}
+
+/// Center-of-mass override set with `set_center_of_mass`. `density` is the primary
+/// collider's own density, which the override keeps at 0 while its mass is carried by
+/// the body; `set_density` and `scale_collider` recompute the mass from it.
+#[table(name = entity_center_of_mass, public)]
+#[derive(Clone)]
+pub struct EntityCenterOfMass {
+    #[primary_key]
+    pub entity_id: u32,
+    pub center: Vec3,
+    pub density: f32,
+}
+
+/// Gameplay group of an entity (team, projectile type, ...), set with `set_tag`.
+#[table(name = entity_tag, public)]
+#[derive(Clone)]
+pub struct EntityTag {
+    #[primary_key]
+    pub entity_id: u32,
+    #[index(btree)]
+    pub tag: u32,
+}
+
+/// Named collision layer (0-31), registered with `define_layer`. Each layer is one bit
+/// of Rapier's interaction groups.
+#[table(name = collision_layer, public)]
+#[derive(Clone)]
+pub struct CollisionLayer {
+    #[primary_key]
+    pub layer: u32,
+    pub name: String,
+}
+
+/// Layers an entity belongs to and collides with, set with `set_layers`.
+#[table(name = entity_layers, public)]
+#[derive(Clone)]
+pub struct EntityLayers {
+    #[primary_key]
+    pub entity_id: u32,
+    pub belongs_to: Vec<u32>,
+    pub collides_with: Vec<u32>,
+}
+
+/// Latest serialized world written by `snapshot_world`, one row per world.
+/// Layout: one `SNAPSHOT_VERSION` byte followed by the bincode-encoded world.
+#[table(name = world_snapshot)]
+pub struct WorldSnapshot {
+    #[primary_key]
+    pub(crate) world_id: u32,
+    pub taken_at: Timestamp,
+    pub data: Vec<u8>,
+}
+
+/// All body poses of a world packed into one buffer by `snapshot_transforms`, one row
+/// per world. Each entity is 32 little-endian bytes, sorted by entity id:
+/// `entity_id: u32, x: f32, y: f32, z: f32, rx: f32, ry: f32, rz: f32, rw: f32`.
+#[table(name = transform_blob, public)]
+#[derive(Clone)]
+pub struct TransformBlob {
+    #[primary_key]
+    pub world_id: u32,
+    pub entity_count: u32,
+    pub data: Vec<u8>,
+    pub taken_at: Timestamp,
+}
+
+impl TransformBlob {
+    pub(crate) fn from_state(state: &PhysicsState, taken_at: Timestamp) -> Self {
+        let mut bodies: Vec<(u32, &Isometry<Real>)> = state
+            .handle_to_entity_id
+            .iter()
+            .filter_map(|(rb_handle, entity_id)| {
+                let rigid_body = state.rigid_body_set.get(*rb_handle)?;
+                Some((*entity_id, rigid_body.position()))
+            })
+            .collect();
+        bodies.sort_unstable_by_key(|(entity_id, _)| *entity_id);
+
+        // Live poses, like `get_physics_transform`, in the layout documented above
+        let mut data = Vec::with_capacity(bodies.len() * 32);
+        for (entity_id, pose) in &bodies {
+            let pos = pose.translation.vector;
+            let rot = pose.rotation;
+            data.extend_from_slice(&entity_id.to_le_bytes());
+            for v in [pos.x, pos.y, pos.z, rot.i, rot.j, rot.k, rot.w] {
+                data.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        TransformBlob {
+            world_id: state.world_id,
+            entity_count: bodies.len() as u32,
+            data,
+            taken_at,
+        }
+    }
+}
+
+/// Live simulation settings of a world, one row per world. Written when the world is
+/// created or imported and by every world-level `set_*` tuning reducer.
+#[table(name = physics_config, public)]
+#[derive(Clone)]
+pub struct PhysicsConfig {
+    #[primary_key]
+    pub world_id: u32,
+    /// Fixed step length in seconds, follows `set_tick_interval`.
+    pub dt: f32,
+    pub num_solver_iterations: u32,
+    pub num_internal_stabilization_iterations: u32,
+    pub max_ccd_substeps: u32,
+    pub damping_ratio: f32,
+    /// Uniform gravity; zero while radial gravity is active.
+    pub gravity: Vec3,
+    pub radial_gravity_center: Option<Vec3>,
+    pub radial_gravity_strength: f32,
+    pub wind: Vec3,
+    pub time_scale: f32,
+    pub deterministic: bool,
+    pub max_velocity: Option<f32>,
+    pub contact_force_threshold: f32,
+    pub max_bodies: u32,
+    pub kill_plane_y: Option<f64>,
+    pub updated_at: Timestamp,
+}
+
+impl PhysicsConfig {
+    pub(crate) fn from_state(state: &PhysicsState, updated_at: Timestamp) -> Self {
+        let params = &state.integration_parameters;
+        let gravity = match state.radial_gravity_center {
+            Some(_) => Vector3::zeros(),
+            None => Vector3::new(0.0, -GRAVITY, 0.0),
+        };
+        PhysicsConfig {
+            world_id: state.world_id,
+            dt: params.dt,
+            num_solver_iterations: params.num_solver_iterations.get() as u32,
+            num_internal_stabilization_iterations: params.num_internal_stabilization_iterations as u32,
+            max_ccd_substeps: params.max_ccd_substeps as u32,
+            damping_ratio: params.damping_ratio,
+            gravity: gravity.into(),
+            radial_gravity_center: state.radial_gravity_center.map(Vec3::from),
+            radial_gravity_strength: state.radial_gravity_strength,
+            wind: state.wind.into(),
+            time_scale: state.time_scale,
+            deterministic: state.deterministic,
+            max_velocity: state.max_velocity,
+            contact_force_threshold: state.contact_force_threshold,
+            max_bodies: state.max_bodies as u32,
+            kill_plane_y: state.kill_plane_y,
+            updated_at,
+        }
+    }
+}
+
+/// Live performance readout, overwritten every tick (one row per world).
+/// `tick_delta_micros` is the `ctx.timestamp` gap since the previous tick, which stands in
+/// for wall-clock step timing since `Instant` isn't available in the module sandbox.
+#[table(name = physics_metrics, public)]
+#[derive(Clone)]
+pub struct PhysicsMetrics {
+    #[primary_key]
+    pub world_id: u32,
+    pub tick: u64,
+    pub body_count: u32,
+    pub active_body_count: u32,
+    pub tick_delta_micros: i64,
+    pub timestamp: Timestamp,
+}
+
+impl PhysicsMetrics {
+    pub(crate) fn from_state(
+        state: &PhysicsState,
+        timestamp: Timestamp,
+        previous: Option<&PhysicsMetrics>,
+    ) -> Self {
+        let tick_delta_micros = previous
+            .map(|prev| {
+                timestamp.to_micros_since_unix_epoch() - prev.timestamp.to_micros_since_unix_epoch()
+            })
+            .unwrap_or(0);
+        PhysicsMetrics {
+            world_id: state.world_id,
+            tick: state.tick,
+            body_count: state.rigid_body_set.len() as u32,
+            active_body_count: state.island_manager.active_dynamic_bodies().len() as u32,
+            tick_delta_micros,
+            timestamp,
+        }
+    }
+}
+
+/// Extra colliders attached to an entity's body with `attach_collider`.
+/// The body's primary collider stays in `EntityPhysics`/`EntityShape`.
+#[table(name = entity_collider, public)]
+#[derive(Clone)]
+pub struct EntityCollider {
+    #[primary_key]
+    pub id: u32,
+    #[index(btree)]
+    pub entity_id: u32,
+    pub shape: ShapeDesc,
+    pub local_x: f32,
+    pub local_y: f32,
+    pub local_z: f32,
+    pub(crate) co_handle_index: u32,
+    pub(crate) co_handle_generation: u32,
+}
+
+/// Impulse joint between two entities' bodies. Rapier drops the joint when either body
+/// is removed, and `delete_entity_rows` drops the row with it.
+#[table(name = entity_joint, public)]
+#[derive(Clone)]
+pub struct EntityJoint {
+    #[primary_key]
+    pub id: u32,
+    pub world_id: u32,
+    #[index(btree)]
+    pub entity_a: u32,
+    #[index(btree)]
+    pub entity_b: u32,
+    pub kind: JointKind,
+    pub(crate) joint_handle_index: u32,
+    pub(crate) joint_handle_generation: u32,
+}
+
+impl EntityJoint {
+    pub(crate) fn joint_handle(&self) -> ImpulseJointHandle {
+        ImpulseJointHandle::from_raw_parts(self.joint_handle_index, self.joint_handle_generation)
+    }
+}
+
+/// Multibody (reduced-coordinate) joint from a parent entity's body to a child's. Each
+/// body has at most one parent, so links form trees. Dropped along with either entity.
+#[table(name = multibody_link, public)]
+#[derive(Clone)]
+pub struct MultibodyLink {
+    #[primary_key]
+    pub id: u32,
+    pub world_id: u32,
+    #[index(btree)]
+    pub parent_entity: u32,
+    #[index(btree)]
+    pub child_entity: u32,
+    pub kind: LinkKind,
+    pub(crate) joint_handle_index: u32,
+    pub(crate) joint_handle_generation: u32,
+}
+
+/// Result of the last `move_character` call for a character entity.
+#[table(name = entity_character_state, public)]
+#[derive(Clone)]
+pub struct EntityCharacterState {
+    #[primary_key]
+    pub(crate) entity_id: u32,
+    pub grounded: bool,
+    pub sliding_down_slope: bool,
+}
+
+/// Shape of each entity's collider so clients can render the matching primitive.
+#[table(name = entity_shape, public)]
+#[derive(Clone)]
+pub struct EntityShape {
+    #[primary_key]
+    pub(crate) entity_id: u32,
+    pub shape: ShapeDesc,
+}
+
+/// Point attractor applied to every dynamic body in its world each tick, on top of global gravity.
+#[table(name = gravity_well, public)]
+#[derive(Clone)]
+pub struct GravityWell {
+    #[primary_key]
+    pub id: u32,
+    #[index(btree)]
+    pub world_id: u32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub strength: f32,
+}
+
+/// Axis-aligned box of fluid. Dynamic bodies inside get buoyancy proportional to their
+/// submerged volume plus drag against their velocity.
+#[table(name = fluid_zone, public)]
+#[derive(Clone)]
+pub struct FluidZone {
+    #[primary_key]
+    pub id: u32,
+    #[index(btree)]
+    pub world_id: u32,
+    pub center: Vec3,
+    pub half_extents: Vec3,
+    pub density: f32,
+    pub drag: f32,
+}
+
+/// Axis-aligned box that pushes every dynamic body whose origin is inside it with `force`
+/// each tick. Overlapping fields add up.
+#[table(name = force_field, public)]
+#[derive(Clone)]
+pub struct ForceField {
+    #[primary_key]
+    pub id: u32,
+    #[index(btree)]
+    pub world_id: u32,
+    pub center: Vec3,
+    pub half_extents: Vec3,
+    pub force: Vec3,
+}
+
+/// Attraction (positive `strength`) or repulsion (negative) between two entities,
+/// applied as equal and opposite `strength / dist²` forces each tick.
+#[table(name = magnet_pair, public)]
+#[derive(Clone)]
+pub struct MagnetPair {
+    #[primary_key]
+    pub id: u32,
+    #[index(btree)]
+    pub world_id: u32,
+    #[index(btree)]
+    pub entity_a: u32,
+    #[index(btree)]
+    pub entity_b: u32,
+    pub strength: f32,
+}
+
+/// Rope of fixed total `length` running from entity A over `anchor_a` and `anchor_b`
+/// (world space) down to entity B: when one body moves away from its anchor, the other
+/// is pulled toward its own. Enforced with tension forces each tick, so it stretches slightly.
+#[table(name = pulley_constraint, public)]
+#[derive(Clone)]
+pub struct PulleyConstraint {
+    #[primary_key]
+    pub id: u32,
+    #[index(btree)]
+    pub world_id: u32,
+    #[index(btree)]
+    pub entity_a: u32,
+    pub anchor_a: Vec3,
+    #[index(btree)]
+    pub entity_b: u32,
+    pub anchor_b: Vec3,
+    pub length: f32,
+}
+
+/// Contact whose total force exceeded the contact-force threshold, e.g. for damage.
+/// `None` means the collider isn't an entity (such as the ground).
+#[table(name = impact_event, public)]
+#[derive(Clone)]
+pub struct ImpactEvent {
+    #[primary_key]
+    pub id: u64,
+    #[index(btree)]
+    pub world_id: u32,
+    pub entity_a: Option<u32>,
+    pub entity_b: Option<u32>,
+    pub total_force_magnitude: f32,
+    pub tick: u64,
+}
+
+/// Start or end of a collision involving a body opted in with `set_collision_events`.
+/// `None` means the collider isn't an entity (such as the ground).
+#[table(name = collision_log, public)]
+#[derive(Clone)]
+pub struct CollisionLog {
+    #[primary_key]
+    pub id: u64,
+    #[index(btree)]
+    pub world_id: u32,
+    pub entity_a: Option<u32>,
+    pub entity_b: Option<u32>,
+    /// `true` when the colliders started touching, `false` when they separated.
+    pub started: bool,
+    pub tick: u64,
+}
+
+/// Result of `get_contacts`: one row per body touching `entity_id` at query time.
+/// `other_entity_id` is `None` for non-entity colliders such as the ground.
+#[table(name = contact_result, public)]
+#[derive(Clone)]
+pub struct ContactResult {
+    #[primary_key]
+    pub id: u32,
+    #[index(btree)]
+    pub entity_id: u32,
+    pub other_entity_id: Option<u32>,
+    pub queried_at: Timestamp,
+}
+
+/// Result of the last `get_contact_between` query made for `entity_a`: the deepest contact
+/// point (world space) and the normal pushing A away from B. `entity_b` `None` is the ground.
+#[table(name = contact_between_result, public)]
+#[derive(Clone)]
+pub struct ContactBetweenResult {
+    #[primary_key]
+    pub entity_a: u32,
+    pub entity_b: Option<u32>,
+    pub in_contact: bool,
+    pub point: Vec3,
+    pub normal: Vec3,
+    /// Penetration depth (negative distance) of the reported point.
+    pub depth: f32,
+    pub queried_at: Timestamp,
+}
+
+/// Result of the last `entity_aabb` query for an entity: the world-space bounding box
+/// of its collider.
+#[table(name = entity_aabb_result, public)]
+#[derive(Clone)]
+pub struct EntityAabbResult {
+    #[primary_key]
+    pub entity_id: u32,
+    pub world_id: u32,
+    pub min: Vec3,
+    pub max: Vec3,
+    pub queried_at: Timestamp,
+}
+
+impl EntityAabbResult {
+    pub(crate) fn from_collider(
+        world_id: u32,
+        entity_id: u32,
+        collider: &Collider,
+        queried_at: Timestamp,
+    ) -> Self {
+        let aabb = collider.compute_aabb();
+        EntityAabbResult {
+            entity_id,
+            world_id,
+            min: aabb.mins.coords.into(),
+            max: aabb.maxs.coords.into(),
+            queried_at,
+        }
+    }
+}
+
+/// Remaining lifetime of entities spawned with `SpawnOptions::ttl_ticks`.
+#[table(name = entity_lifetime, public)]
+#[derive(Clone)]
+pub struct EntityLifetime {
+    #[primary_key]
+    pub entity_id: u32,
+    #[index(btree)]
+    pub world_id: u32,
+    pub remaining_ticks: u32,
+}
+
+impl EntityLifetime {
+    /// Counts down one tick and reports whether the entity has expired. A TTL of 0
+    /// expires right away.
+    pub(crate) fn tick_down(&mut self) -> bool {
+        self.remaining_ticks = self.remaining_ticks.saturating_sub(1);
+        self.remaining_ticks == 0
+    }
+}
+
+/// Sensor zone created by `spawn_trigger_zone`, keyed by the zone's entity id.
+/// `enter_count` counts every entry, so leaving and re-entering counts twice.
+#[table(name = trigger_state, public)]
+#[derive(Clone)]
+pub struct TriggerState {
+    #[primary_key]
+    pub zone_id: u32,
+    pub world_id: u32,
+    pub entities_inside: Vec<u32>,
+    pub enter_count: u64,
+}
+
+impl TriggerState {
+    /// Applies a sensor intersection of `other_id` with the zone, counting an entry only
+    /// when the entity wasn't already inside. Returns whether the row changed.
+    pub(crate) fn record_intersection(&mut self, other_id: u32, started: bool) -> bool {
+        if started {
+            if self.entities_inside.contains(&other_id) {
+                return false;
+            }
+            self.entities_inside.push(other_id);
+            self.enter_count += 1;
+            true
+        } else {
+            let before = self.entities_inside.len();
+            self.entities_inside.retain(|id| *id != other_id);
+            self.entities_inside.len() != before
+        }
+    }
+}
+
+/// Recorded reducer calls of worlds with recording on (`set_recording`), in call order.
+/// `tick` is the world's tick count at the time, so the call took effect in tick `tick + 1`.
+#[table(name = reducer_log, public)]
+#[derive(Clone)]
+pub struct ReducerLog {
+    #[primary_key]
+    pub id: u64,
+    #[index(btree)]
+    pub world_id: u32,
+    pub tick: u64,
+    pub call: LoggedCall,
+    /// Entities the call spawned, in spawn order.
+    pub entity_ids: Vec<u32>,
+}
+
+/// Result of the last `body_count` query for a world.
+#[table(name = body_count_result, public)]
+#[derive(Clone)]
+pub struct BodyCountResult {
+    #[primary_key]
+    pub world_id: u32,
+    pub body_count: u32,
+    pub active_body_count: u32,
+    pub queried_at: Timestamp,
+}
+
+impl BodyCountResult {
+    pub(crate) fn from_state(state: &PhysicsState, queried_at: Timestamp) -> Self {
+        BodyCountResult {
+            world_id: state.world_id,
+            body_count: state.rigid_body_set.len() as u32,
+            active_body_count: state.island_manager.active_dynamic_bodies().len() as u32,
+            queried_at,
+        }
+    }
+}
+
+/// Result of the last `total_kinetic_energy` query for a world, summed over its dynamic bodies.
+#[table(name = energy_result, public)]
+#[derive(Clone)]
+pub struct EnergyResult {
+    #[primary_key]
+    pub world_id: u32,
+    pub total_kinetic_energy: f32,
+    pub queried_at: Timestamp,
+}
+
+/// Per-entity breakdown of the last `total_kinetic_energy` query (joules).
+/// Each query replaces the previous rows for its world.
+#[table(name = entity_energy, public)]
+#[derive(Clone)]
+pub struct EntityEnergy {
+    #[primary_key]
+    pub entity_id: u32,
+    #[index(btree)]
+    pub world_id: u32,
+    pub linear: f32,
+    pub angular: f32,
+    pub total: f32,
+}
+
+impl EntityEnergy {
+    pub(crate) fn from_body(world_id: u32, entity_id: u32, rigid_body: &RigidBody) -> Self {
+        // Rapier's kinetic energy includes the rotational part (with the world-space
+        // inertia), so the angular share is whatever the translational term leaves
+        let total = rigid_body.kinetic_energy();
+        let linear = 0.5 * rigid_body.mass() * rigid_body.linvel().norm_squared();
+        EntityEnergy {
+            entity_id,
+            world_id,
+            linear,
+            angular: (total - linear).max(0.0),
+            total,
+        }
+    }
+}
+
+/// Result of `query_grid_sphere` / `query_grid_box`: one row per entity found.
+/// Each query replaces the previous rows for its world.
+#[table(name = grid_query_result, public)]
+#[derive(Clone)]
+pub struct GridQueryResult {
+    #[primary_key]
+    pub id: u32,
+    #[index(btree)]
+    pub world_id: u32,
+    pub entity_id: u32,
+    pub queried_at: Timestamp,
+}
+
+/// Result of `region_histogram`: one row per occupied cell. Each query replaces the
+/// previous rows for its world.
+#[table(name = region_histogram_cell, public)]
+#[derive(Clone)]
+pub struct RegionHistogramCell {
+    #[primary_key]
+    pub id: u32,
+    #[index(btree)]
+    pub world_id: u32,
+    pub cell_x: i32,
+    pub cell_y: i32,
+    pub cell_z: i32,
+    pub count: u32,
+    pub cell_size: f32,
+    pub queried_at: Timestamp,
+}
+
+/// Result of `list_entities_in_region`: one row per entity inside the queried box.
+/// Each query replaces the previous rows for its world.
+#[table(name = region_result, public)]
+#[derive(Clone)]
+pub struct RegionResult {
+    #[primary_key]
+    pub id: u32,
+    #[index(btree)]
+    pub world_id: u32,
+    pub entity_id: u32,
+    pub queried_at: Timestamp,
+}
+
+/// Result of the last `get_physics_transform` query for an entity: the pose read
+/// straight from the rigid body, which `EntityTransform` only catches up with on the next tick.
+#[table(name = physics_transform_result, public)]
+#[derive(Clone)]
+pub struct PhysicsTransformResult {
+    #[primary_key]
+    pub entity_id: u32,
+    pub world_id: u32,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub rx: f32,
+    pub ry: f32,
+    pub rz: f32,
+    pub rw: f32,
+    pub queried_at: Timestamp,
+}
+
+impl PhysicsTransformResult {
+    pub(crate) fn from_pose(
+        world_id: u32,
+        entity_id: u32,
+        pose: &Isometry<Real>,
+        queried_at: Timestamp,
+    ) -> Self {
+        // Same layout as `EntityTransform`, but read now rather than at the end of the last tick
+        let transform = EntityTransform::from_pose(world_id, entity_id, pose);
+        PhysicsTransformResult {
+            entity_id,
+            world_id,
+            x: transform.x,
+            y: transform.y,
+            z: transform.z,
+            rx: transform.rx,
+            ry: transform.ry,
+            rz: transform.rz,
+            rw: transform.rw,
+            queried_at,
+        }
+    }
+}
+
+/// Result of `list_joints`: one row per joint of the queried world.
+/// Each query replaces the previous rows for its world.
+#[table(name = joint_list_result, public)]
+#[derive(Clone)]
+pub struct JointListResult {
+    #[primary_key]
+    pub joint_id: u32,
+    #[index(btree)]
+    pub world_id: u32,
+    pub kind: JointKind,
+    #[index(btree)]
+    pub entity_a: u32,
+    #[index(btree)]
+    pub entity_b: u32,
+    pub queried_at: Timestamp,
+}
+
+/// Result of the last `raycast_from_entity` for a source entity. `hit_entity` is `None`
+/// for the ground, or when nothing was hit (`hit` false).
+#[table(name = entity_raycast_result, public)]
+#[derive(Clone)]
+pub struct EntityRaycastResult {
+    #[primary_key]
+    pub entity_id: u32,
+    pub hit: bool,
+    pub hit_entity: Option<u32>,
+    pub distance: f32,
+    pub queried_at: Timestamp,
+}
+
+/// Result of the last `sweep_entity` for an entity. `fraction` is how much of the
+/// requested offset is free (1 when `blocked` is false). `blocking_entity` is `None` for
+/// the ground or when nothing blocks.
+#[table(name = sweep_result, public)]
+#[derive(Clone)]
+pub struct SweepResult {
+    #[primary_key]
+    pub entity_id: u32,
+    pub blocked: bool,
+    pub fraction: f32,
+    pub blocking_entity: Option<u32>,
+    pub queried_at: Timestamp,
+}
+
+/// Result of the last `project_point` query in a world: the closest point on any
+/// collider's surface. `entity_id` is `None` for the ground.
+#[table(name = point_projection_result, public)]
+#[derive(Clone)]
+pub struct PointProjectionResult {
+    #[primary_key]
+    pub world_id: u32,
+    /// `false` if nothing was within `max_distance`; the other fields are then zero.
+    pub found: bool,
+    pub point: Vec3,
+    /// Whether the queried point was inside the collider.
+    pub is_inside: bool,
+    pub distance: f32,
+    pub entity_id: Option<u32>,
+    pub queried_at: Timestamp,
+}
+
+/// Result of `raycast_all`: one row per collider hit, `rank` 0 being the nearest.
+/// Each query replaces the previous rows with the same `query_id`.
+#[table(name = raycast_hit, public)]
+#[derive(Clone)]
+pub struct RaycastHit {
+    #[primary_key]
+    pub id: u32,
+    #[index(btree)]
+    pub query_id: u32,
+    pub world_id: u32,
+    pub rank: u32,
+    /// `None` for colliders that aren't an entity (the ground).
+    pub entity_id: Option<u32>,
+    pub distance: f32,
+    pub queried_at: Timestamp,
+}
+
+/// Result of `query_by_tag`: one row per entity carrying the tag.
+/// Each query replaces the previous rows for its tag.
+#[table(name = tag_query_result, public)]
+#[derive(Clone)]
+pub struct TagQueryResult {
+    #[primary_key]
+    pub id: u32,
+    #[index(btree)]
+    pub tag: u32,
+    #[index(btree)]
+    pub entity_id: u32,
+    pub queried_at: Timestamp,
+}
+
+/// Result of the last `is_grounded` query for an entity.
+#[table(name = grounded_result, public)]
+#[derive(Clone)]
+pub struct GroundedResult {
+    #[primary_key]
+    pub entity_id: u32,
+    pub grounded: bool,
+    pub queried_at: Timestamp,
+}
+
+/// Velocity of a body stashed by `freeze_entity`, restored by `unfreeze_entity`.
+/// A row means the entity is currently frozen.
+#[table(name = frozen_state, public)]
+#[derive(Clone)]
+pub struct FrozenState {
+    #[primary_key]
+    pub entity_id: u32,
+    pub linvel: Vec3,
+    pub angvel: Vec3,
+    pub frozen_at: Timestamp,
+}
+
+/// Result of the last `get_mass` query for an entity. `mass` is 0 for fixed and
+/// kinematic bodies, which impulses can't move.
+#[table(name = mass_result, public)]
+#[derive(Clone)]
+pub struct MassResult {
+    #[primary_key]
+    pub entity_id: u32,
+    pub mass: f32,
+    /// World-space center of mass.
+    pub center_of_mass: Vec3,
+    pub queried_at: Timestamp,
+}
+
+impl MassResult {
+    pub(crate) fn from_body(entity_id: u32, rigid_body: &RigidBody, queried_at: Timestamp) -> Self {
+        // Includes collider-derived mass and any explicit mass, e.g. from `SpawnOptions::mass`
+        let mass = if rigid_body.is_dynamic() {
+            rigid_body.mass()
+        } else {
+            0.0
+        };
+        MassResult {
+            entity_id,
+            mass,
+            center_of_mass: rigid_body.center_of_mass().coords.into(),
+            queried_at,
+        }
+    }
+}
+
+/// Result of the last `is_moving` query for an entity.
+#[table(name = moving_result, public)]
+#[derive(Clone)]
+pub struct MovingResult {
+    #[primary_key]
+    pub entity_id: u32,
+    pub moving: bool,
+    pub threshold: f32,
+    pub queried_at: Timestamp,
+}
+
+/// Reaction attached to an entity with `add_collision_trigger`. Dropped along with the
+/// entity.
+#[table(name = collision_trigger, public)]
+#[derive(Clone)]
+pub struct CollisionTrigger {
+    #[primary_key]
+    pub id: u32,
+    pub world_id: u32,
+    #[index(btree)]
+    pub entity_id: u32,
+    pub handler: CollisionHandler,
+}
+
+/// One pending `handle_collision_trigger` call, inserted by the tick for a trigger whose
+/// entity started a collision. `other_entity` is `None` for the ground.
+#[table(name = collision_dispatch, scheduled(handle_collision_trigger))]
+#[derive(Clone)]
+pub struct CollisionDispatch {
+    #[primary_key]
+    pub(crate) scheduled_id: u64,
+    pub scheduled_at: ScheduleAt,
+    pub world_id: u32,
+    pub entity_id: u32,
+    pub other_entity: Option<u32>,
+    pub handler: CollisionHandler,
+    pub tick: u64,
+}
+
+/// Contacts recorded by `CollisionHandler::Log` triggers.
+#[table(name = collision_trigger_log, public)]
+#[derive(Clone)]
+pub struct CollisionTriggerLog {
+    #[primary_key]
+    pub id: u64,
+    #[index(btree)]
+    pub world_id: u32,
+    pub entity_id: u32,
+    pub other_entity: Option<u32>,
+    pub tick: u64,
+}
+
+impl CollisionTriggerLog {
+    pub(crate) fn from_dispatch(id: u64, dispatch: &CollisionDispatch) -> Self {
+        CollisionTriggerLog {
+            id,
+            world_id: dispatch.world_id,
+            entity_id: dispatch.entity_id,
+            other_entity: dispatch.other_entity,
+            tick: dispatch.tick,
+        }
+    }
+}
+
+#[table(name = physics_tick_timer, scheduled(process_physics_tick))]
+pub struct PhysicsTickTimer {
+    #[primary_key]
+    pub(crate) id: u64, // Same as world_id
+    pub world_id: u32,
+    pub scheduled_at: ScheduleAt,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rapier3d::na::{UnitQuaternion, Vector3};
+
+    #[test]
+    fn metrics_count_spawned_bodies_and_time_since_last_tick() {
+        let mut state = PhysicsState::new(0);
+        for i in 0..3 {
+            state.add_test_ball(Vector3::new(i as f32 * 3.0, 5.0, 0.0));
+        }
+        state.step_once();
+
+        let first = PhysicsMetrics::from_state(
+            &state,
+            Timestamp::from_micros_since_unix_epoch(1_000),
+            None,
+        );
+        assert_eq!(first.body_count, 3);
+        assert_eq!(first.active_body_count, 3);
+        assert_eq!(first.tick_delta_micros, 0);
+
+        let second = PhysicsMetrics::from_state(
+            &state,
+            Timestamp::from_micros_since_unix_epoch(17_000),
+            Some(&first),
+        );
+        assert_eq!(second.tick_delta_micros, 16_000);
+    }
+
+    #[test]
+    fn prev_transform_holds_the_pre_step_pose() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(1.0, 5.0, -2.0));
+        let before = *state.rigid_body_set[rb_handle].position();
+        let prev: EntityTransformPrev = EntityTransform::from_pose(0, 7, &before).into();
+        state.step_once();
+        let current = EntityTransform::from_pose(0, 7, state.rigid_body_set[rb_handle].position());
+
+        assert_eq!(prev.entity_id, 7);
+        assert_eq!((prev.x, prev.y, prev.z), (1.0, 5.0, -2.0));
+        assert_eq!(prev.rw, 1.0);
+        assert_eq!((current.x, current.z), (prev.x, prev.z));
+        assert!(current.y < prev.y);
+    }
+
+    #[test]
+    fn body_count_reports_all_bodies_and_the_awake_ones() {
+        let mut state = PhysicsState::new(4);
+        let handles: Vec<RigidBodyHandle> = (0..5)
+            .map(|i| state.add_test_ball(Vector3::new(i as f32 * 3.0, 5.0, 0.0)))
+            .collect();
+        state.step_once();
+        state.rigid_body_set[handles[0]].sleep();
+        state.step_once();
+
+        let row = BodyCountResult::from_state(&state, Timestamp::from_micros_since_unix_epoch(0));
+        assert_eq!(row.world_id, 4);
+        assert_eq!(row.body_count, 5);
+        assert_eq!(row.active_body_count, 4);
+    }
+
+    #[test]
+    fn physics_transform_sees_a_teleport_before_the_next_tick() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 5.0, 0.0));
+        state.step_once();
+        let published =
+            EntityTransform::from_pose(0, 3, state.rigid_body_set[rb_handle].position());
+
+        let body = &mut state.rigid_body_set[rb_handle];
+        body.set_translation(Vector3::new(8.0, 2.0, -1.0), true);
+        body.set_rotation(Rotation::from_axis_angle(&Vector3::y_axis(), 1.0), true);
+        let queried_at = Timestamp::from_micros_since_unix_epoch(42);
+        let row = PhysicsTransformResult::from_pose(0, 3, body.position(), queried_at);
+
+        assert_eq!((row.x, row.y, row.z), (8.0, 2.0, -1.0));
+        assert!((row.ry - 0.5f32.sin()).abs() < 1.0e-6);
+        assert!((row.rw - 0.5f32.cos()).abs() < 1.0e-6);
+        assert_eq!((row.entity_id, row.queried_at), (3, queried_at));
+        // The published row still has the pre-teleport pose until the tick writes it
+        assert!(published.y > 4.0 && published.x == 0.0);
+    }
+
+    #[test]
+    fn velocity_row_reports_the_speed_of_a_shoved_body() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 10.0, 0.0));
+        // 3-4-5 triangle: a 5 m/s shove
+        state.rigid_body_set[rb_handle].set_linvel(Vector3::new(3.0, 0.0, 4.0), true);
+
+        let row = EntityVelocity::from_body(2, &state.rigid_body_set[rb_handle]);
+        assert_eq!(row.entity_id, 2);
+        assert!((row.speed - 5.0).abs() < 1.0e-4, "speed {}", row.speed);
+        assert!((row.vx - 3.0).abs() < 1.0e-4 && (row.vz - 4.0).abs() < 1.0e-4);
+
+        state.step_once();
+        let row = EntityVelocity::from_body(2, &state.rigid_body_set[rb_handle]);
+        let expected = (row.vx.powi(2) + row.vy.powi(2) + row.vz.powi(2)).sqrt();
+        assert!(row.vy < 0.0);
+        assert!((row.speed - expected).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn faster_body_reports_more_energy_and_spin_counts_as_angular() {
+        let mut state = PhysicsState::new(0);
+        let fast = state.add_test_ball(Vector3::new(0.0, 10.0, 0.0));
+        let slow = state.add_test_ball(Vector3::new(5.0, 10.0, 0.0));
+        let spinning = state.add_test_ball(Vector3::new(10.0, 10.0, 0.0));
+        // Let the mass properties settle before setting velocities
+        state.step_once();
+        state.rigid_body_set[fast].set_linvel(Vector3::new(10.0, 0.0, 0.0), true);
+        state.rigid_body_set[slow].set_linvel(Vector3::new(1.0, 0.0, 0.0), true);
+        state.rigid_body_set[spinning].set_linvel(Vector3::zeros(), true);
+        state.rigid_body_set[spinning].set_angvel(Vector3::new(0.0, 5.0, 0.0), true);
+
+        let energy =
+            |handle: RigidBodyHandle| EntityEnergy::from_body(0, 1, &state.rigid_body_set[handle]);
+        let (fast, slow, spinning) = (energy(fast), energy(slow), energy(spinning));
+        let mass = 4.0 / 3.0 * std::f32::consts::PI;
+        assert!(
+            (fast.linear - 50.0 * mass).abs() < 1.0e-2,
+            "fast {}",
+            fast.linear
+        );
+        assert!((slow.linear - 0.5 * mass).abs() < 1.0e-3);
+        assert!(fast.total > 50.0 * slow.total);
+        assert!(fast.angular < 1.0e-3);
+
+        // Solid ball: I = 2/5 m r², so 0.5 * I * ω² = 5 m
+        assert_eq!(spinning.linear, 0.0);
+        assert!(
+            (spinning.angular - 5.0 * mass).abs() < 1.0e-2,
+            "spin {}",
+            spinning.angular
+        );
+    }
+
+    #[test]
+    fn aabb_spans_the_half_extents_around_the_body_and_follows_its_rotation() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.rigid_body_set.insert(
+            RigidBodyBuilder::dynamic()
+                .translation(Vector3::new(1.0, 2.0, 3.0))
+                .build(),
+        );
+        let co_handle = state.collider_set.insert_with_parent(
+            ColliderBuilder::cuboid(0.5, 1.0, 1.5).build(),
+            rb_handle,
+            &mut state.rigid_body_set,
+        );
+        let queried_at = Timestamp::from_micros_since_unix_epoch(0);
+        let row = EntityAabbResult::from_collider(0, 7, &state.collider_set[co_handle], queried_at);
+        assert_eq!((row.min.x, row.min.y, row.min.z), (0.5, 1.0, 1.5));
+        assert_eq!((row.max.x, row.max.y, row.max.z), (1.5, 3.0, 4.5));
+
+        // A quarter turn about Y swaps the X and Z extents
+        state.rigid_body_set[rb_handle].set_rotation(
+            UnitQuaternion::from_axis_angle(&Vector3::y_axis(), std::f32::consts::FRAC_PI_2),
+            true,
+        );
+        state.step_once(); // Moves the collider along with its body
+        let row = EntityAabbResult::from_collider(0, 7, &state.collider_set[co_handle], queried_at);
+        let width = row.max.x - row.min.x;
+        let depth = row.max.z - row.min.z;
+        assert!((width - 3.0).abs() < 1.0e-3 && (depth - 1.0).abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn transform_blob_packs_every_entity_pose_sorted_by_id() {
+        let mut state = PhysicsState::new(2);
+        for (entity_id, x) in [(9, 3.0), (4, -3.0), (6, 0.0)] {
+            let rb_handle = state.add_test_ball(Vector3::new(x, 5.0, 1.0));
+            state.handle_to_entity_id.insert(rb_handle, entity_id);
+        }
+        state.step_once();
+
+        let blob = TransformBlob::from_state(&state, Timestamp::from_micros_since_unix_epoch(0));
+        assert_eq!((blob.world_id, blob.entity_count), (2, 3));
+        assert_eq!(blob.data.len(), 3 * 32);
+        let mut unpacked = Vec::new();
+        for record in blob.data.chunks_exact(32) {
+            let entity_id = u32::from_le_bytes(record[..4].try_into().unwrap());
+            let values: Vec<f32> = record[4..]
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect();
+            unpacked.push((entity_id, values));
+        }
+        assert_eq!(
+            unpacked
+                .iter()
+                .map(|(entity_id, _)| *entity_id)
+                .collect::<Vec<_>>(),
+            [4, 6, 9]
+        );
+        for (rb_handle, entity_id) in &state.handle_to_entity_id {
+            let pose = state.rigid_body_set[*rb_handle].position();
+            let (_, values) = unpacked.iter().find(|(id, _)| id == entity_id).unwrap();
+            let (pos, rot) = (pose.translation.vector, pose.rotation);
+            assert_eq!(values, &[pos.x, pos.y, pos.z, rot.i, rot.j, rot.k, rot.w]);
+        }
+    }
+
+    #[test]
+    fn config_row_follows_the_timestep_and_gravity_settings() {
+        let mut state = PhysicsState::new(3);
+        let updated_at = Timestamp::from_micros_since_unix_epoch(0);
+        let row = PhysicsConfig::from_state(&state, updated_at);
+        assert_eq!(row.world_id, 3);
+        assert_eq!(
+            (row.gravity.x, row.gravity.y, row.gravity.z),
+            (0.0, -GRAVITY, 0.0)
+        );
+
+        crate::state::match_tick_interval(&mut state, 33);
+        state.radial_gravity_center = Some(Vector3::new(0.0, -100.0, 0.0));
+        let row = PhysicsConfig::from_state(&state, updated_at);
+        assert_eq!(row.dt, 0.033);
+        // Radial gravity replaces the uniform pull
+        assert_eq!(
+            (row.gravity.x, row.gravity.y, row.gravity.z),
+            (0.0, 0.0, 0.0)
+        );
+        assert_eq!(
+            row.radial_gravity_center.map(|center| center.y),
+            Some(-100.0)
+        );
+    }
+
+    #[test]
+    fn mass_result_sums_colliders_and_zeroes_fixed_bodies() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.rigid_body_set.insert(
+            RigidBodyBuilder::dynamic()
+                .translation(Vector3::new(1.0, 20.0, 3.0))
+                .gravity_scale(0.0)
+                .build(),
+        );
+        for offset in [0.0, 2.0] {
+            state.collider_set.insert_with_parent(
+                ColliderBuilder::ball(1.0)
+                    .translation(Vector3::new(offset, 0.0, 0.0))
+                    .build(),
+                rb_handle,
+                &mut state.rigid_body_set,
+            );
+        }
+        state.step_once();
+        let queried_at = Timestamp::from_micros_since_unix_epoch(0);
+
+        let row = MassResult::from_body(5, &state.rigid_body_set[rb_handle], queried_at);
+        let ball_mass = 4.0 / 3.0 * std::f32::consts::PI;
+        assert_eq!(row.entity_id, 5);
+        assert!(
+            (row.mass - 2.0 * ball_mass).abs() < 1e-3,
+            "mass {}",
+            row.mass
+        );
+        assert!((row.center_of_mass.x - 2.0).abs() < 1e-4);
+        assert!((row.center_of_mass.y - 20.0).abs() < 1e-4);
+        assert!((row.center_of_mass.z - 3.0).abs() < 1e-4);
+
+        state.rigid_body_set[rb_handle].set_body_type(RigidBodyType::Fixed, true);
+        let row = MassResult::from_body(5, &state.rigid_body_set[rb_handle], queried_at);
+        assert_eq!(row.mass, 0.0);
+    }
+
+    #[test]
+    fn mass_result_scales_with_density() {
+        let mut state = PhysicsState::new(0);
+        let balls: Vec<RigidBodyHandle> = [1.0, 3.0]
+            .into_iter()
+            .enumerate()
+            .map(|(i, density)| {
+                let rb_handle = state.rigid_body_set.insert(
+                    RigidBodyBuilder::dynamic()
+                        .translation(Vector3::new(i as f32 * 3.0, 5.0, 0.0))
+                        .build(),
+                );
+                state.collider_set.insert_with_parent(
+                    ColliderBuilder::ball(1.0).density(density).build(),
+                    rb_handle,
+                    &mut state.rigid_body_set,
+                );
+                rb_handle
+            })
+            .collect();
+        state.step_once();
+        let queried_at = Timestamp::from_micros_since_unix_epoch(0);
+
+        let light = MassResult::from_body(1, &state.rigid_body_set[balls[0]], queried_at);
+        let heavy = MassResult::from_body(2, &state.rigid_body_set[balls[1]], queried_at);
+        assert!((light.mass - 4.0 / 3.0 * std::f32::consts::PI).abs() < 1e-3);
+        assert!(
+            (heavy.mass / light.mass - 3.0).abs() < 1e-4,
+            "{} vs {}",
+            heavy.mass,
+            light.mass
+        );
+    }
+}
@@ -0,0 +1,1190 @@
+//! The scheduled physics step.
+
+use log::{info, warn};
+use rapier3d::na::Vector3;
+use rapier3d::prelude::*;
+use spacetimedb::{reducer, ReducerContext, Table};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::grid::rebuild_grid_if_enabled;
+use crate::state::*;
+use crate::triggers::dispatch_collision_triggers;
+use crate::tables::*;
+
+/// Impact rows older than this many ticks are pruned.
+pub(crate) const EVENT_RETENTION_TICKS: u64 = 60;
+
+/// Collects events raised during `step` so they can be written to tables afterwards
+/// (the event handler only gets `&self`, hence the interior mutability).
+#[derive(Default)]
+pub(crate) struct EventCollector {
+    pub(crate) contact_forces: Mutex<Vec<(ColliderHandle, ColliderHandle, f32)>>,
+    pub(crate) collisions: Mutex<Vec<CollisionEvent>>,
+}
+
+impl EventHandler for EventCollector {
+    fn handle_collision_event(
+        &self,
+        _bodies: &RigidBodySet,
+        _colliders: &ColliderSet,
+        event: CollisionEvent,
+        _contact_pair: Option<&ContactPair>,
+    ) {
+        if let Ok(mut collisions) = self.collisions.lock() {
+            collisions.push(event);
+        }
+    }
+
+    fn handle_contact_force_event(
+        &self,
+        _dt: Real,
+        _bodies: &RigidBodySet,
+        _colliders: &ColliderSet,
+        contact_pair: &ContactPair,
+        total_force_magnitude: Real,
+    ) {
+        if let Ok(mut contact_forces) = self.contact_forces.lock() {
+            contact_forces.push((
+                contact_pair.collider1,
+                contact_pair.collider2,
+                total_force_magnitude,
+            ));
+        }
+    }
+}
+
+/// Downward gravitational acceleration applied by every world's step.
+pub(crate) const GRAVITY: f32 = 9.81;
+
+/// Upper bound on fixed-dt steps per tick. Time beyond this is dropped so a slow tick
+/// can't trigger ever-longer catch-up ticks (the "spiral of death").
+pub(crate) const MAX_SUBSTEPS: u32 = 4;
+
+// --- Per-Tick Forces ---
+
+/// Pushes every dynamic body with the world's global wind force.
+pub(crate) fn apply_wind(rigid_body_set: &mut RigidBodySet, wind: Vector3<f32>) {
+    if wind == Vector3::zeros() {
+        return;
+    }
+    for (_, rigid_body) in rigid_body_set.iter_mut() {
+        if rigid_body.is_dynamic() {
+            rigid_body.add_force(wind, true);
+        }
+    }
+}
+
+/// Pulls every dynamic body toward each well with `F = strength * dir / dist²`.
+pub(crate) fn apply_gravity_wells(rigid_body_set: &mut RigidBodySet, wells: &[GravityWell]) {
+    if wells.is_empty() {
+        return;
+    }
+    for (_, rigid_body) in rigid_body_set.iter_mut() {
+        if !rigid_body.is_dynamic() {
+            continue;
+        }
+        let mut force = Vector3::zeros();
+        for well in wells {
+            let to_well = Vector3::new(well.x, well.y, well.z) - rigid_body.translation();
+            // Clamp the distance so bodies passing through the center don't get flung away
+            let dist_sq = to_well.norm_squared().max(1.0);
+            force += to_well.normalize() * (well.strength / dist_sq);
+        }
+        if force.iter().all(|f| f.is_finite()) {
+            rigid_body.add_force(force, true);
+        }
+    }
+}
+
+/// Pushes submerged dynamic bodies up by the weight of the fluid they displace and slows
+/// them down. The submerged part of each collider is estimated from how much of its AABB
+/// lies inside the zone; gravity supplies the body's own weight.
+pub(crate) fn apply_fluid_zones(rigid_body_set: &mut RigidBodySet, collider_set: &ColliderSet, zones: &[FluidZone]) {
+    if zones.is_empty() {
+        return;
+    }
+    for (_, rigid_body) in rigid_body_set.iter_mut() {
+        if !rigid_body.is_dynamic() {
+            continue;
+        }
+        let mut force = Vector3::zeros();
+        for co_handle in rigid_body.colliders() {
+            let Some(collider) = collider_set.get(*co_handle) else {
+                continue;
+            };
+            let aabb = collider.compute_aabb();
+            let aabb_volume = aabb.volume();
+            if aabb_volume <= 0.0 {
+                continue;
+            }
+            // Unit density makes the mass equal to the shape's volume
+            let volume = collider.shape().mass_properties(1.0).mass();
+            for zone in zones {
+                let center = Vector3::new(zone.center.x, zone.center.y, zone.center.z);
+                let half = Vector3::new(zone.half_extents.x, zone.half_extents.y, zone.half_extents.z);
+                let zone_aabb = Aabb::new((center - half).into(), (center + half).into());
+                let Some(overlap) = aabb.intersection(&zone_aabb) else {
+                    continue;
+                };
+                let submerged = overlap.volume() / aabb_volume;
+                force.y += zone.density * GRAVITY * volume * submerged;
+                force -= rigid_body.linvel() * (zone.drag * submerged);
+            }
+        }
+        if force.iter().all(|f| f.is_finite()) && force != Vector3::zeros() {
+            rigid_body.add_force(force, true);
+        }
+    }
+}
+
+/// Accelerates every dynamic body toward `center` at `strength` (m/s²), regardless of
+/// distance, like standing on a planet.
+pub(crate) fn apply_radial_gravity(rigid_body_set: &mut RigidBodySet, center: Vector3<f32>, strength: f32) {
+    for (_, rigid_body) in rigid_body_set.iter_mut() {
+        if !rigid_body.is_dynamic() {
+            continue;
+        }
+        let to_center = center - rigid_body.translation();
+        // A body sitting exactly on the center has no "down"
+        let Some(direction) = to_center.try_normalize(1.0e-6) else {
+            continue;
+        };
+        let force = direction * (strength * rigid_body.mass());
+        rigid_body.add_force(force, true);
+    }
+}
+
+/// Applies the world's radial gravity if it has one and returns the uniform gravity to
+/// step with: radial gravity is the only gravity source when set, so the step gets none.
+pub(crate) fn apply_world_gravity(state: &mut PhysicsState) -> Vector3<f32> {
+    match state.radial_gravity_center {
+        Some(center) => {
+            apply_radial_gravity(&mut state.rigid_body_set, center, state.radial_gravity_strength);
+            Vector3::zeros()
+        }
+        None => Vector3::new(0.0, -GRAVITY, 0.0),
+    }
+}
+
+/// Adds the force of every field containing a dynamic body's origin to that body.
+pub(crate) fn apply_force_fields(rigid_body_set: &mut RigidBodySet, fields: &[ForceField]) {
+    if fields.is_empty() {
+        return;
+    }
+    for (_, rigid_body) in rigid_body_set.iter_mut() {
+        if !rigid_body.is_dynamic() {
+            continue;
+        }
+        let position = rigid_body.translation();
+        let mut force = Vector3::zeros();
+        for field in fields {
+            let center = Vector3::new(field.center.x, field.center.y, field.center.z);
+            let half = Vector3::new(field.half_extents.x, field.half_extents.y, field.half_extents.z);
+            if (position - center).iter().zip(half.iter()).all(|(d, h)| d.abs() <= *h) {
+                force += Vector3::new(field.force.x, field.force.y, field.force.z);
+            }
+        }
+        if force != Vector3::zeros() {
+            rigid_body.add_force(force, true);
+        }
+    }
+}
+
+/// Applies equal and opposite `strength / dist²` forces between each pair of bodies.
+pub(crate) fn apply_magnets(rigid_body_set: &mut RigidBodySet, magnets: &[(RigidBodyHandle, RigidBodyHandle, f32)]) {
+    for (rb_a, rb_b, strength) in magnets {
+        let (Some(a), Some(b)) = (rigid_body_set.get(*rb_a), rigid_body_set.get(*rb_b)) else {
+            continue;
+        };
+        let a_to_b = b.translation() - a.translation();
+        // Same clamp as gravity wells so overlapping bodies don't get flung apart
+        let dist_sq = a_to_b.norm_squared().max(1.0);
+        let force = a_to_b.normalize() * (strength / dist_sq);
+        if !force.iter().all(|f| f.is_finite()) {
+            continue;
+        }
+        if let Some(a) = rigid_body_set.get_mut(*rb_a) {
+            a.add_force(force, true);
+        }
+        if let Some(b) = rigid_body_set.get_mut(*rb_b) {
+            b.add_force(-force, true);
+        }
+    }
+}
+
+/// Stiffness (1/s²) and damping (1/s) of pulley ropes, per unit of the pair's effective
+/// mass. Critically damped, and stiff enough to sag only ~2.5cm under gravity.
+pub(crate) const PULLEY_STIFFNESS: f32 = 400.0;
+pub(crate) const PULLEY_DAMPING: f32 = 40.0;
+
+/// Pulls both bodies of each pulley toward their anchors with the same rope tension
+/// whenever the two anchor distances add up to more than the rope length.
+pub(crate) fn apply_pulleys(
+    rigid_body_set: &mut RigidBodySet,
+    pulleys: &[(RigidBodyHandle, RigidBodyHandle, PulleyConstraint)],
+) {
+    for (rb_a, rb_b, pulley) in pulleys {
+        let (Some(a), Some(b)) = (rigid_body_set.get(*rb_a), rigid_body_set.get(*rb_b)) else {
+            continue;
+        };
+        let anchor_a = Vector3::new(pulley.anchor_a.x, pulley.anchor_a.y, pulley.anchor_a.z);
+        let anchor_b = Vector3::new(pulley.anchor_b.x, pulley.anchor_b.y, pulley.anchor_b.z);
+        let to_anchor_a = anchor_a - a.translation();
+        let to_anchor_b = anchor_b - b.translation();
+        let (dist_a, dist_b) = (to_anchor_a.norm(), to_anchor_b.norm());
+        if dist_a < 1.0e-6 || dist_b < 1.0e-6 {
+            continue;
+        }
+        let (dir_a, dir_b) = (to_anchor_a / dist_a, to_anchor_b / dist_b);
+
+        // A slack rope pushes nothing; a taut one resists stretching and its rate
+        let stretch = dist_a + dist_b - pulley.length;
+        let stretch_rate = -a.linvel().dot(&dir_a) - b.linvel().dot(&dir_b);
+        let (mass_a, mass_b) = (a.mass(), b.mass());
+        let effective_mass = if mass_a > 0.0 && mass_b > 0.0 {
+            mass_a * mass_b / (mass_a + mass_b)
+        } else {
+            mass_a.max(mass_b) // One side fixed: only the other body is pulled
+        };
+        let tension = effective_mass * (PULLEY_STIFFNESS * stretch + PULLEY_DAMPING * stretch_rate);
+        if stretch <= 0.0 || tension <= 0.0 || !tension.is_finite() {
+            continue;
+        }
+        if let Some(a) = rigid_body_set.get_mut(*rb_a) {
+            a.add_force(dir_a * tension, true);
+        }
+        if let Some(b) = rigid_body_set.get_mut(*rb_b) {
+            b.add_force(dir_b * tension, true);
+        }
+    }
+}
+
+// --- Stepping ---
+
+/// Adds the time since the previous tick to the world's accumulator and returns how many
+/// whole `dt` steps to run now.
+pub(crate) fn take_substeps(state: &mut PhysicsState, now_micros: i64) -> u32 {
+    // Fixed-timestep accumulator: consume the real time since the previous tick in whole
+    // steps of `dt`, so late ticks catch up smoothly and the result stays deterministic.
+    // Integer microseconds avoid float drift turning a 16ms gap into 0 or 2 steps.
+    let dt_micros = ((state.integration_parameters.dt * 1_000_000.0).round() as i64).max(1);
+    let elapsed_micros = match state.last_tick_micros {
+        Some(last) => (now_micros - last).max(0),
+        None => dt_micros, // First tick: assume exactly one step
+    };
+    state.last_tick_micros = Some(now_micros);
+    state.time_accumulator_micros += elapsed_micros;
+    let substeps = (state.time_accumulator_micros / dt_micros) as u32;
+    if state.deterministic {
+        // Wall-clock jitter would change the step count between runs, so advance
+        // by exactly one `dt` per tick and let the simulation lag real time instead
+        state.time_accumulator_micros = 0;
+        1
+    } else if substeps > MAX_SUBSTEPS {
+        info!("Physics fell behind: dropping {} steps", substeps - MAX_SUBSTEPS);
+        state.time_accumulator_micros = 0;
+        MAX_SUBSTEPS
+    } else {
+        state.time_accumulator_micros -= substeps as i64 * dt_micros;
+        substeps
+    }
+}
+
+/// Rapier keeps user forces and torques until they are reset, so the tick clears them
+/// once a step has consumed them.
+pub(crate) fn clear_user_forces(rigid_body_set: &mut RigidBodySet) {
+    for (_, rigid_body) in rigid_body_set.iter_mut() {
+        if rigid_body.is_dynamic() {
+            rigid_body.reset_forces(false);
+            rigid_body.reset_torques(false);
+        }
+    }
+}
+
+/// Entity bodies whose pose the tick writes back: awake dynamic ones. Sleeping bodies
+/// haven't moved since their last write, and the other types only move on request.
+pub(crate) fn moved_entity_bodies(state: &PhysicsState) -> impl Iterator<Item = (u32, &RigidBody)> {
+    state.rigid_body_set.iter().filter_map(|(handle, rigid_body)| {
+        let entity_id = *state.handle_to_entity_id.get(&handle)?;
+        (rigid_body.is_dynamic() && !rigid_body.is_sleeping()).then_some((entity_id, rigid_body))
+    })
+}
+
+/// Collisions to record in `CollisionLog`: non-sensor ones involving at least one entity
+/// opted in with `set_collision_events`.
+pub(crate) fn logged_collisions<'a>(
+    collisions: &'a [CollisionEvent],
+    collider_to_entity_id: &'a HashMap<ColliderHandle, u32>,
+    collision_logging: &'a HashSet<u32>,
+) -> impl Iterator<Item = &'a CollisionEvent> {
+    let logged = |co_handle: ColliderHandle| {
+        collider_to_entity_id
+            .get(&co_handle)
+            .is_some_and(|entity_id| collision_logging.contains(entity_id))
+    };
+    collisions
+        .iter()
+        .filter(move |event| !event.sensor() && (logged(event.collider1()) || logged(event.collider2())))
+}
+
+/// Runs `substeps` steps of the world's `dt` scaled by its time scale, collecting events
+/// into `events`. The stored dt stays in lockstep with the tick interval.
+pub(crate) fn step_world(
+    state: &mut PhysicsState,
+    substeps: u32,
+    gravity: &Vector3<f32>,
+    events: &EventCollector,
+) {
+    // Destructure the state completely for the step call.
+    // This provides mutable borrows to the fields required by physics_pipeline.step
+    // without violating Rust's borrowing rules (can borrow disjoint fields from a mutable reference).
+    let PhysicsState {
+        rigid_body_set,
+        collider_set,
+        integration_parameters,
+        physics_pipeline,
+        island_manager,
+        broad_phase,
+        narrow_phase,
+        impulse_joint_set,
+        multibody_joint_set,
+        ccd_solver,
+        query_pipeline,
+        hooks,
+        time_scale,
+        ..
+    } = state;
+
+    // Scale dt for these steps only
+    let base_dt = integration_parameters.dt;
+    integration_parameters.dt = base_dt * *time_scale;
+    for _ in 0..substeps {
+        physics_pipeline.step(
+            gravity,
+            integration_parameters,
+            island_manager,
+            broad_phase,
+            narrow_phase,
+            rigid_body_set,
+            collider_set,
+            impulse_joint_set,
+            multibody_joint_set,
+            ccd_solver,
+            Some(&mut *query_pipeline), // Keep scene queries in sync with the stepped world
+            &*hooks, // physics_hooks
+            events,  // event_handler
+        );
+    }
+    integration_parameters.dt = base_dt;
+}
+
+/// Scales every dynamic body's linear velocity above `max_speed` back down to it,
+/// keeping its direction.
+pub(crate) fn clamp_velocities(rigid_body_set: &mut RigidBodySet, max_speed: f32) {
+    for (_, rigid_body) in rigid_body_set.iter_mut() {
+        if !rigid_body.is_dynamic() {
+            continue;
+        }
+        let speed = rigid_body.linvel().norm();
+        if speed > max_speed {
+            let clamped = rigid_body.linvel() * (max_speed / speed);
+            rigid_body.set_linvel(clamped, false);
+        }
+    }
+}
+
+// --- Reducers ---
+
+#[reducer]
+pub fn process_physics_tick(ctx: &ReducerContext, timer: PhysicsTickTimer) -> Result<(), String> {
+    // Removed start time logging
+    // let start_time = Instant::now();
+    // info!("process_physics_tick started at {:?}", start_time);
+
+    // Log invocation using context timestamp (less precise for duration, but available)
+    // Access timestamp as a field, not a method
+    info!("process_physics_tick invoked. Timestamp: {}", ctx.timestamp);
+
+    let world_id = timer.world_id;
+    let gravity_wells: Vec<GravityWell> = ctx.db.gravity_well().world_id().filter(world_id).collect();
+    let fluid_zones: Vec<FluidZone> = ctx.db.fluid_zone().world_id().filter(world_id).collect();
+    let force_fields: Vec<ForceField> = ctx.db.force_field().world_id().filter(world_id).collect();
+    let mut magnets = Vec::new();
+    for magnet in ctx.db.magnet_pair().world_id().filter(world_id) {
+        let (_, rb_a, _) = get_entity_handles(ctx, magnet.entity_a)?;
+        let (_, rb_b, _) = get_entity_handles(ctx, magnet.entity_b)?;
+        magnets.push((rb_a, rb_b, magnet.strength));
+    }
+    let mut pulleys = Vec::new();
+    for pulley in ctx.db.pulley_constraint().world_id().filter(world_id) {
+        let (_, rb_a, _) = get_entity_handles(ctx, pulley.entity_a)?;
+        let (_, rb_b, _) = get_entity_handles(ctx, pulley.entity_b)?;
+        pulleys.push((rb_a, rb_b, pulley));
+    }
+
+    let mut worlds = lock_state();
+    // The map is in-memory only, so after a module restart the timer rows outlive it:
+    // recreate the world rather than failing every tick
+    let state = worlds
+        .entry(world_id)
+        .or_insert_with(|| PhysicsState::new(world_id));
+
+    // Remember pre-step positions so clients can interpolate between the two rows
+    for (handle, rigid_body) in state.rigid_body_set.iter() {
+        if !rigid_body.is_dynamic() || rigid_body.is_sleeping() {
+            continue;
+        }
+        if let Some(entity_id) = state.handle_to_entity_id.get(&handle) {
+            let transform = EntityTransform::from_pose(world_id, *entity_id, rigid_body.position());
+            ctx.db
+                .entity_transform_prev()
+                .entity_id()
+                .update(transform.into());
+        }
+    }
+
+    // Per-tick forces: added before the steps and cleared right after them
+    apply_gravity_wells(&mut state.rigid_body_set, &gravity_wells);
+    apply_fluid_zones(&mut state.rigid_body_set, &state.collider_set, &fluid_zones);
+    apply_force_fields(&mut state.rigid_body_set, &force_fields);
+    let uniform_gravity = apply_world_gravity(state);
+    apply_magnets(&mut state.rigid_body_set, &magnets);
+    apply_pulleys(&mut state.rigid_body_set, &pulleys);
+    apply_wind(&mut state.rigid_body_set, state.wind);
+
+    let substeps = take_substeps(state, ctx.timestamp.to_micros_since_unix_epoch());
+    let events = EventCollector::default();
+    step_world(state, substeps, &uniform_gravity, &events);
+    state.tick += 1;
+    // From here on the in-memory world has moved on. Failing the reducer would roll back
+    // only the table writes and leave them out of step with it for good, so errors past
+    // this point are logged and the tick carries on.
+
+    // Destructure the state for the post-step bookkeeping.
+    // This provides mutable borrows to the fields required below
+    // without violating Rust's borrowing rules (can borrow disjoint fields from a mutable reference).
+    let PhysicsState {
+        world_id: _,
+        rigid_body_set,
+        collider_set: _,
+        integration_parameters: _,
+        physics_pipeline: _,
+        island_manager: _,
+        broad_phase: _,
+        narrow_phase: _,
+        impulse_joint_set: _,
+        multibody_joint_set: _,
+        ccd_solver: _,
+        query_pipeline: _,
+        handle_to_entity_id: _,
+        collider_to_entity_id,
+        tick,
+        max_bodies: _,
+        wind: _,
+        sleep_thresholds: _,
+        time_scale: _,
+        last_tick_micros: _,
+        time_accumulator_micros: _,
+        max_velocity,
+        contact_force_threshold: _,
+        hooks: _,
+        deterministic: _,
+        collision_logging,
+        seeded_spawns: _,
+        kill_plane_y: _,
+        recording: _,
+        radial_gravity_center: _,
+        radial_gravity_strength: _,
+        awake_ticks: _,
+    } = &mut *state; // Reborrow the world so `state` is usable again afterwards
+
+    // Record impacts above the threshold and prune old ones.
+    // Colliders that don't belong to an entity (the ground) are reported as None.
+    let contact_forces = events
+        .contact_forces
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let stale_impacts: Vec<u64> = ctx
+        .db
+        .impact_event()
+        .world_id()
+        .filter(world_id)
+        .filter(|impact| impact.tick + EVENT_RETENTION_TICKS < *tick)
+        .map(|impact| impact.id)
+        .collect();
+    for id in &stale_impacts {
+        ctx.db.impact_event().id().delete(id);
+    }
+    let mut next_impact_id = ctx
+        .db
+        .impact_event()
+        .iter()
+        .map(|impact| impact.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    for (collider1, collider2, total_force_magnitude) in contact_forces {
+        let inserted = ctx.db.impact_event().try_insert(ImpactEvent {
+            id: next_impact_id,
+            world_id,
+            entity_a: collider_to_entity_id.get(&collider1).copied(),
+            entity_b: collider_to_entity_id.get(&collider2).copied(),
+            total_force_magnitude,
+            tick: *tick,
+        });
+        if let Err(e) = inserted {
+            warn!("Failed to record impact in world {}: {}", world_id, e);
+        }
+        next_impact_id += 1;
+    }
+
+    // Log collisions of opted-in bodies. Rapier also raises them for bodies that only
+    // have a collision trigger, so check the opt-in rather than the collider flag.
+    let collisions = events
+        .collisions
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let stale_collisions: Vec<u64> = ctx
+        .db
+        .collision_log()
+        .world_id()
+        .filter(world_id)
+        .filter(|collision| collision.tick + EVENT_RETENTION_TICKS < *tick)
+        .map(|collision| collision.id)
+        .collect();
+    for id in &stale_collisions {
+        ctx.db.collision_log().id().delete(id);
+    }
+    let mut next_collision_id = ctx
+        .db
+        .collision_log()
+        .iter()
+        .map(|collision| collision.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    for event in logged_collisions(&collisions, collider_to_entity_id, collision_logging) {
+        let inserted = ctx.db.collision_log().try_insert(CollisionLog {
+            id: next_collision_id,
+            world_id,
+            entity_a: collider_to_entity_id.get(&event.collider1()).copied(),
+            entity_b: collider_to_entity_id.get(&event.collider2()).copied(),
+            started: event.started(),
+            tick: *tick,
+        });
+        if let Err(e) = inserted {
+            warn!("Failed to log collision in world {}: {}", world_id, e);
+        }
+        next_collision_id += 1;
+    }
+
+    if let Err(e) = dispatch_collision_triggers(ctx, world_id, *tick, &collisions, collider_to_entity_id) {
+        warn!("Failed to dispatch collision triggers in world {}: {}", world_id, e);
+    }
+
+    // Update trigger zone membership from sensor intersection events
+    for event in collisions {
+        if !event.sensor() {
+            continue;
+        }
+        let (Some(entity1), Some(entity2)) = (
+            collider_to_entity_id.get(&event.collider1()).copied(),
+            collider_to_entity_id.get(&event.collider2()).copied(),
+        ) else {
+            continue;
+        };
+        for (zone_id, other_id) in [(entity1, entity2), (entity2, entity1)] {
+            let Some(mut zone) = ctx.db.trigger_state().zone_id().find(zone_id) else {
+                continue;
+            };
+            if zone.record_intersection(other_id, event.started()) {
+                ctx.db.trigger_state().zone_id().update(zone);
+            }
+        }
+    }
+
+    // Safety net against numerical blowups: scale runaway velocities back to the cap
+    if let Some(max_speed) = *max_velocity {
+        clamp_velocities(rigid_body_set, max_speed);
+    }
+
+    clear_user_forces(rigid_body_set);
+
+    // Removed post-step logging loop
+
+    // The borrow from the destructuring above ends here.
+    // Now, re-access the state fields needed for the loop via the original `state` reference.
+    // This is safe because the previous mutable borrow from destructuring is finished.
+    for (entity_id, rigid_body) in moved_entity_bodies(state) {
+        // Removed physics tick + velocity/sleeping/type logs
+
+        // Construct the struct with the updated data
+        let updated_transform =
+            EntityTransform::from_pose(world_id, entity_id, rigid_body.position());
+
+        // Use the .update() method, accessed via the primary key index.
+        // Assuming it returns () on success or panics on failure (e.g., row not found).
+        ctx.db
+            .entity_transform()
+            .entity_id()
+            .update(updated_transform);
+        ctx.db
+            .entity_velocity()
+            .entity_id()
+            .update(EntityVelocity::from_body(entity_id, rigid_body));
+
+        // Note: We removed the find/delete/insert logic and match statement.
+        // The .update() method should handle finding and updating the row based on the primary key.
+    }
+
+    // Track how long each body has stayed awake, for `nudge_stuck_bodies`
+    count_awake_ticks(state);
+
+    // Count down lifetimes and despawn expired entities
+    let lifetimes: Vec<EntityLifetime> =
+        ctx.db.entity_lifetime().world_id().filter(world_id).collect();
+    for mut lifetime in lifetimes {
+        if lifetime.tick_down() {
+            if let Err(e) = remove_entity(ctx, state, lifetime.entity_id) {
+                warn!("Failed to despawn expired entity {}: {}", lifetime.entity_id, e);
+            }
+        } else {
+            ctx.db.entity_lifetime().entity_id().update(lifetime);
+        }
+    }
+
+    // Despawn bodies that fell off the world
+    if let Some(threshold) = state.kill_plane_y {
+        for entity_id in dynamic_entities_below(state, threshold) {
+            if let Err(e) = remove_entity(ctx, state, entity_id) {
+                warn!("Failed to despawn entity {} below the kill plane: {}", entity_id, e);
+            }
+        }
+    }
+
+    // Record metrics; the timestamp delta approximates the removed duration logging
+    let previous_metrics = ctx.db.physics_metrics().world_id().find(world_id);
+    let metrics = PhysicsMetrics::from_state(state, ctx.timestamp, previous_metrics.as_ref());
+    if previous_metrics.is_some() {
+        ctx.db.physics_metrics().world_id().update(metrics);
+    } else if let Err(e) = ctx.db.physics_metrics().try_insert(metrics) {
+        warn!("Failed to record metrics for world {}: {}", world_id, e);
+    }
+
+    // Queries against the grid only need the transforms written above
+    drop(worlds);
+    rebuild_grid_if_enabled(ctx, world_id);
+
+    info!("process_physics_tick finished."); // Simple finish log
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Vec3;
+
+    #[test]
+    fn gravity_well_pulls_a_still_body_toward_it() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 10.0, 0.0));
+        let well = GravityWell {
+            id: 1,
+            world_id: 0,
+            x: 10.0,
+            y: 10.0,
+            z: 0.0,
+            strength: 2000.0,
+        };
+        apply_gravity_wells(&mut state.rigid_body_set, &[well]);
+        state.step_once();
+
+        let body = &state.rigid_body_set[rb_handle];
+        assert!(body.linvel().x > 0.0);
+        assert!(body.translation().x > 0.0);
+        assert_eq!(body.linvel().z, 0.0);
+    }
+
+    #[test]
+    fn horizontal_wind_makes_a_falling_ball_drift() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 20.0, 0.0));
+        apply_wind(&mut state.rigid_body_set, Vector3::new(5.0, 0.0, 0.0));
+        for _ in 0..10 {
+            state.step_once();
+        }
+
+        let body = &state.rigid_body_set[rb_handle];
+        assert!(body.translation().x > 0.0);
+        assert!(body.translation().y < 20.0);
+        assert_eq!(body.translation().z, 0.0);
+    }
+
+    /// How far a weightless ball moving at 4 m/s travels in one tick at `time_scale`.
+    fn distance_per_tick(time_scale: f32) -> f32 {
+        let mut state = PhysicsState::new(0);
+        state.time_scale = time_scale;
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 10.0, 0.0));
+        state.rigid_body_set[rb_handle].set_linvel(Vector3::new(4.0, 0.0, 0.0), true);
+        step_world(&mut state, 1, &Vector3::zeros(), &EventCollector::default());
+        state.rigid_body_set[rb_handle].translation().x
+    }
+
+    #[test]
+    fn half_time_scale_moves_bodies_half_as_far_per_tick() {
+        let full = distance_per_tick(1.0);
+        let half = distance_per_tick(0.5);
+        assert!(full > 0.0);
+        assert!((half / full - 0.5).abs() < 1.0e-4, "{} vs {}", half, full);
+    }
+
+    #[test]
+    fn step_world_restores_the_stored_dt() {
+        let mut state = PhysicsState::new(0);
+        let dt = state.integration_parameters.dt;
+        state.time_scale = 0.25;
+        step_world(&mut state, 2, &Vector3::zeros(), &EventCollector::default());
+        assert_eq!(state.integration_parameters.dt, dt);
+    }
+
+    #[test]
+    fn accumulator_runs_whole_steps_and_carries_the_rest() {
+        let mut state = PhysicsState::new(0);
+        let dt_micros = (state.integration_parameters.dt * 1_000_000.0).round() as i64;
+        assert_eq!(
+            take_substeps(&mut state, 0),
+            1,
+            "first tick assumes one step"
+        );
+
+        // A gap of three and a half steps runs three and keeps the half for later
+        let mut now = 3 * dt_micros + dt_micros / 2;
+        assert_eq!(take_substeps(&mut state, now), 3);
+        assert_eq!(state.time_accumulator_micros, dt_micros / 2);
+        now += dt_micros / 2 + dt_micros / 4;
+        assert_eq!(take_substeps(&mut state, now), 1);
+
+        // Falling far behind is capped and the backlog dropped
+        now += 100 * dt_micros;
+        assert_eq!(take_substeps(&mut state, now), MAX_SUBSTEPS);
+        assert_eq!(state.time_accumulator_micros, 0);
+
+        // Deterministic worlds always advance by a single step
+        state.deterministic = true;
+        now += 3 * dt_micros;
+        assert_eq!(take_substeps(&mut state, now), 1);
+    }
+
+    #[test]
+    fn large_timestamp_gap_advances_by_a_multiple_of_dt() {
+        let mut caught_up = PhysicsState::new(0);
+        let mut stepped = PhysicsState::new(0);
+        let a = caught_up.add_test_ball(Vector3::new(0.0, 20.0, 0.0));
+        let b = stepped.add_test_ball(Vector3::new(0.0, 20.0, 0.0));
+        let dt_micros = (caught_up.integration_parameters.dt * 1_000_000.0).round() as i64;
+        let gravity = Vector3::new(0.0, -GRAVITY, 0.0);
+
+        take_substeps(&mut caught_up, 0);
+        let substeps = take_substeps(&mut caught_up, 3 * dt_micros);
+        assert_eq!(substeps, 3);
+        step_world(
+            &mut caught_up,
+            substeps,
+            &gravity,
+            &EventCollector::default(),
+        );
+        for _ in 0..3 {
+            step_world(&mut stepped, 1, &gravity, &EventCollector::default());
+        }
+        assert_eq!(
+            caught_up.rigid_body_set[a].position(),
+            stepped.rigid_body_set[b].position()
+        );
+    }
+
+    #[test]
+    fn huge_impulse_is_clamped_to_the_max_velocity() {
+        let mut state = PhysicsState::new(0);
+        let fast = state.add_test_ball(Vector3::new(0.0, 50.0, 0.0));
+        let slow = state.add_test_ball(Vector3::new(10.0, 50.0, 0.0));
+        state.rigid_body_set[fast].apply_impulse(Vector3::new(1.0e6, 0.0, 1.0e6), true);
+        state.rigid_body_set[slow].set_linvel(Vector3::new(0.0, 0.0, 5.0), true);
+        state.step_once();
+        clamp_velocities(&mut state.rigid_body_set, 20.0);
+
+        let linvel = state.rigid_body_set[fast].linvel();
+        assert!(
+            (linvel.norm() - 20.0).abs() < 1.0e-3,
+            "speed {}",
+            linvel.norm()
+        );
+        assert!(linvel.x > 0.0 && linvel.z > 0.0);
+        assert!(state.rigid_body_set[slow].linvel().norm() < 20.0);
+        assert_eq!(state.rigid_body_set[slow].linvel().z, 5.0);
+    }
+
+    #[test]
+    fn heavy_impact_raises_a_contact_force_event() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 6.0, 0.0));
+        let ball = state.rigid_body_set[rb_handle].colliders()[0];
+        state.collider_set[ball].set_density(50.0);
+        let gravity = Vector3::new(0.0, -GRAVITY, 0.0);
+        let events = EventCollector::default();
+        for _ in 0..90 {
+            step_world(&mut state, 1, &gravity, &events);
+        }
+
+        let contact_forces = events.contact_forces.into_inner().unwrap();
+        let strongest = contact_forces
+            .iter()
+            .filter(|(a, b, _)| *a == ball || *b == ball)
+            .map(|(_, _, force)| *force)
+            .fold(0.0, f32::max);
+        assert!(
+            strongest > state.contact_force_threshold,
+            "strongest impact {}",
+            strongest
+        );
+    }
+
+    #[test]
+    fn trigger_zone_counts_each_entry_once() {
+        let mut state = PhysicsState::new(0);
+        let zone_body = state.rigid_body_set.insert(
+            RigidBodyBuilder::fixed()
+                .translation(Vector3::new(0.0, 5.0, 0.0))
+                .build(),
+        );
+        state.collider_set.insert_with_parent(
+            ColliderBuilder::cuboid(1.0, 1.0, 1.0)
+                .sensor(true)
+                .active_events(ActiveEvents::COLLISION_EVENTS)
+                .build(),
+            zone_body,
+            &mut state.rigid_body_set,
+        );
+        let ball = state.add_test_ball(Vector3::new(10.0, 5.0, 0.0));
+        let mut zone = TriggerState {
+            zone_id: 1,
+            world_id: 0,
+            entities_inside: Vec::new(),
+            enter_count: 0,
+        };
+
+        let inside = Vector3::new(0.0, 5.0, 0.0);
+        let outside = Vector3::new(10.0, 5.0, 0.0);
+        let mut counts = Vec::new();
+        for position in [inside, inside, outside, outside, inside, outside] {
+            state.rigid_body_set[ball].set_translation(position, true);
+            let events = EventCollector::default();
+            step_world(&mut state, 1, &Vector3::zeros(), &events);
+            for event in events.collisions.into_inner().unwrap() {
+                assert!(event.sensor());
+                zone.record_intersection(2, event.started());
+            }
+            counts.push((zone.enter_count, zone.entities_inside.len()));
+        }
+        assert_eq!(counts, [(1, 1), (1, 1), (1, 0), (1, 0), (2, 1), (2, 0)]);
+    }
+
+    /// Drops a unit ball of `density` into a 10m deep pool of water and returns its height
+    /// and speed after ten seconds.
+    fn settle_in_pool(density: f32) -> (f32, f32) {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 12.0, 0.0));
+        let co_handle = state.rigid_body_set[rb_handle].colliders()[0];
+        state.collider_set[co_handle].set_density(density);
+        let pools = [FluidZone {
+            id: 1,
+            world_id: 0,
+            center: Vec3 {
+                x: 0.0,
+                y: 5.0,
+                z: 0.0,
+            },
+            half_extents: Vec3 {
+                x: 10.0,
+                y: 5.0,
+                z: 10.0,
+            },
+            density: 1.0,
+            drag: 8.0,
+        }];
+        for _ in 0..600 {
+            state.rigid_body_set[rb_handle].reset_forces(false);
+            apply_fluid_zones(&mut state.rigid_body_set, &state.collider_set, &pools);
+            state.step_once();
+        }
+        let body = &state.rigid_body_set[rb_handle];
+        (body.translation().y, body.linvel().norm())
+    }
+
+    #[test]
+    fn light_ball_floats_half_submerged_and_heavy_ball_sinks() {
+        // Half the ball's volume displaces its own weight, so it floats centered on the surface
+        let (floating_y, speed) = settle_in_pool(0.5);
+        assert!(
+            (floating_y - 10.0).abs() < 0.2,
+            "light ball at {}",
+            floating_y
+        );
+        assert!(speed < 0.1, "still bobbing at {}", speed);
+
+        let (sunk_y, _) = settle_in_pool(3.0);
+        assert!((sunk_y - 1.1).abs() < 0.05, "heavy ball at {}", sunk_y);
+    }
+
+    /// Floats two unit balls 6m apart and returns their gap after a second of `strength`.
+    fn magnet_gap(strength: f32) -> f32 {
+        let mut state = PhysicsState::new(0);
+        let a = state.add_test_ball(Vector3::new(-3.0, 10.0, 0.0));
+        let b = state.add_test_ball(Vector3::new(3.0, 10.0, 0.0));
+        let events = EventCollector::default();
+        for _ in 0..60 {
+            for handle in [a, b] {
+                state.rigid_body_set[handle].reset_forces(false);
+            }
+            apply_magnets(&mut state.rigid_body_set, &[(a, b, strength)]);
+            step_world(&mut state, 1, &Vector3::zeros(), &events);
+        }
+        // Equal and opposite forces leave the pair's center where it started
+        let midpoint =
+            (state.rigid_body_set[a].translation() + state.rigid_body_set[b].translation()) / 2.0;
+        assert!((midpoint - Vector3::new(0.0, 10.0, 0.0)).norm() < 1.0e-3);
+        (state.rigid_body_set[b].translation() - state.rigid_body_set[a].translation()).norm()
+    }
+
+    #[test]
+    fn magnets_attract_for_positive_strength_and_repel_for_negative() {
+        assert!(magnet_gap(200.0) < 5.0);
+        assert!(magnet_gap(-200.0) > 7.0);
+        assert_eq!(magnet_gap(0.0), 6.0);
+    }
+
+    #[test]
+    fn force_and_torque_act_for_exactly_one_tick() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 10.0, 0.0));
+        let body = &mut state.rigid_body_set[rb_handle];
+        body.add_force(Vector3::new(50.0, 0.0, 0.0), true);
+        body.add_torque(Vector3::new(0.0, 20.0, 0.0), true);
+
+        let mut velocities = Vec::new();
+        for _ in 0..3 {
+            step_world(&mut state, 1, &Vector3::zeros(), &EventCollector::default());
+            clear_user_forces(&mut state.rigid_body_set);
+            let body = &state.rigid_body_set[rb_handle];
+            velocities.push((body.linvel().x, body.angvel().y));
+        }
+        assert!(velocities[0].0 > 0.0 && velocities[0].1 > 0.0);
+        // No further push once the tick has consumed them
+        assert_eq!(velocities[1], velocities[0]);
+        assert_eq!(velocities[2], velocities[0]);
+    }
+
+    #[test]
+    fn heavier_pulley_side_sinks_as_the_other_rises_by_as_much() {
+        let mut state = PhysicsState::new(0);
+        let heavy = state.add_test_ball(Vector3::new(0.0, 15.0, 0.0));
+        let light = state.add_test_ball(Vector3::new(6.0, 15.0, 0.0));
+        let co_handle = state.rigid_body_set[heavy].colliders()[0];
+        state.collider_set[co_handle].set_density(3.0);
+        let pulleys = [(
+            heavy,
+            light,
+            PulleyConstraint {
+                id: 1,
+                world_id: 0,
+                entity_a: 1,
+                anchor_a: Vec3 {
+                    x: 0.0,
+                    y: 20.0,
+                    z: 0.0,
+                },
+                entity_b: 2,
+                anchor_b: Vec3 {
+                    x: 6.0,
+                    y: 20.0,
+                    z: 0.0,
+                },
+                length: 10.0,
+            },
+        )];
+        for _ in 0..30 {
+            clear_user_forces(&mut state.rigid_body_set);
+            apply_pulleys(&mut state.rigid_body_set, &pulleys);
+            state.step_once();
+        }
+
+        let heavy_drop = 15.0 - state.rigid_body_set[heavy].translation().y;
+        let light_rise = state.rigid_body_set[light].translation().y - 15.0;
+        assert!(heavy_drop > 0.3, "heavy side only fell {}", heavy_drop);
+        assert!(
+            (heavy_drop - light_rise).abs() < 0.1,
+            "fell {} but rose {}",
+            heavy_drop,
+            light_rise
+        );
+    }
+
+    #[test]
+    fn overlapping_force_fields_sum_and_only_push_bodies_inside() {
+        let mut state = PhysicsState::new(0);
+        let in_both = state.add_test_ball(Vector3::new(-3.0, 10.0, 0.0));
+        let in_one = state.add_test_ball(Vector3::new(3.0, 10.0, 0.0));
+        let outside = state.add_test_ball(Vector3::new(10.0, 10.0, 0.0));
+        // Each field alone is weaker than the unit ball's weight (about 41 N), both together aren't
+        let field = |id, center_x, half_x| ForceField {
+            id,
+            world_id: 0,
+            center: Vec3 {
+                x: center_x,
+                y: 10.0,
+                z: 0.0,
+            },
+            half_extents: Vec3 {
+                x: half_x,
+                y: 20.0,
+                z: 5.0,
+            },
+            force: Vec3 {
+                x: 0.0,
+                y: 30.0,
+                z: 0.0,
+            },
+        };
+        let fields = [field(1, 0.0, 5.0), field(2, -3.0, 2.0)];
+        let gravity = Vector3::new(0.0, -GRAVITY, 0.0);
+        for _ in 0..30 {
+            clear_user_forces(&mut state.rigid_body_set);
+            apply_force_fields(&mut state.rigid_body_set, &fields);
+            step_world(&mut state, 1, &gravity, &EventCollector::default());
+        }
+
+        let height = |handle: RigidBodyHandle| state.rigid_body_set[handle].translation().y;
+        assert!(height(in_both) > 10.0);
+        assert!(height(in_one) < 10.0);
+        assert!(height(outside) < height(in_one));
+    }
+
+    #[test]
+    fn radial_gravity_pulls_toward_its_center_and_replaces_uniform_gravity() {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 10.0, 0.0));
+        // Off to the side, so a pull toward it can't be mistaken for uniform gravity
+        state.radial_gravity_center = Some(Vector3::new(10.0, 10.0, 0.0));
+        state.radial_gravity_strength = 5.0;
+        for _ in 0..30 {
+            clear_user_forces(&mut state.rigid_body_set);
+            let gravity = apply_world_gravity(&mut state);
+            assert_eq!(gravity, Vector3::zeros());
+            step_world(&mut state, 1, &gravity, &EventCollector::default());
+        }
+        let position = state.rigid_body_set[rb_handle].translation();
+        assert!(position.x > 0.4, "x {}", position.x);
+        assert!((position.y - 10.0).abs() < 1.0e-3, "y {}", position.y);
+
+        state.radial_gravity_center = None;
+        assert_eq!(
+            apply_world_gravity(&mut state),
+            Vector3::new(0.0, -GRAVITY, 0.0)
+        );
+    }
+
+    #[test]
+    fn only_collisions_of_opted_in_entities_are_logged() {
+        let mut state = PhysicsState::new(0);
+        // Both raise collision events (entity 2 as if for a collision trigger), only 1 opted in
+        for (x, entity_id) in [(-3.0, 1), (3.0, 2)] {
+            let rb_handle = state.add_test_ball(Vector3::new(x, 2.0, 0.0));
+            let co_handle = state.rigid_body_set[rb_handle].colliders()[0];
+            state.collider_set[co_handle].set_active_events(ActiveEvents::COLLISION_EVENTS);
+            state.collider_to_entity_id.insert(co_handle, entity_id);
+        }
+        state.collision_logging.insert(1);
+        let events = EventCollector::default();
+        for _ in 0..60 {
+            step_world(&mut state, 1, &Vector3::new(0.0, -GRAVITY, 0.0), &events);
+        }
+
+        let collisions = events.collisions.into_inner().unwrap();
+        let entities = |event: &CollisionEvent| {
+            [event.collider1(), event.collider2()]
+                .map(|co_handle| state.collider_to_entity_id.get(&co_handle).copied())
+        };
+        assert!(collisions
+            .iter()
+            .any(|event| entities(event).contains(&Some(2))));
+        let logged: Vec<&CollisionEvent> = logged_collisions(
+            &collisions,
+            &state.collider_to_entity_id,
+            &state.collision_logging,
+        )
+        .collect();
+        assert!(!logged.is_empty());
+        for event in logged {
+            // Ball 1 against the ground
+            let pair = entities(event);
+            assert!(
+                pair.contains(&Some(1)) && pair.contains(&None),
+                "logged {:?}",
+                pair
+            );
+        }
+    }
+
+    #[test]
+    fn retuned_tick_interval_keeps_one_step_per_tick() {
+        let mut state = PhysicsState::new(0);
+        assert_eq!(take_substeps(&mut state, 0), 1);
+        // Half a 16ms step is banked when the interval changes
+        assert_eq!(take_substeps(&mut state, 24_000), 1);
+
+        match_tick_interval(&mut state, 32);
+        assert_eq!(state.integration_parameters.dt, 0.032);
+        let steps: Vec<u32> = (1..=5)
+            .map(|i| take_substeps(&mut state, 24_000 + i * 32_000))
+            .collect();
+        assert_eq!(steps, [1; 5]);
+        assert_eq!(state.time_accumulator_micros, 0);
+    }
+
+    #[test]
+    fn teleported_body_falls_under_the_kill_plane() {
+        let mut state = PhysicsState::new(0);
+        state.kill_plane_y = Some(-10.0);
+        let resting = state.add_test_ball(Vector3::new(0.0, 1.1, 0.0));
+        let lost = state.add_test_ball(Vector3::new(4.0, 1.1, 0.0));
+        let anchor = state.rigid_body_set.insert(
+            RigidBodyBuilder::fixed()
+                .translation(Vector3::new(0.0, -50.0, 0.0))
+                .build(),
+        );
+        for (rb_handle, entity_id) in [(resting, 1), (lost, 2), (anchor, 3)] {
+            state.handle_to_entity_id.insert(rb_handle, entity_id);
+        }
+        // Off the edge of the ground plane
+        state.rigid_body_set[lost].set_translation(Vector3::new(150.0, 1.1, 0.0), true);
+
+        let mut removed_on = None;
+        for tick in 1..=120 {
+            state.step_once();
+            let threshold = state.kill_plane_y.unwrap();
+            for entity_id in dynamic_entities_below(&state, threshold) {
+                assert_eq!(entity_id, 2);
+                remove_body(&mut state, entity_id, lost);
+                removed_on.get_or_insert(tick);
+            }
+        }
+        // Falling 11.1m takes about 1.5s
+        assert!(
+            matches!(removed_on, Some(tick) if (85..=100).contains(&tick)),
+            "removed on {:?}",
+            removed_on
+        );
+        assert_eq!(state.rigid_body_set.len(), 2);
+    }
+}
@@ -0,0 +1,306 @@
+//! Server-side reactions to collisions, run as scheduled reducer calls.
+
+use log::info;
+use rapier3d::prelude::*;
+use spacetimedb::{reducer, ReducerContext, ScheduleAt, Table, Timestamp};
+use std::collections::HashMap;
+
+use crate::bodies::freeze_entity;
+use crate::state::*;
+use crate::tables::*;
+use crate::tick::EVENT_RETENTION_TICKS;
+use crate::types::*;
+
+// --- Helper Functions ---
+
+/// Schedules a `handle_collision_trigger` call for every trigger on an entity that
+/// started touching something this tick, and prunes old `CollisionTriggerLog` rows.
+pub(crate) fn dispatch_collision_triggers(
+    ctx: &ReducerContext,
+    world_id: u32,
+    tick: u64,
+    collisions: &[CollisionEvent],
+    collider_to_entity_id: &HashMap<ColliderHandle, u32>,
+) -> Result<(), String> {
+    let stale: Vec<u64> = ctx
+        .db
+        .collision_trigger_log()
+        .world_id()
+        .filter(world_id)
+        .filter(|row| row.tick + EVENT_RETENTION_TICKS < tick)
+        .map(|row| row.id)
+        .collect();
+    for id in stale {
+        ctx.db.collision_trigger_log().id().delete(id);
+    }
+
+    let first_id = ctx
+        .db
+        .collision_dispatch()
+        .iter()
+        .map(|row| row.scheduled_id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let handlers_of = |entity_id: u32| -> Vec<CollisionHandler> {
+        ctx.db
+            .collision_trigger()
+            .entity_id()
+            .filter(entity_id)
+            .map(|trigger| trigger.handler)
+            .collect()
+    };
+    let dispatches = collision_dispatches(
+        world_id,
+        tick,
+        ctx.timestamp,
+        first_id,
+        collisions,
+        collider_to_entity_id,
+        handlers_of,
+    );
+    for dispatch in dispatches {
+        ctx.db
+            .collision_dispatch()
+            .try_insert(dispatch)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// One `CollisionDispatch` per trigger handler of each entity that started a non-sensor
+/// collision, numbered from `first_id`. `handlers_of` lists an entity's trigger handlers.
+pub(crate) fn collision_dispatches(
+    world_id: u32,
+    tick: u64,
+    now: Timestamp,
+    first_id: u64,
+    collisions: &[CollisionEvent],
+    collider_to_entity_id: &HashMap<ColliderHandle, u32>,
+    handlers_of: impl Fn(u32) -> Vec<CollisionHandler>,
+) -> Vec<CollisionDispatch> {
+    let mut dispatches = Vec::new();
+    for event in collisions.iter().filter(|event| event.started() && !event.sensor()) {
+        let entity1 = collider_to_entity_id.get(&event.collider1()).copied();
+        let entity2 = collider_to_entity_id.get(&event.collider2()).copied();
+        for (entity, other_entity) in [(entity1, entity2), (entity2, entity1)] {
+            let Some(entity_id) = entity else {
+                continue;
+            };
+            for handler in handlers_of(entity_id) {
+                // Runs as its own transaction right after this tick commits
+                dispatches.push(CollisionDispatch {
+                    scheduled_id: first_id + dispatches.len() as u64,
+                    scheduled_at: ScheduleAt::Time(now),
+                    world_id,
+                    entity_id,
+                    other_entity,
+                    handler,
+                    tick,
+                });
+            }
+        }
+    }
+    dispatches
+}
+
+/// The entity a `Despawn` or `DespawnOther` dispatch removes; `None` for the ground and
+/// for the other handlers.
+pub(crate) fn despawn_target(dispatch: &CollisionDispatch) -> Option<u32> {
+    match dispatch.handler {
+        CollisionHandler::Despawn => Some(dispatch.entity_id),
+        CollisionHandler::DespawnOther => dispatch.other_entity,
+        CollisionHandler::Log | CollisionHandler::Freeze => None,
+    }
+}
+
+// --- Reducers ---
+
+#[reducer]
+pub fn add_collision_trigger(
+    ctx: &ReducerContext,
+    entity_id: u32,
+    handler: CollisionHandler,
+) -> Result<(), String> {
+    info!("add_collision_trigger called for entity {}: {:?}", entity_id, handler);
+    if ctx
+        .db
+        .collision_trigger()
+        .entity_id()
+        .filter(entity_id)
+        .any(|trigger| trigger.handler == handler)
+    {
+        return Err(format!("Entity {} already has a {:?} trigger", entity_id, handler));
+    }
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    let id = ctx
+        .db
+        .collision_trigger()
+        .iter()
+        .map(|trigger| trigger.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    ctx.db
+        .collision_trigger()
+        .try_insert(CollisionTrigger {
+            id,
+            world_id,
+            entity_id,
+            handler,
+        })
+        .map_err(|e| e.to_string())?;
+    // Rapier only reports collisions of colliders with collision events enabled. This
+    // doesn't opt the entity in to `CollisionLog`.
+    sync_collision_events(ctx, state, entity_id, rb_handle)?;
+    info!("  -> Created collision trigger {}", id);
+    Ok(())
+}
+
+#[reducer]
+pub fn remove_collision_trigger(ctx: &ReducerContext, id: u32) -> Result<(), String> {
+    info!("remove_collision_trigger called for {}", id);
+    let trigger = ctx
+        .db
+        .collision_trigger()
+        .id()
+        .find(id)
+        .ok_or_else(|| format!("Collision trigger {} not found", id))?;
+    ctx.db.collision_trigger().id().delete(id);
+    // Clears the collider flag unless the entity still logs or has other triggers
+    let (world_id, rb_handle, _) = get_entity_handles(ctx, trigger.entity_id)?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    sync_collision_events(ctx, state, trigger.entity_id, rb_handle)
+}
+
+#[reducer]
+pub fn handle_collision_trigger(ctx: &ReducerContext, dispatch: CollisionDispatch) -> Result<(), String> {
+    info!(
+        "handle_collision_trigger called for entity {} ({:?}, other {:?})",
+        dispatch.entity_id, dispatch.handler, dispatch.other_entity
+    );
+    // An earlier handler (or a despawn in between) may have removed the entity already
+    if ctx.db.entity().id().find(dispatch.entity_id).is_none() {
+        return Ok(());
+    }
+    match dispatch.handler {
+        CollisionHandler::Log => {
+            let id = ctx
+                .db
+                .collision_trigger_log()
+                .iter()
+                .map(|row| row.id)
+                .max()
+                .unwrap_or(0)
+                + 1;
+            ctx.db
+                .collision_trigger_log()
+                .try_insert(CollisionTriggerLog::from_dispatch(id, &dispatch))
+                .map_err(|e| e.to_string())?;
+        }
+        CollisionHandler::Despawn | CollisionHandler::DespawnOther => {
+            // The ground isn't an entity and can't be despawned
+            let target = despawn_target(&dispatch);
+            let Some(target) = target.filter(|id| ctx.db.entity().id().find(*id).is_some()) else {
+                return Ok(());
+            };
+            let mut worlds = lock_state();
+            let state = get_world(&mut worlds, dispatch.world_id)?;
+            remove_entity(ctx, state, target)?;
+        }
+        CollisionHandler::Freeze => {
+            if ctx.db.frozen_state().entity_id().find(dispatch.entity_id).is_none() {
+                freeze_entity(ctx, dispatch.entity_id)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tick::{step_world, EventCollector, GRAVITY};
+    use rapier3d::na::Vector3;
+
+    #[test]
+    fn ball_landing_on_a_crate_dispatches_its_log_and_despawn_other_triggers() {
+        let mut state = PhysicsState::new(0);
+        let ball = state.add_test_ball(Vector3::new(0.0, 5.0, 0.0));
+        let ball_collider = state.rigid_body_set[ball].colliders()[0];
+        state.collider_set[ball_collider].set_active_events(ActiveEvents::COLLISION_EVENTS);
+        let crate_body = state.rigid_body_set.insert(
+            RigidBodyBuilder::fixed()
+                .translation(Vector3::new(0.0, 1.1, 0.0))
+                .build(),
+        );
+        let crate_collider = state.collider_set.insert_with_parent(
+            ColliderBuilder::cuboid(1.0, 1.0, 1.0).build(),
+            crate_body,
+            &mut state.rigid_body_set,
+        );
+        for (rb_handle, co_handle, entity_id) in
+            [(ball, ball_collider, 1), (crate_body, crate_collider, 2)]
+        {
+            state.handle_to_entity_id.insert(rb_handle, entity_id);
+            state.collider_to_entity_id.insert(co_handle, entity_id);
+        }
+
+        let events = EventCollector::default();
+        for _ in 0..60 {
+            step_world(&mut state, 1, &Vector3::new(0.0, -GRAVITY, 0.0), &events);
+        }
+        let collisions = events.collisions.into_inner().unwrap();
+        // Only the ball has triggers
+        let handlers_of = |entity_id: u32| match entity_id {
+            1 => vec![CollisionHandler::Log, CollisionHandler::DespawnOther],
+            _ => Vec::new(),
+        };
+        let now = Timestamp::from_micros_since_unix_epoch(5_000);
+        let dispatches = collision_dispatches(
+            0,
+            7,
+            now,
+            10,
+            &collisions,
+            &state.collider_to_entity_id,
+            handlers_of,
+        );
+
+        assert_eq!(dispatches.len(), 2);
+        for (index, (dispatch, handler)) in dispatches
+            .iter()
+            .zip([CollisionHandler::Log, CollisionHandler::DespawnOther])
+            .enumerate()
+        {
+            assert_eq!(dispatch.scheduled_id, 10 + index as u64);
+            assert_eq!((dispatch.entity_id, dispatch.other_entity), (1, Some(2)));
+            assert_eq!(dispatch.handler, handler);
+            assert_eq!(dispatch.tick, 7);
+            assert!(matches!(dispatch.scheduled_at, ScheduleAt::Time(at) if at == now));
+        }
+
+        // Log records the contact as dispatched
+        let log = CollisionTriggerLog::from_dispatch(3, &dispatches[0]);
+        assert_eq!(
+            (log.id, log.entity_id, log.other_entity, log.tick),
+            (3, 1, Some(2), 7)
+        );
+        assert_eq!(despawn_target(&dispatches[0]), None);
+
+        // DespawnOther removes the crate, so the ball drops on to the ground
+        let target = despawn_target(&dispatches[1]).unwrap();
+        assert_eq!(target, 2);
+        remove_body(&mut state, target, crate_body);
+        assert!(state.rigid_body_set.get(crate_body).is_none());
+        assert!(!state.collider_to_entity_id.contains_key(&crate_collider));
+        for _ in 0..120 {
+            state.step_once();
+        }
+        assert!(state.rigid_body_set[ball].translation().y < 1.2);
+    }
+}
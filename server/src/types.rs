@@ -0,0 +1,899 @@
+//! Reducer argument types, also used as table columns.
+
+use rapier3d::na::Vector3;
+use rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+use spacetimedb::{ReducerContext, SpacetimeType, Table};
+
+use crate::state::{get_entity_handles, PhysicsState};
+use crate::tables::*;
+
+/// Plain 3D vector for reducer arguments.
+#[derive(SpacetimeType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    /// Converts to a Rapier vector, rejecting NaN/infinite components.
+    pub(crate) fn to_finite_vector(self, what: &str) -> Result<Vector3<f32>, String> {
+        if [self.x, self.y, self.z].iter().all(|v| v.is_finite()) {
+            Ok(Vector3::new(self.x, self.y, self.z))
+        } else {
+            Err(format!("{} must be finite", what))
+        }
+    }
+}
+
+impl From<Vector3<f32>> for Vec3 {
+    fn from(v: Vector3<f32>) -> Self {
+        Vec3 { x: v.x, y: v.y, z: v.z }
+    }
+}
+
+/// Quaternion for reducer arguments; normalized on use.
+#[derive(SpacetimeType, Clone, Copy, Debug, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quat {
+    /// Normalizes into a rotation, rejecting non-finite and (near-)zero quaternions.
+    pub(crate) fn to_rotation(self) -> Result<Rotation<Real>, String> {
+        let q = rapier3d::na::Quaternion::new(self.w, self.x, self.y, self.z);
+        let norm = q.norm();
+        if !norm.is_finite() || norm < 1.0e-6 {
+            return Err("Rotation quaternion must be finite and non-zero".to_string());
+        }
+        Ok(rapier3d::na::UnitQuaternion::from_quaternion(q))
+    }
+}
+
+/// Which body-type pairs a collider generates contacts and events with. Rapier's default
+/// covers only pairs with a dynamic body, so e.g. a fixed sensor never notices a
+/// kinematic character.
+#[derive(SpacetimeType, Clone, Copy, Debug, PartialEq)]
+pub struct CollisionTypes {
+    pub dynamic_dynamic: bool,
+    pub dynamic_kinematic: bool,
+    pub dynamic_fixed: bool,
+    pub kinematic_kinematic: bool,
+    pub kinematic_fixed: bool,
+    pub fixed_fixed: bool,
+}
+
+impl From<CollisionTypes> for ActiveCollisionTypes {
+    fn from(types: CollisionTypes) -> Self {
+        let mut active = ActiveCollisionTypes::empty();
+        for (enabled, flag) in [
+            (types.dynamic_dynamic, ActiveCollisionTypes::DYNAMIC_DYNAMIC),
+            (types.dynamic_kinematic, ActiveCollisionTypes::DYNAMIC_KINEMATIC),
+            (types.dynamic_fixed, ActiveCollisionTypes::DYNAMIC_FIXED),
+            (types.kinematic_kinematic, ActiveCollisionTypes::KINEMATIC_KINEMATIC),
+            (types.kinematic_fixed, ActiveCollisionTypes::KINEMATIC_FIXED),
+            (types.fixed_fixed, ActiveCollisionTypes::FIXED_FIXED),
+        ] {
+            if enabled {
+                active |= flag;
+            }
+        }
+        active
+    }
+}
+
+/// `ActiveHooks` flags requested for a collider, see `set_active_hooks`. Rapier only
+/// calls a `PhysicsHooks` callback for contacts where one of the colliders has its flag.
+#[derive(SpacetimeType, Clone, Copy, Debug, PartialEq)]
+pub struct HookFlags {
+    pub filter_contact_pairs: bool,
+    pub filter_intersection_pairs: bool,
+    pub modify_solver_contacts: bool,
+}
+
+impl From<HookFlags> for ActiveHooks {
+    fn from(flags: HookFlags) -> Self {
+        let mut active = ActiveHooks::empty();
+        for (enabled, flag) in [
+            (flags.filter_contact_pairs, ActiveHooks::FILTER_CONTACT_PAIRS),
+            (flags.filter_intersection_pairs, ActiveHooks::FILTER_INTERSECTION_PAIR),
+            (flags.modify_solver_contacts, ActiveHooks::MODIFY_SOLVER_CONTACTS),
+        ] {
+            if enabled {
+                active |= flag;
+            }
+        }
+        active
+    }
+}
+
+/// Optional per-body settings accepted by `spawn_with_options`.
+#[derive(SpacetimeType, Clone, Default)]
+pub struct SpawnOptions {
+    /// Enable continuous collision detection so fast bodies don't tunnel through thin colliders.
+    pub ccd_enabled: bool,
+    /// Explicit mass in kg. Replaces the density-derived mass (inertia still follows the shape).
+    pub mass: Option<f32>,
+    /// Initial spin in rad/s around each world axis.
+    pub angular_velocity: Option<Vec3>,
+    /// Initial orientation; bodies spawn axis-aligned without it.
+    pub rotation: Option<Quat>,
+    /// Bodies in a higher dominance group push lower ones without being pushed back.
+    pub dominance_group: Option<i8>,
+    /// Despawn the entity after this many physics ticks.
+    pub ttl_ticks: Option<u32>,
+    /// Collider position relative to the body origin (e.g. a wheel off its axle).
+    pub collider_offset: Option<Vec3>,
+    /// Collider orientation relative to the body.
+    pub collider_rotation: Option<Quat>,
+    /// How this collider's friction combines with the other collider's on contact.
+    pub friction_combine_rule: Option<CombineRule>,
+    /// How this collider's restitution combines with the other collider's on contact.
+    pub restitution_combine_rule: Option<CombineRule>,
+    /// Log this body's collisions to `CollisionLog`, see `set_collision_events`.
+    pub collision_events: bool,
+    /// Body-type pairs the collider interacts with; Rapier's default when unset.
+    pub collision_types: Option<CollisionTypes>,
+    /// Contact hooks the collider opts in to, see `set_active_hooks`.
+    pub active_hooks: Option<HookFlags>,
+}
+
+impl SpawnOptions {
+    pub(crate) fn apply_to_body(&self, builder: RigidBodyBuilder) -> Result<RigidBodyBuilder, String> {
+        let mut builder = builder.ccd_enabled(self.ccd_enabled);
+        if let Some(angvel) = &self.angular_velocity {
+            builder = builder.angvel(angvel.to_finite_vector("Angular velocity")?);
+        }
+        if let Some(rotation) = &self.rotation {
+            // The builder takes a scaled rotation axis rather than a quaternion
+            builder = builder.rotation(rotation.to_rotation()?.scaled_axis());
+        }
+        if let Some(group) = self.dominance_group {
+            builder = builder.dominance_group(group);
+        }
+        Ok(builder)
+    }
+
+    /// Validates the collider-level options and applies them to `builder`.
+    pub(crate) fn apply_to_collider(&self, mut builder: ColliderBuilder) -> Result<ColliderBuilder, String> {
+        if let Some(mass) = self.mass {
+            if !(mass.is_finite() && mass > 0.0) {
+                return Err("Mass must be > 0".to_string());
+            }
+            builder = builder.mass(mass);
+        }
+        if let Some(offset) = &self.collider_offset {
+            builder = builder.translation(offset.to_finite_vector("Collider offset")?);
+        }
+        if let Some(rotation) = &self.collider_rotation {
+            builder = builder.rotation(rotation.to_rotation()?.scaled_axis());
+        }
+        if let Some(rule) = self.friction_combine_rule {
+            builder = builder.friction_combine_rule(rule.into());
+        }
+        if let Some(rule) = self.restitution_combine_rule {
+            builder = builder.restitution_combine_rule(rule.into());
+        }
+        if self.collision_events {
+            builder = builder.active_events(ActiveEvents::COLLISION_EVENTS);
+        }
+        if let Some(types) = self.collision_types {
+            builder = builder.active_collision_types(types.into());
+        }
+        Ok(builder)
+    }
+
+    /// Applies the options that live in the world state and in table rows to a freshly
+    /// registered entity. Every spawn reducer taking `SpawnOptions` calls this.
+    pub(crate) fn apply_to_entity(
+        &self,
+        ctx: &ReducerContext,
+        state: &mut PhysicsState,
+        entity_id: u32,
+    ) -> Result<(), String> {
+        let (_, _, co_handle) = get_entity_handles(ctx, entity_id)?;
+        self.apply_to_state(state, entity_id, co_handle);
+        if let Some(ttl_ticks) = self.ttl_ticks {
+            ctx.db
+                .entity_lifetime()
+                .try_insert(EntityLifetime {
+                    entity_id,
+                    world_id: state.world_id,
+                    remaining_ticks: ttl_ticks,
+                })
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// The in-memory part of `apply_to_entity`, for the entity's primary collider `co_handle`.
+    pub(crate) fn apply_to_state(&self, state: &mut PhysicsState, entity_id: u32, co_handle: ColliderHandle) {
+        // The collider flag alone isn't enough: the tick only logs opted-in entities
+        if self.collision_events {
+            state.collision_logging.insert(entity_id);
+        }
+        if let Some(flags) = self.active_hooks {
+            state.hooks.requested.insert(co_handle, flags.into());
+            if let Some(collider) = state.collider_set.get_mut(co_handle) {
+                state.hooks.sync_active_hooks(co_handle, collider);
+            }
+        }
+    }
+}
+
+/// A state-mutating reducer call recorded in `ReducerLog`, with what `replay` needs to
+/// repeat it. Entity ids are the recording world's; `replay` maps them to the replayed ones.
+#[derive(SpacetimeType, Clone)]
+pub enum LoggedCall {
+    /// `spawn` / `spawn_with_options`.
+    Spawn(SpawnCall),
+    /// `spawn_exploding_spheres`, with the seed its directions were drawn from.
+    SpawnExplodingSpheres(u64),
+    ApplyImpulseAtPoint(ImpulseCall),
+    ApplyForce(ForceCall),
+    ApplyTorque(ForceCall),
+    Shockwave(ShockwaveCall),
+}
+
+/// Arguments of a logged `spawn_with_options` call.
+#[derive(SpacetimeType, Clone)]
+pub struct SpawnCall {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub options: SpawnOptions,
+}
+
+/// Arguments of a logged `apply_impulse_at_point` call.
+#[derive(SpacetimeType, Clone, Copy, Debug, PartialEq)]
+pub struct ImpulseCall {
+    pub entity_id: u32,
+    pub impulse: Vec3,
+    pub point: Vec3,
+}
+
+/// Arguments of a logged `apply_force` or `apply_torque` call.
+#[derive(SpacetimeType, Clone, Copy, Debug, PartialEq)]
+pub struct ForceCall {
+    pub entity_id: u32,
+    pub vector: Vec3,
+}
+
+/// Arguments of a logged `shockwave` call.
+#[derive(SpacetimeType, Clone, Copy, Debug, PartialEq)]
+pub struct ShockwaveCall {
+    pub center: Vec3,
+    pub radius: f32,
+    pub strength: f32,
+}
+
+/// Rule for combining two colliders' friction or restitution coefficients.
+/// When the two colliders disagree, rapier uses the rule with the higher precedence:
+/// `Max` > `Multiply` > `Min` > `Average`.
+#[derive(SpacetimeType, Clone, Copy, Debug, PartialEq)]
+pub enum CombineRule {
+    Average,
+    Min,
+    Multiply,
+    Max,
+}
+
+impl From<CombineRule> for CoefficientCombineRule {
+    fn from(rule: CombineRule) -> Self {
+        match rule {
+            CombineRule::Average => CoefficientCombineRule::Average,
+            CombineRule::Min => CoefficientCombineRule::Min,
+            CombineRule::Multiply => CoefficientCombineRule::Multiply,
+            CombineRule::Max => CoefficientCombineRule::Max,
+        }
+    }
+}
+
+/// Body types `set_body_type` can switch between.
+#[derive(SpacetimeType, Clone, Copy, Debug, PartialEq)]
+pub enum BodyType {
+    Dynamic,
+    Fixed,
+    KinematicPositionBased,
+}
+
+impl From<BodyType> for RigidBodyType {
+    fn from(body_type: BodyType) -> Self {
+        match body_type {
+            BodyType::Dynamic => RigidBodyType::Dynamic,
+            BodyType::Fixed => RigidBodyType::Fixed,
+            BodyType::KinematicPositionBased => RigidBodyType::KinematicPositionBased,
+        }
+    }
+}
+
+/// Primitive collider shapes that can be spawned and rendered by clients.
+#[derive(SpacetimeType, Clone, Copy, Debug, PartialEq)]
+pub enum ShapeKind {
+    Ball,
+    Cuboid,
+    Capsule,
+    Heightfield,
+    Trimesh,
+    ConvexHull,
+}
+
+/// Shape discriminant plus dimensions. Unused dimensions are left at zero:
+/// - `Ball`: `radius`
+/// - `Cuboid`: `half_x`, `half_y`, `half_z`
+/// - `Capsule`: `radius`, with `half_y` as the half-height of the segment (Y-aligned)
+/// - `Heightfield`: `half_x`, `half_y`, `half_z` hold the (full) scale; heights aren't replicated
+/// - `Trimesh`, `ConvexHull`: no dimensions; the geometry is whatever the caller uploaded
+#[derive(SpacetimeType, Clone, Debug, PartialEq)]
+pub struct ShapeDesc {
+    pub kind: ShapeKind,
+    pub radius: f32,
+    pub half_x: f32,
+    pub half_y: f32,
+    pub half_z: f32,
+}
+
+impl ShapeDesc {
+    pub(crate) fn ball(radius: f32) -> Self {
+        ShapeDesc {
+            kind: ShapeKind::Ball,
+            radius,
+            half_x: 0.0,
+            half_y: 0.0,
+            half_z: 0.0,
+        }
+    }
+
+    pub(crate) fn capsule(half_height: f32, radius: f32) -> Self {
+        ShapeDesc {
+            kind: ShapeKind::Capsule,
+            radius,
+            half_x: 0.0,
+            half_y: half_height,
+            half_z: 0.0,
+        }
+    }
+
+    /// Same shape with every dimension multiplied by `factor`. Only primitives can be
+    /// scaled; the other kinds don't keep their geometry in the descriptor.
+    pub(crate) fn scaled(&self, factor: f32) -> Result<Self, String> {
+        match self.kind {
+            ShapeKind::Ball | ShapeKind::Cuboid | ShapeKind::Capsule => Ok(ShapeDesc {
+                kind: self.kind,
+                radius: self.radius * factor,
+                half_x: self.half_x * factor,
+                half_y: self.half_y * factor,
+                half_z: self.half_z * factor,
+            }),
+            _ => Err(format!("{:?} colliders can't be scaled", self.kind)),
+        }
+    }
+
+    /// Descriptor for shapes whose geometry doesn't fit the primitive dimensions.
+    pub(crate) fn kind_only(kind: ShapeKind) -> Self {
+        ShapeDesc {
+            kind,
+            radius: 0.0,
+            half_x: 0.0,
+            half_y: 0.0,
+            half_z: 0.0,
+        }
+    }
+
+    /// Recovers a descriptor from a collider shape, e.g. after `import_world`.
+    pub(crate) fn from_shape(shape: &dyn Shape) -> Option<Self> {
+        if let Some(ball) = shape.as_ball() {
+            Some(ShapeDesc::ball(ball.radius))
+        } else if let Some(cuboid) = shape.as_cuboid() {
+            Some(ShapeDesc {
+                kind: ShapeKind::Cuboid,
+                radius: 0.0,
+                half_x: cuboid.half_extents.x,
+                half_y: cuboid.half_extents.y,
+                half_z: cuboid.half_extents.z,
+            })
+        } else {
+            shape
+                .as_capsule()
+                .map(|capsule| ShapeDesc::capsule(capsule.half_height(), capsule.radius))
+        }
+    }
+
+    /// Validates the dimensions and builds the matching collider.
+    pub(crate) fn collider_builder(&self) -> Result<ColliderBuilder, String> {
+        let positive = |v: f32| v.is_finite() && v > 0.0;
+        match self.kind {
+            ShapeKind::Ball if positive(self.radius) => Ok(ColliderBuilder::ball(self.radius)),
+            ShapeKind::Cuboid
+                if positive(self.half_x) && positive(self.half_y) && positive(self.half_z) =>
+            {
+                Ok(ColliderBuilder::cuboid(self.half_x, self.half_y, self.half_z))
+            }
+            ShapeKind::Capsule if positive(self.radius) && positive(self.half_y) => {
+                Ok(ColliderBuilder::capsule_y(self.half_y, self.radius))
+            }
+            ShapeKind::Heightfield => Err("Heightfields are spawned with spawn_heightfield".to_string()),
+            ShapeKind::Trimesh => Err("Trimeshes are spawned with spawn_trimesh".to_string()),
+            ShapeKind::ConvexHull => {
+                Err("Convex hulls are spawned with spawn_convex_hull".to_string())
+            }
+            _ => Err(format!("Invalid dimensions for {:?} shape", self.kind)),
+        }
+    }
+}
+
+/// Joint types tracked in `EntityJoint`.
+#[derive(SpacetimeType, Clone, Copy, Debug, PartialEq)]
+pub enum JointKind {
+    Spring,
+    Prismatic,
+    Spherical,
+    Distance,
+}
+
+impl JointKind {
+    /// Recovers the kind of a restored joint, e.g. after `import_world`.
+    /// Springs lock no axes, so anything unrecognized is treated as one.
+    pub(crate) fn from_joint(joint: &GenericJoint) -> Self {
+        if joint.locked_axes == JointAxesMask::LOCKED_PRISMATIC_AXES {
+            JointKind::Prismatic
+        } else if joint.locked_axes == JointAxesMask::LOCKED_SPHERICAL_AXES {
+            JointKind::Spherical
+        } else if joint.limit_axes.contains(JointAxesMask::LIN_X) {
+            // Distance joints limit the coupled distance; springs only drive it with a motor
+            JointKind::Distance
+        } else {
+            JointKind::Spring
+        }
+    }
+}
+
+/// Joint types of `MultibodyLink` rows.
+#[derive(SpacetimeType, Clone, Copy, Debug, PartialEq)]
+pub enum LinkKind {
+    Revolute,
+    Prismatic,
+    Spherical,
+    Fixed,
+}
+
+impl LinkKind {
+    /// Recovers the kind of a restored link, e.g. after `import_world`.
+    pub(crate) fn from_joint(joint: &GenericJoint) -> Self {
+        if joint.locked_axes == JointAxesMask::LOCKED_REVOLUTE_AXES {
+            LinkKind::Revolute
+        } else if joint.locked_axes == JointAxesMask::LOCKED_PRISMATIC_AXES {
+            LinkKind::Prismatic
+        } else if joint.locked_axes == JointAxesMask::LOCKED_SPHERICAL_AXES {
+            LinkKind::Spherical
+        } else {
+            LinkKind::Fixed
+        }
+    }
+}
+
+/// Server-side reaction run when an entity with an `add_collision_trigger` starts
+/// touching something.
+#[derive(SpacetimeType, Clone, Copy, Debug, PartialEq)]
+pub enum CollisionHandler {
+    /// Record the contact in `CollisionTriggerLog`.
+    Log,
+    /// Despawn the entity itself (projectiles).
+    Despawn,
+    /// Despawn the entity it touched (pickups, hazards).
+    DespawnOther,
+    /// `freeze_entity` the entity where it hit (sticky darts).
+    Freeze,
+}
+
+/// Velocity motor for a joint's free axis.
+#[derive(SpacetimeType, Clone, Copy, Debug, PartialEq)]
+pub struct JointMotorDesc {
+    /// Target speed along (or around) the free axis.
+    pub target_velocity: f32,
+    /// Upper bound on the force the motor may apply to reach it.
+    pub max_force: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tick::{logged_collisions, step_world, EventCollector, GRAVITY};
+
+    fn cuboid(half_x: f32, half_y: f32, half_z: f32) -> ShapeDesc {
+        ShapeDesc {
+            kind: ShapeKind::Cuboid,
+            half_x,
+            half_y,
+            half_z,
+            ..ShapeDesc::kind_only(ShapeKind::Cuboid)
+        }
+    }
+
+    #[test]
+    fn primitive_descriptors_survive_a_collider_round_trip() {
+        for desc in [
+            ShapeDesc::ball(0.5),
+            cuboid(1.0, 2.0, 3.0),
+            ShapeDesc::capsule(0.75, 0.25),
+        ] {
+            let collider = desc.collider_builder().unwrap().build();
+            assert_eq!(ShapeDesc::from_shape(collider.shape()), Some(desc));
+        }
+    }
+
+    #[test]
+    fn collider_builder_rejects_bad_dimensions() {
+        assert!(ShapeDesc::ball(0.0).collider_builder().is_err());
+        assert!(ShapeDesc::ball(f32::NAN).collider_builder().is_err());
+        assert!(cuboid(1.0, -1.0, 1.0).collider_builder().is_err());
+        assert!(ShapeDesc::kind_only(ShapeKind::Trimesh)
+            .collider_builder()
+            .is_err());
+    }
+
+    /// Adds a dynamic unit ball built with `options`, like `spawn_with_options` does.
+    fn spawn_with(
+        state: &mut PhysicsState,
+        position: Vector3<f32>,
+        options: &SpawnOptions,
+    ) -> RigidBodyHandle {
+        let body = options
+            .apply_to_body(RigidBodyBuilder::dynamic().translation(position))
+            .unwrap();
+        let rb_handle = state.rigid_body_set.insert(body);
+        let collider = options
+            .apply_to_collider(ColliderBuilder::ball(1.0))
+            .unwrap();
+        state
+            .collider_set
+            .insert_with_parent(collider, rb_handle, &mut state.rigid_body_set);
+        rb_handle
+    }
+
+    /// Shoves a ball of `shoved_mass` into a still one of `target_mass` and returns the
+    /// target's speed afterwards.
+    fn target_speed_after_shove(shoved_mass: f32, target_mass: f32) -> f32 {
+        let mut state = PhysicsState::new(0);
+        let with_mass = |mass| SpawnOptions {
+            mass: Some(mass),
+            ..Default::default()
+        };
+        let shoved = spawn_with(
+            &mut state,
+            Vector3::new(0.0, 5.0, 0.0),
+            &with_mass(shoved_mass),
+        );
+        let target = spawn_with(
+            &mut state,
+            Vector3::new(3.0, 5.0, 0.0),
+            &with_mass(target_mass),
+        );
+        state.rigid_body_set[shoved].set_linvel(Vector3::new(10.0, 0.0, 0.0), true);
+        for _ in 0..20 {
+            state.step_once();
+        }
+        assert!((state.rigid_body_set[shoved].mass() - shoved_mass).abs() < 1.0e-4);
+        state.rigid_body_set[target].linvel().x
+    }
+
+    #[test]
+    fn explicit_mass_replaces_density_and_changes_collisions() {
+        let light_into_heavy = target_speed_after_shove(1.0, 10.0);
+        let heavy_into_light = target_speed_after_shove(10.0, 1.0);
+        assert!(light_into_heavy > 0.0);
+        assert!(heavy_into_light > 2.0 * light_into_heavy);
+    }
+
+    #[test]
+    fn spawn_mass_must_be_positive() {
+        for mass in [0.0, -1.0, f32::NAN] {
+            let options = SpawnOptions {
+                mass: Some(mass),
+                ..Default::default()
+            };
+            assert!(options
+                .apply_to_collider(ColliderBuilder::ball(1.0))
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn spawn_spin_keeps_turning_the_stored_orientation() {
+        let mut state = PhysicsState::new(0);
+        let options = SpawnOptions {
+            angular_velocity: Some(Vec3 {
+                x: 0.0,
+                y: 2.0,
+                z: 0.0,
+            }),
+            ..Default::default()
+        };
+        let rb_handle = spawn_with(&mut state, Vector3::new(0.0, 20.0, 0.0), &options);
+        let mut previous =
+            EntityTransform::from_pose(0, 1, state.rigid_body_set[rb_handle].position());
+        for _ in 0..3 {
+            state.step_once();
+            let current =
+                EntityTransform::from_pose(0, 1, state.rigid_body_set[rb_handle].position());
+            assert!(
+                current.ry > previous.ry,
+                "{} vs {}",
+                current.ry,
+                previous.ry
+            );
+            assert_eq!((current.rx, current.rz), (0.0, 0.0));
+            previous = current;
+        }
+    }
+
+    #[test]
+    fn non_finite_angular_velocity_is_rejected() {
+        let options = SpawnOptions {
+            angular_velocity: Some(Vec3 {
+                x: f32::INFINITY,
+                y: 0.0,
+                z: 0.0,
+            }),
+            ..Default::default()
+        };
+        assert!(options.apply_to_body(RigidBodyBuilder::dynamic()).is_err());
+    }
+
+    #[test]
+    fn tilted_spawn_shows_in_the_first_transform_row() {
+        let mut state = PhysicsState::new(0);
+        let half_sqrt2 = std::f32::consts::FRAC_1_SQRT_2;
+        let options = SpawnOptions {
+            // 45° about Z, passed unnormalized
+            rotation: Some(Quat {
+                x: 0.0,
+                y: 0.0,
+                z: 2.0 * (std::f32::consts::FRAC_PI_8).sin(),
+                w: 2.0 * (std::f32::consts::FRAC_PI_8).cos(),
+            }),
+            ..Default::default()
+        };
+        let rb_handle = spawn_with(&mut state, Vector3::new(0.0, 10.0, 0.0), &options);
+        let row = EntityTransform::from_pose(0, 1, state.rigid_body_set[rb_handle].position());
+        assert!((row.rz - (std::f32::consts::FRAC_PI_8).sin()).abs() < 1.0e-5);
+        assert!((row.rw - (std::f32::consts::FRAC_PI_8).cos()).abs() < 1.0e-5);
+        let tilt = state.rigid_body_set[rb_handle].rotation() * Vector3::x();
+        assert!((tilt.x - half_sqrt2).abs() < 1.0e-5 && (tilt.y - half_sqrt2).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn zero_quaternion_is_rejected() {
+        let zero = Quat {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 0.0,
+        };
+        assert!(zero.to_rotation().is_err());
+        let options = SpawnOptions {
+            rotation: Some(zero),
+            ..Default::default()
+        };
+        assert!(options.apply_to_body(RigidBodyBuilder::dynamic()).is_err());
+    }
+
+    #[test]
+    fn dominant_body_ploughs_through_a_crowd_without_slowing() {
+        let mut state = PhysicsState::new(0);
+        let boss = spawn_with(
+            &mut state,
+            Vector3::new(0.0, 20.0, 0.0),
+            &SpawnOptions {
+                dominance_group: Some(10),
+                ..Default::default()
+            },
+        );
+        let crowd: Vec<RigidBodyHandle> = (1..4)
+            .map(|i| {
+                spawn_with(
+                    &mut state,
+                    Vector3::new(i as f32 * 3.0, 20.0, 0.1 * i as f32),
+                    &SpawnOptions::default(),
+                )
+            })
+            .collect();
+        state.rigid_body_set[boss].set_linvel(Vector3::new(10.0, 0.0, 0.0), true);
+        for _ in 0..60 {
+            state.step_once();
+        }
+
+        assert!((state.rigid_body_set[boss].linvel().x - 10.0).abs() < 1.0e-4);
+        for handle in crowd {
+            assert!(state.rigid_body_set[handle].linvel().x > 1.0);
+        }
+    }
+
+    #[test]
+    fn offset_collider_holds_the_body_above_its_resting_ball() {
+        let mut state = PhysicsState::new(0);
+        let options = SpawnOptions {
+            collider_offset: Some(Vec3 {
+                x: 0.0,
+                y: -2.0,
+                z: 0.0,
+            }),
+            ..Default::default()
+        };
+        let rb_handle = spawn_with(&mut state, Vector3::new(0.0, 5.0, 0.0), &options);
+        for _ in 0..120 {
+            state.step_once();
+        }
+
+        // Ground top 0.1 + ball radius 1 + offset 2
+        let body = &state.rigid_body_set[rb_handle];
+        assert!(
+            (body.translation().y - 3.1).abs() < 0.05,
+            "body at {:?}",
+            body.translation()
+        );
+        let co_handle = body.colliders()[0];
+        assert!((state.collider_set[co_handle].translation().y - 1.1).abs() < 0.05);
+    }
+
+    #[test]
+    fn non_finite_collider_offset_is_rejected() {
+        let options = SpawnOptions {
+            collider_offset: Some(Vec3 {
+                x: f32::NAN,
+                y: 0.0,
+                z: 0.0,
+            }),
+            ..Default::default()
+        };
+        assert!(options
+            .apply_to_collider(ColliderBuilder::ball(1.0))
+            .is_err());
+    }
+
+    /// Drops a fully elastic ball with `restitution_rule` onto the dead ground and returns
+    /// the fastest speed it bounces back up at.
+    fn bounce_off_dead_ground(restitution_rule: CombineRule) -> f32 {
+        let mut state = PhysicsState::new(0);
+        let options = SpawnOptions {
+            restitution_combine_rule: Some(restitution_rule),
+            ..Default::default()
+        };
+        let rb_handle = spawn_with(&mut state, Vector3::new(0.0, 6.0, 0.0), &options);
+        let co_handle = state.rigid_body_set[rb_handle].colliders()[0];
+        state.collider_set[co_handle].set_restitution(1.0);
+        let mut rebound: f32 = 0.0;
+        for _ in 0..90 {
+            state.step_once();
+            rebound = rebound.max(state.rigid_body_set[rb_handle].linvel().y);
+        }
+        rebound
+    }
+
+    #[test]
+    fn max_restitution_rule_keeps_the_bounce_on_a_dead_floor() {
+        // Impact speed from 4.9m up is about 9.8 m/s
+        let max = bounce_off_dead_ground(CombineRule::Max);
+        let average = bounce_off_dead_ground(CombineRule::Average);
+        let min = bounce_off_dead_ground(CombineRule::Min);
+        assert!(max > 8.0, "max rule rebound {}", max);
+        assert!(
+            average > 3.0 && average < max - 2.0,
+            "average rule rebound {}",
+            average
+        );
+        assert!(min < 0.5, "min rule rebound {}", min);
+    }
+
+    #[test]
+    fn scaling_multiplies_every_primitive_dimension() {
+        assert_eq!(ShapeDesc::ball(0.5).scaled(3.0), Ok(ShapeDesc::ball(1.5)));
+        assert_eq!(cuboid(1.0, 2.0, 3.0).scaled(0.5), Ok(cuboid(0.5, 1.0, 1.5)));
+        assert_eq!(
+            ShapeDesc::capsule(0.75, 0.25).scaled(2.0),
+            Ok(ShapeDesc::capsule(1.5, 0.5))
+        );
+        assert!(ShapeDesc::kind_only(ShapeKind::Heightfield)
+            .scaled(2.0)
+            .is_err());
+    }
+
+    /// Spawns a unit ball above the ground the way `spawn_shape` does, minus the tables.
+    fn spawn_ball(
+        state: &mut PhysicsState,
+        entity_id: u32,
+        x: f32,
+        options: &SpawnOptions,
+    ) -> ColliderHandle {
+        let rb_handle = state.rigid_body_set.insert(
+            options
+                .apply_to_body(RigidBodyBuilder::dynamic())
+                .unwrap()
+                .translation(Vector3::new(x, 2.0, 0.0))
+                .build(),
+        );
+        let collider = options
+            .apply_to_collider(ShapeDesc::ball(1.0).collider_builder().unwrap())
+            .unwrap()
+            .build();
+        let co_handle =
+            state
+                .collider_set
+                .insert_with_parent(collider, rb_handle, &mut state.rigid_body_set);
+        state.handle_to_entity_id.insert(rb_handle, entity_id);
+        state.collider_to_entity_id.insert(co_handle, entity_id);
+        options.apply_to_state(state, entity_id, co_handle);
+        co_handle
+    }
+
+    #[test]
+    fn spawn_option_collision_events_get_the_landing_logged() {
+        let mut state = PhysicsState::new(0);
+        let logging = SpawnOptions {
+            collision_events: true,
+            ..SpawnOptions::default()
+        };
+        spawn_ball(&mut state, 1, -3.0, &logging);
+        spawn_ball(&mut state, 2, 3.0, &SpawnOptions::default());
+        assert!(state.collision_logging.contains(&1));
+        assert!(!state.collision_logging.contains(&2));
+
+        let events = EventCollector::default();
+        for _ in 0..60 {
+            step_world(&mut state, 1, &Vector3::new(0.0, -GRAVITY, 0.0), &events);
+        }
+        let collisions = events.collisions.into_inner().unwrap();
+        let logged: Vec<&CollisionEvent> = logged_collisions(
+            &collisions,
+            &state.collider_to_entity_id,
+            &state.collision_logging,
+        )
+        .collect();
+        assert!(logged.iter().any(|event| event.started()));
+        assert!(logged
+            .iter()
+            .all(|event| [event.collider1(), event.collider2()]
+                .iter()
+                .any(|co_handle| state.collider_to_entity_id.get(co_handle) == Some(&1))));
+    }
+
+    #[test]
+    fn spawn_option_active_hooks_reach_the_collider_and_survive_registrations() {
+        let mut state = PhysicsState::new(0);
+        let hooked = SpawnOptions {
+            active_hooks: Some(HookFlags {
+                filter_contact_pairs: true,
+                filter_intersection_pairs: false,
+                modify_solver_contacts: false,
+            }),
+            ..SpawnOptions::default()
+        };
+        let co_handle = spawn_ball(&mut state, 1, -3.0, &hooked);
+        let plain = spawn_ball(&mut state, 2, 3.0, &SpawnOptions::default());
+        assert_eq!(
+            state.collider_set[co_handle].active_hooks(),
+            ActiveHooks::FILTER_CONTACT_PAIRS
+        );
+        assert_eq!(
+            state.collider_set[plain].active_hooks(),
+            ActiveHooks::empty()
+        );
+
+        // Becoming a conveyor adds the solver hook on top of the requested flag
+        state
+            .hooks
+            .conveyors
+            .insert(co_handle, Vector3::new(1.0, 0.0, 0.0));
+        state
+            .hooks
+            .sync_active_hooks(co_handle, &mut state.collider_set[co_handle]);
+        assert_eq!(
+            state.collider_set[co_handle].active_hooks(),
+            ActiveHooks::FILTER_CONTACT_PAIRS | ActiveHooks::MODIFY_SOLVER_CONTACTS
+        );
+    }
+}
@@ -0,0 +1,954 @@
+//! World lifecycle, world-level settings and whole-world operations.
+
+use log::info;
+use rapier3d::na::Vector3;
+use spacetimedb::{reducer, ReducerContext};
+
+use crate::queries::entities_with_tag;
+use crate::state::*;
+use crate::tables::*;
+use crate::types::*;
+
+// --- Helper Functions ---
+
+/// Puts every dynamic body of the world to sleep, or wakes them all up.
+fn set_dynamic_bodies_asleep(state: &mut PhysicsState, asleep: bool) {
+    for (_, rigid_body) in state.rigid_body_set.iter_mut() {
+        if !rigid_body.is_dynamic() {
+            continue;
+        }
+        if asleep {
+            rigid_body.sleep();
+        } else {
+            rigid_body.wake_up(true);
+        }
+    }
+}
+
+/// Sets the linear velocity of every dynamic body in the world. Returns the velocity
+/// rows of the entity bodies it changed.
+fn set_dynamic_linvel(state: &mut PhysicsState, velocity: Vector3<f32>) -> Vec<EntityVelocity> {
+    let mut rows = Vec::new();
+    for (handle, rigid_body) in state.rigid_body_set.iter_mut() {
+        if !rigid_body.is_dynamic() {
+            continue;
+        }
+        rigid_body.set_linvel(velocity, true);
+        if let Some(entity_id) = state.handle_to_entity_id.get(&handle) {
+            rows.push(EntityVelocity::from_body(*entity_id, rigid_body));
+        }
+    }
+    rows
+}
+
+/// Stops every dynamic body in the world, dropping its pending forces and torques.
+/// Returns the velocity rows of the entity bodies it stopped.
+fn halt_dynamic_bodies(state: &mut PhysicsState) -> Vec<EntityVelocity> {
+    let mut rows = Vec::new();
+    for (handle, rigid_body) in state.rigid_body_set.iter_mut() {
+        if !rigid_body.is_dynamic() {
+            continue;
+        }
+        // Sleeping also zeroes both velocities, and keeps gravity from restarting the
+        // bodies until something wakes them
+        rigid_body.reset_forces(false);
+        rigid_body.reset_torques(false);
+        rigid_body.sleep();
+        if let Some(entity_id) = state.handle_to_entity_id.get(&handle) {
+            rows.push(EntityVelocity::from_body(*entity_id, rigid_body));
+        }
+    }
+    rows
+}
+
+/// Upper bound for `set_contact_damping_ratio`.
+const MAX_DAMPING_RATIO: f32 = 20.0;
+
+/// Sets the damping of the contact springs that push penetrating bodies apart. New
+/// worlds start at 0, which leaves resting stacks free to bounce on those springs.
+fn set_contact_damping_ratio(state: &mut PhysicsState, ratio: f32) -> Result<(), String> {
+    if !(ratio.is_finite() && (0.0..=MAX_DAMPING_RATIO).contains(&ratio)) {
+        return Err(format!("Damping ratio must be between 0 and {}", MAX_DAMPING_RATIO));
+    }
+    state.integration_parameters.damping_ratio = ratio;
+    Ok(())
+}
+
+/// Entities whose dynamic body has fallen asleep.
+fn sleeping_dynamic_entities(state: &PhysicsState) -> Vec<u32> {
+    state
+        .handle_to_entity_id
+        .iter()
+        .filter(|(handle, _)| {
+            state
+                .rigid_body_set
+                .get(**handle)
+                .is_some_and(|rigid_body| rigid_body.is_dynamic() && rigid_body.is_sleeping())
+        })
+        .map(|(_, entity_id)| *entity_id)
+        .collect()
+}
+
+/// Shifts the whole world by minus `anchor`, or by the centroid of its dynamic bodies
+/// without one. Returns the offset, or `None` if there was nothing to center on.
+fn recenter_state(state: &mut PhysicsState, anchor: Option<Vector3<f32>>) -> Option<Vector3<f32>> {
+    // Without an anchor, the centroid of the dynamic bodies moves to the origin
+    let offset = match anchor {
+        Some(anchor) => anchor,
+        None => {
+            let positions: Vec<Vector3<f32>> = state
+                .rigid_body_set
+                .iter()
+                .filter(|(_, rigid_body)| rigid_body.is_dynamic())
+                .map(|(_, rigid_body)| *rigid_body.translation())
+                .collect();
+            if positions.is_empty() {
+                return None;
+            }
+            positions.iter().sum::<Vector3<f32>>() / positions.len() as f32
+        }
+    };
+
+    // Everything shifts by the same amount, so relative positions and all velocities
+    // are untouched. Sleeping bodies stay asleep.
+    for (_, rigid_body) in state.rigid_body_set.iter_mut() {
+        let translation = rigid_body.translation() - offset;
+        rigid_body.set_translation(translation, false);
+    }
+    // Parentless colliders such as the ground
+    for (_, collider) in state.collider_set.iter_mut() {
+        if collider.parent().is_none() {
+            let translation = collider.translation() - offset;
+            collider.set_translation(translation);
+        }
+    }
+    state.radial_gravity_center = state.radial_gravity_center.map(|center| center - offset);
+    state.kill_plane_y = state.kill_plane_y.map(|y| y - offset.y as f64);
+    Some(offset)
+}
+
+// --- Reducers ---
+
+#[reducer(init)]
+pub fn init_physics(ctx: &ReducerContext) -> Result<(), String> {
+    info!("Initializing physics world and timer.");
+    let mut worlds = lock_state();
+    insert_world(ctx, &mut worlds, DEFAULT_WORLD_ID)
+}
+
+#[reducer]
+pub fn create_world(ctx: &ReducerContext, world_id: u32) -> Result<(), String> {
+    info!("create_world called for world {}", world_id);
+    let mut worlds = lock_state();
+    insert_world(ctx, &mut worlds, world_id)?;
+    info!("  -> World {} created", world_id);
+    Ok(())
+}
+
+#[reducer]
+pub fn wake_all(_ctx: &ReducerContext, world_id: u32) -> Result<(), String> {
+    info!("wake_all called");
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    set_dynamic_bodies_asleep(state, false);
+    Ok(())
+}
+
+#[reducer]
+pub fn sleep_all(_ctx: &ReducerContext, world_id: u32) -> Result<(), String> {
+    info!("sleep_all called");
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    set_dynamic_bodies_asleep(state, true);
+    Ok(())
+}
+
+#[reducer]
+pub fn set_velocity_all(ctx: &ReducerContext, world_id: u32, x: f32, y: f32, z: f32) -> Result<(), String> {
+    info!("set_velocity_all called for world {}: ({}, {}, {})", world_id, x, y, z);
+    let velocity = Vec3 { x, y, z }.to_finite_vector("Velocity")?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    for row in set_dynamic_linvel(state, velocity) {
+        ctx.db.entity_velocity().entity_id().update(row);
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn halt_all(ctx: &ReducerContext, world_id: u32) -> Result<(), String> {
+    info!("halt_all called for world {}", world_id);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    // The tick skips sleeping bodies, so publish the stop here
+    for row in halt_dynamic_bodies(state) {
+        ctx.db.entity_velocity().entity_id().update(row);
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn nudge_stuck_bodies(
+    _ctx: &ReducerContext,
+    world_id: u32,
+    min_ticks_awake: u32,
+) -> Result<(), String> {
+    info!(
+        "nudge_stuck_bodies called for world {} (min {} ticks awake)",
+        world_id, min_ticks_awake
+    );
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    let nudged = nudge_stuck(state, min_ticks_awake);
+    info!("  -> Put {} stuck bodies to sleep", nudged);
+    Ok(())
+}
+
+#[reducer]
+pub fn set_sleep_thresholds(
+    _ctx: &ReducerContext,
+    world_id: u32,
+    linear: f32,
+    angular: f32,
+) -> Result<(), String> {
+    info!("set_sleep_thresholds called: linear={}, angular={}", linear, angular);
+    if !(linear.is_finite() && linear >= 0.0 && angular.is_finite() && angular >= 0.0) {
+        return Err("Sleep thresholds must be >= 0".to_string());
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    // Existing bodies are updated now; register_body applies it to future spawns
+    for (_, rigid_body) in state.rigid_body_set.iter_mut() {
+        apply_sleep_thresholds(rigid_body, linear, angular);
+    }
+    state.sleep_thresholds = Some((linear, angular));
+    Ok(())
+}
+
+#[reducer]
+pub fn set_tick_interval(ctx: &ReducerContext, world_id: u32, millis: u64) -> Result<(), String> {
+    info!("set_tick_interval called for world {}: {}ms", world_id, millis);
+    if millis == 0 {
+        return Err("Tick interval must be > 0".to_string());
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    // An interval timer can't be retuned in place, so replace the row
+    ctx.db.physics_tick_timer().id().delete(world_id as u64);
+    schedule_tick(ctx, world_id, millis)?;
+    // Keep one step per tick; the accumulator absorbs any jitter
+    match_tick_interval(state, millis);
+    publish_config(ctx, state)
+}
+
+#[reducer]
+pub fn set_time_scale(ctx: &ReducerContext, world_id: u32, scale: f32) -> Result<(), String> {
+    info!("set_time_scale called: {}", scale);
+    if !(scale.is_finite() && scale > 0.0) {
+        return Err("Time scale must be > 0".to_string());
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    state.time_scale = scale;
+    publish_config(ctx, state)
+}
+
+#[reducer]
+pub fn set_deterministic(ctx: &ReducerContext, world_id: u32, enabled: bool) -> Result<(), String> {
+    info!("set_deterministic called for world {}: {}", world_id, enabled);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    state.deterministic = enabled;
+    // Don't let time banked before the switch leak into the first steps after it
+    state.time_accumulator_micros = 0;
+    publish_config(ctx, state)
+}
+
+#[reducer]
+pub fn set_max_velocity(
+    ctx: &ReducerContext,
+    world_id: u32,
+    max_velocity: Option<f32>,
+) -> Result<(), String> {
+    info!("set_max_velocity called: {:?}", max_velocity);
+    if let Some(v) = max_velocity {
+        if !(v.is_finite() && v > 0.0) {
+            return Err("Max velocity must be > 0".to_string());
+        }
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    state.max_velocity = max_velocity; // None disables the clamp
+    publish_config(ctx, state)
+}
+
+/// Upper bound for `set_stabilization_iterations`; each one is a full extra solver pass.
+const MAX_STABILIZATION_ITERATIONS: u32 = 100;
+
+#[reducer]
+pub fn set_stabilization_iterations(
+    ctx: &ReducerContext,
+    world_id: u32,
+    iterations: u32,
+) -> Result<(), String> {
+    info!("set_stabilization_iterations called for world {}: {}", world_id, iterations);
+    if iterations > MAX_STABILIZATION_ITERATIONS {
+        return Err(format!(
+            "Stabilization iterations must be at most {}",
+            MAX_STABILIZATION_ITERATIONS
+        ));
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    // Extra position-correction passes after the velocity solve. They pull penetrating
+    // bodies apart without adding velocity, which is what keeps tall stacks from creeping.
+    state.integration_parameters.num_internal_stabilization_iterations = iterations as usize;
+    publish_config(ctx, state)
+}
+
+#[reducer]
+pub fn set_damping_ratio(ctx: &ReducerContext, world_id: u32, ratio: f32) -> Result<(), String> {
+    info!("set_damping_ratio called for world {}: {}", world_id, ratio);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    set_contact_damping_ratio(state, ratio)?;
+    publish_config(ctx, state)
+}
+
+/// Upper bound for `set_ccd_params`.
+const MAX_CCD_SUBSTEPS: u32 = 16;
+
+#[reducer]
+pub fn set_ccd_params(ctx: &ReducerContext, world_id: u32, max_ccd_substeps: u32) -> Result<(), String> {
+    info!("set_ccd_params called for world {}: {} substeps", world_id, max_ccd_substeps);
+    if !(1..=MAX_CCD_SUBSTEPS).contains(&max_ccd_substeps) {
+        return Err(format!("CCD substeps must be between 1 and {}", MAX_CCD_SUBSTEPS));
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    // The CCD solver reads this from the integration parameters passed to every step.
+    // More substeps let a CCD body resolve several impacts per step instead of stopping
+    // at the first one, at the cost of extra time-of-impact queries.
+    state.integration_parameters.max_ccd_substeps = max_ccd_substeps as usize;
+    publish_config(ctx, state)
+}
+
+#[reducer]
+pub fn set_contact_force_threshold(
+    ctx: &ReducerContext,
+    world_id: u32,
+    threshold: f32,
+) -> Result<(), String> {
+    info!("set_contact_force_threshold called: {}", threshold);
+    if !(threshold.is_finite() && threshold >= 0.0) {
+        return Err("Contact force threshold must be >= 0".to_string());
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    for (_, collider) in state.collider_set.iter_mut() {
+        collider.set_contact_force_event_threshold(threshold);
+    }
+    state.contact_force_threshold = threshold;
+    publish_config(ctx, state)
+}
+
+#[reducer]
+pub fn set_max_bodies(ctx: &ReducerContext, world_id: u32, max_bodies: u32) -> Result<(), String> {
+    info!("set_max_bodies called: {}", max_bodies);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    // Lowering the cap below the current count only blocks new spawns; nothing is removed
+    state.max_bodies = max_bodies as usize;
+    publish_config(ctx, state)
+}
+
+#[reducer]
+pub fn set_radial_gravity(
+    ctx: &ReducerContext,
+    world_id: u32,
+    center: Option<Vec3>,
+    strength: f32,
+) -> Result<(), String> {
+    info!("set_radial_gravity called for world {}: {:?}, strength {}", world_id, center, strength);
+    let center = center
+        .map(|center| center.to_finite_vector("Gravity center"))
+        .transpose()?;
+    if !strength.is_finite() {
+        return Err("Gravity strength must be finite".to_string());
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    state.radial_gravity_center = center; // None restores uniform downward gravity
+    state.radial_gravity_strength = strength;
+    publish_config(ctx, state)
+}
+
+#[reducer]
+pub fn set_wind(ctx: &ReducerContext, world_id: u32, x: f32, y: f32, z: f32) -> Result<(), String> {
+    info!("set_wind called: ({}, {}, {})", x, y, z);
+    if ![x, y, z].iter().all(|v| v.is_finite()) {
+        return Err("Wind must be finite".to_string());
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    state.wind = Vector3::new(x, y, z);
+    publish_config(ctx, state)
+}
+
+#[reducer]
+pub fn despawn_asleep(ctx: &ReducerContext, world_id: u32) -> Result<(), String> {
+    info!("despawn_asleep called for world {}", world_id);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    // Collect first: removal mutates the body set and handle map
+    let asleep = sleeping_dynamic_entities(state);
+    for entity_id in &asleep {
+        remove_entity(ctx, state, *entity_id)?;
+    }
+    info!("  -> Despawned {} sleeping bodies", asleep.len());
+    Ok(())
+}
+
+#[reducer]
+pub fn despawn_below_y(ctx: &ReducerContext, world_id: u32, threshold: f64) -> Result<(), String> {
+    info!("despawn_below_y called for world {}: {}", world_id, threshold);
+    if !threshold.is_finite() {
+        return Err("Threshold must be finite".to_string());
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    let below = dynamic_entities_below(state, threshold);
+    for entity_id in &below {
+        remove_entity(ctx, state, *entity_id)?;
+    }
+    info!("  -> Despawned {} bodies below y = {}", below.len(), threshold);
+    Ok(())
+}
+
+#[reducer]
+pub fn set_kill_plane(ctx: &ReducerContext, world_id: u32, y: Option<f64>) -> Result<(), String> {
+    info!("set_kill_plane called for world {}: {:?}", world_id, y);
+    if let Some(y) = y {
+        if !y.is_finite() {
+            return Err("Kill plane height must be finite".to_string());
+        }
+    }
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+    state.kill_plane_y = y; // None disables it
+    publish_config(ctx, state)
+}
+
+#[reducer]
+pub fn recenter_world(ctx: &ReducerContext, world_id: u32, anchor: Option<Vec3>) -> Result<(), String> {
+    info!("recenter_world called for world {}: {:?}", world_id, anchor);
+    let anchor = anchor.map(|a| a.to_finite_vector("Anchor")).transpose()?;
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    let Some(offset) = recenter_state(state, anchor) else {
+        return Ok(());
+    };
+
+    for (rb_handle, entity_id) in state.handle_to_entity_id.iter() {
+        let Some(rigid_body) = state.rigid_body_set.get(*rb_handle) else {
+            continue;
+        };
+        let transform = EntityTransform::from_pose(world_id, *entity_id, rigid_body.position());
+        ctx.db
+            .entity_transform_prev()
+            .entity_id()
+            .update(transform.clone().into());
+        ctx.db.entity_transform().entity_id().update(transform);
+    }
+    let shift = |v: Vec3| Vec3 {
+        x: v.x - offset.x,
+        y: v.y - offset.y,
+        z: v.z - offset.z,
+    };
+    let wells: Vec<GravityWell> = ctx.db.gravity_well().world_id().filter(world_id).collect();
+    for mut well in wells {
+        well.x -= offset.x;
+        well.y -= offset.y;
+        well.z -= offset.z;
+        ctx.db.gravity_well().id().update(well);
+    }
+    let fluid_zones: Vec<FluidZone> = ctx.db.fluid_zone().world_id().filter(world_id).collect();
+    for mut zone in fluid_zones {
+        zone.center = shift(zone.center);
+        ctx.db.fluid_zone().id().update(zone);
+    }
+    let force_fields: Vec<ForceField> = ctx.db.force_field().world_id().filter(world_id).collect();
+    for mut field in force_fields {
+        field.center = shift(field.center);
+        ctx.db.force_field().id().update(field);
+    }
+    info!("  -> Shifted world by {:?}", offset);
+    publish_config(ctx, state)
+}
+
+#[reducer]
+pub fn despawn_by_tag(ctx: &ReducerContext, tag: u32) -> Result<(), String> {
+    info!("despawn_by_tag called for tag {}", tag);
+    let tagged = entities_with_tag(ctx.db.entity_tag().tag().filter(tag), tag);
+
+    // Look every entity up before removing any: a failure then leaves all worlds untouched,
+    // rather than rolling back the rows of bodies that are already gone. Tags aren't per
+    // world, so each entity is removed from whichever world it is in.
+    let mut worlds = lock_state();
+    let mut targets = Vec::with_capacity(tagged.len());
+    for entity_id in &tagged {
+        let (world_id, rb_handle, _) = get_entity_handles(ctx, *entity_id)?;
+        get_world(&mut worlds, world_id)?;
+        targets.push((world_id, *entity_id, rb_handle));
+    }
+    // The shared removal also drops its joints and tag row
+    for (world_id, entity_id, rb_handle) in targets {
+        if let Some(state) = worlds.get_mut(&world_id) {
+            remove_body(state, entity_id, rb_handle);
+            delete_entity_rows(ctx, entity_id);
+        }
+    }
+    info!("  -> Despawned {} entities tagged {}", tagged.len(), tag);
+    Ok(())
+}
+
+#[reducer]
+pub fn reset_simulation(ctx: &ReducerContext, world_id: u32) -> Result<(), String> {
+    info!("Resetting simulation...");
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    // Collect entity IDs first to avoid iterating a table while deleting from it
+    let entities_to_remove: Vec<u32> = ctx
+        .db
+        .entity()
+        .world_id()
+        .filter(world_id)
+        .map(|entity| entity.id)
+        .collect();
+
+    info!("Removing {} physics bodies and colliders.", entities_to_remove.len());
+    for entity_id in &entities_to_remove {
+        remove_entity(ctx, state, *entity_id)?;
+    }
+
+    info!("Simulation reset complete. {} entities removed.", entities_to_remove.len());
+    Ok(())
+}
+
+#[reducer]
+pub fn reset_simulation_except(
+    ctx: &ReducerContext,
+    world_id: u32,
+    keep_ids: Vec<u32>,
+) -> Result<(), String> {
+    info!("reset_simulation_except called for world {}, keeping {:?}", world_id, keep_ids);
+    let mut worlds = lock_state();
+    let state = get_world(&mut worlds, world_id)?;
+
+    let entities_to_remove: Vec<u32> = ctx
+        .db
+        .entity()
+        .world_id()
+        .filter(world_id)
+        .map(|entity| entity.id)
+        .filter(|entity_id| !keep_ids.contains(entity_id))
+        .collect();
+
+    // Joints between a kept and a removed body go with the removed one
+    for entity_id in &entities_to_remove {
+        remove_entity(ctx, state, *entity_id)?;
+    }
+
+    info!("  -> {} entities removed", entities_to_remove.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rapier3d::prelude::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn despawning_a_tag_removes_its_five_bodies_and_keeps_the_other_five() {
+        let mut state = PhysicsState::new(0);
+        let mut tags = Vec::new();
+        for entity_id in 1..=10u32 {
+            let rb_handle = state.add_test_ball(Vector3::new(entity_id as f32 * 3.0, 1.1, 0.0));
+            let co_handle = state.rigid_body_set[rb_handle].colliders()[0];
+            state.handle_to_entity_id.insert(rb_handle, entity_id);
+            state.collider_to_entity_id.insert(co_handle, entity_id);
+            tags.push(EntityTag {
+                entity_id,
+                tag: if entity_id <= 5 { 7 } else { 8 },
+            });
+        }
+
+        let handle_of = |state: &PhysicsState, entity_id: u32| {
+            state
+                .handle_to_entity_id
+                .iter()
+                .find(|(_, id)| **id == entity_id)
+                .map(|(handle, _)| *handle)
+        };
+        for entity_id in entities_with_tag(tags, 7) {
+            let rb_handle = handle_of(&state, entity_id).unwrap();
+            remove_body(&mut state, entity_id, rb_handle);
+        }
+
+        assert_eq!(state.rigid_body_set.len(), 5);
+        assert_eq!(state.collider_to_entity_id.len(), 5);
+        let mut remaining: Vec<u32> = state.handle_to_entity_id.values().copied().collect();
+        remaining.sort_unstable();
+        assert_eq!(remaining, vec![6, 7, 8, 9, 10]);
+        state.step_once();
+    }
+
+    #[test]
+    fn sleeping_bodies_hold_still_until_woken() {
+        let mut state = PhysicsState::new(0);
+        let handles: Vec<RigidBodyHandle> = (0..2)
+            .map(|i| state.add_test_ball(Vector3::new(i as f32 * 3.0, 10.0, 0.0)))
+            .collect();
+        state.step_once();
+
+        set_dynamic_bodies_asleep(&mut state, true);
+        let asleep_at: Vec<Vector3<f32>> = handles
+            .iter()
+            .map(|handle| *state.rigid_body_set[*handle].translation())
+            .collect();
+        for _ in 0..10 {
+            state.step_once();
+        }
+        for (handle, position) in handles.iter().zip(&asleep_at) {
+            let body = &state.rigid_body_set[*handle];
+            assert!(body.is_sleeping());
+            assert_eq!(body.translation(), position);
+        }
+
+        set_dynamic_bodies_asleep(&mut state, false);
+        state.step_once();
+        for (handle, position) in handles.iter().zip(&asleep_at) {
+            let body = &state.rigid_body_set[*handle];
+            assert!(!body.is_sleeping());
+            assert!(body.translation().y < position.y);
+        }
+    }
+
+    /// Whether a weightless ball drifting at 0.5 m/s falls asleep within 150 steps.
+    fn drifting_ball_sleeps(thresholds: Option<(f32, f32)>) -> bool {
+        let mut state = PhysicsState::new(0);
+        let rb_handle = state.add_test_ball(Vector3::new(0.0, 10.0, 0.0));
+        let rigid_body = &mut state.rigid_body_set[rb_handle];
+        rigid_body.set_gravity_scale(0.0, true);
+        rigid_body.set_linvel(Vector3::new(0.5, 0.0, 0.0), true);
+        if let Some((linear, angular)) = thresholds {
+            apply_sleep_thresholds(rigid_body, linear, angular);
+        }
+        for _ in 0..150 {
+            state.step_once();
+        }
+        state.rigid_body_set[rb_handle].is_sleeping()
+    }
+
+    #[test]
+    fn raised_sleep_threshold_puts_a_drifting_body_to_sleep() {
+        assert!(!drifting_ball_sleeps(None));
+        assert!(drifting_ball_sleeps(Some((1.0, 1.0))));
+    }
+
+    #[test]
+    fn worlds_step_independently() {
+        let mut worlds = HashMap::from([(1, PhysicsState::new(1)), (2, PhysicsState::new(2))]);
+        let first = get_world(&mut worlds, 1)
+            .unwrap()
+            .add_test_ball(Vector3::new(0.0, 5.0, 0.0));
+        let second = get_world(&mut worlds, 2)
+            .unwrap()
+            .add_test_ball(Vector3::new(0.0, 5.0, 0.0));
+        // Same spot in both worlds, yet no collision between them
+        for _ in 0..30 {
+            get_world(&mut worlds, 1).unwrap().step_once();
+        }
+
+        assert!(worlds[&1].rigid_body_set[first].translation().y < 5.0);
+        assert_eq!(worlds[&2].rigid_body_set[second].translation().y, 5.0);
+        assert_eq!(worlds[&1].rigid_body_set.len(), 1);
+        assert!(get_world(&mut worlds, 3).is_err());
+    }
+
+    #[test]
+    fn only_sleeping_dynamic_bodies_are_reported_for_cleanup() {
+        let mut state = PhysicsState::new(0);
+        for i in 0..4u32 {
+            let rb_handle = state.add_test_ball(Vector3::new(i as f32 * 3.0, 1.1, 0.0));
+            state.handle_to_entity_id.insert(rb_handle, i + 1);
+        }
+        let wall = state
+            .rigid_body_set
+            .insert(RigidBodyBuilder::fixed().build());
+        state.handle_to_entity_id.insert(wall, 10);
+        for _ in 0..300 {
+            state.step_once();
+        }
+        let woken = state.add_test_ball(Vector3::new(0.0, 1.1, 6.0));
+        state.handle_to_entity_id.insert(woken, 20);
+
+        let mut asleep = sleeping_dynamic_entities(&state);
+        asleep.sort_unstable();
+        assert_eq!(asleep, [1, 2, 3, 4]);
+        let handles: Vec<(RigidBodyHandle, u32)> = state
+            .handle_to_entity_id
+            .iter()
+            .filter(|(_, entity_id)| asleep.contains(entity_id))
+            .map(|(handle, entity_id)| (*handle, *entity_id))
+            .collect();
+        for (rb_handle, entity_id) in handles {
+            remove_body(&mut state, entity_id, rb_handle);
+        }
+        assert_eq!(state.rigid_body_set.len(), 2);
+        assert!(state.rigid_body_set.contains(woken) && state.rigid_body_set.contains(wall));
+    }
+
+    #[test]
+    fn removing_all_but_a_kept_body_drops_its_joints_to_removed_ones() {
+        let mut state = PhysicsState::new(0);
+        let handles: Vec<RigidBodyHandle> = (1..=3u32)
+            .map(|entity_id| {
+                let rb_handle = state.add_test_ball(Vector3::new(entity_id as f32 * 3.0, 5.0, 0.0));
+                state.handle_to_entity_id.insert(rb_handle, entity_id);
+                rb_handle
+            })
+            .collect();
+        let joint = FixedJointBuilder::new().local_anchor2(Point::new(3.0, 0.0, 0.0));
+        state
+            .impulse_joint_set
+            .insert(handles[0], handles[1], joint, true);
+
+        let keep_ids = [1];
+        for (rb_handle, entity_id) in handles.iter().zip(1..) {
+            if !keep_ids.contains(&entity_id) {
+                remove_body(&mut state, entity_id, *rb_handle);
+            }
+        }
+        assert_eq!(state.rigid_body_set.len(), 1);
+        assert_eq!(state.impulse_joint_set.len(), 0);
+        assert_eq!(state.handle_to_entity_id.get(&handles[0]), Some(&1));
+
+        // The kept body carries on simulating on its own
+        for _ in 0..10 {
+            state.step_once();
+        }
+        assert!(state.rigid_body_set[handles[0]].translation().y < 5.0);
+    }
+
+    /// Stacks ten unit boxes on the ground with a weak solver and returns how far the top
+    /// one strays from its ideal resting place over two seconds.
+    fn stack_drift(stabilization_iterations: usize) -> f32 {
+        let mut state = PhysicsState::new(0);
+        state.integration_parameters.num_solver_iterations =
+            std::num::NonZeroUsize::new(1).unwrap();
+        state
+            .integration_parameters
+            .num_internal_stabilization_iterations = stabilization_iterations;
+        let mut top = None;
+        for level in 0..10 {
+            let rb_handle = state.rigid_body_set.insert(
+                RigidBodyBuilder::dynamic()
+                    .translation(Vector3::new(0.0, 0.6 + level as f32, 0.0))
+                    .build(),
+            );
+            state.collider_set.insert_with_parent(
+                ColliderBuilder::cuboid(0.5, 0.5, 0.5).build(),
+                rb_handle,
+                &mut state.rigid_body_set,
+            );
+            top = Some(rb_handle);
+        }
+        let top = top.unwrap();
+        let ideal = Vector3::new(0.0, 9.6, 0.0);
+        let mut drift: f32 = 0.0;
+        for _ in 0..120 {
+            state.step_once();
+            drift = drift.max((state.rigid_body_set[top].translation() - ideal).norm());
+        }
+        drift
+    }
+
+    #[test]
+    fn stabilization_iterations_keep_a_tall_stack_closer_to_rest() {
+        let unstabilized = stack_drift(0);
+        let stabilized = stack_drift(20);
+        assert!(
+            stabilized < unstabilized,
+            "stabilized {} vs unstabilized {}",
+            stabilized,
+            unstabilized
+        );
+    }
+
+    #[test]
+    fn halting_stops_dynamic_bodies_and_drops_their_queued_torques() {
+        let mut state = PhysicsState::new(0);
+        let balls: Vec<RigidBodyHandle> = (0..3)
+            .map(|i| state.add_test_ball(Vector3::new(i as f32 * 3.0, 5.0, 0.0)))
+            .collect();
+        for (entity_id, rb_handle) in balls.iter().enumerate().take(2) {
+            state
+                .handle_to_entity_id
+                .insert(*rb_handle, entity_id as u32 + 1);
+        }
+        let platform = state.rigid_body_set.insert(
+            RigidBodyBuilder::kinematic_velocity_based()
+                .linvel(Vector3::new(0.0, 0.0, 1.0))
+                .build(),
+        );
+
+        // Every dynamic body gets the velocity, but only entity bodies are reported
+        let rows = set_dynamic_linvel(&mut state, Vector3::new(4.0, 0.0, 0.0));
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|row| row.vx == 4.0 && row.speed == 4.0));
+        assert_eq!(state.rigid_body_set[balls[2]].linvel().x, 4.0);
+        assert_eq!(
+            *state.rigid_body_set[platform].linvel(),
+            Vector3::new(0.0, 0.0, 1.0)
+        );
+
+        state.rigid_body_set[balls[0]].add_torque(Vector3::new(0.0, 50.0, 0.0), true);
+        let rows = halt_dynamic_bodies(&mut state);
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|row| row.speed == 0.0));
+        for rb_handle in &balls {
+            let ball = &state.rigid_body_set[*rb_handle];
+            assert!(ball.is_sleeping());
+            assert_eq!(*ball.linvel(), Vector3::zeros());
+        }
+        assert_eq!(
+            *state.rigid_body_set[platform].linvel(),
+            Vector3::new(0.0, 0.0, 1.0)
+        );
+
+        // Woken again mid-air, the ball falls without picking up the dropped spin
+        state.rigid_body_set[balls[0]].wake_up(true);
+        state.step_once();
+        assert_eq!(*state.rigid_body_set[balls[0]].angvel(), Vector3::zeros());
+    }
+
+    #[test]
+    fn recentering_shifts_everything_and_keeps_the_simulation_unchanged() {
+        let far = Vector3::new(5000.0, 0.0, -3000.0);
+        let mut state = PhysicsState::new(0);
+        recenter_state(&mut state, Some(-far)).unwrap();
+        let resting = state.add_test_ball(far + Vector3::new(0.0, 1.1, 0.0));
+        let rolling = state.add_test_ball(far + Vector3::new(4.0, 1.1, 0.0));
+        state.rigid_body_set[rolling].set_linvel(Vector3::new(1.0, 0.0, 0.0), true);
+        state.radial_gravity_center = Some(far);
+        state.kill_plane_y = Some(-50.0);
+        state.step_once();
+
+        // Without an anchor the two balls' midpoint moves to the origin
+        let offset = recenter_state(&mut state, None).unwrap();
+        assert!((offset - (far + Vector3::new(2.0, 1.1, 0.0))).norm() < 1e-2);
+        let resting_at = *state.rigid_body_set[resting].translation();
+        let rolling_at = *state.rigid_body_set[rolling].translation();
+        assert!((resting_at + rolling_at).norm() < 1e-3);
+        assert!((rolling_at - resting_at - Vector3::new(4.0, 0.0, 0.0)).norm() < 0.1);
+        assert!(state.rigid_body_set[rolling].linvel().x > 0.9);
+        assert_eq!(state.radial_gravity_center, Some(far - offset));
+        assert_eq!(state.kill_plane_y, Some(-50.0 - offset.y as f64));
+
+        // The ground came along, so the resting ball stays put
+        for _ in 0..30 {
+            state.step_once();
+        }
+        let settled = state.rigid_body_set[resting].translation();
+        assert!(
+            (settled - resting_at).norm() < 1e-2,
+            "moved to {:?}",
+            settled
+        );
+
+        let mut empty = PhysicsState::new(1);
+        assert_eq!(recenter_state(&mut empty, None), None);
+    }
+
+    #[test]
+    fn damping_ratio_is_validated_published_and_keeps_contacts_solid() {
+        let mut state = PhysicsState::new(0);
+        for ratio in [-0.1, 20.5, f32::NAN, f32::INFINITY] {
+            assert!(
+                set_contact_damping_ratio(&mut state, ratio).is_err(),
+                "accepted {}",
+                ratio
+            );
+        }
+        assert_eq!(state.integration_parameters.damping_ratio, 0.0);
+
+        set_contact_damping_ratio(&mut state, 1.0).unwrap();
+        let config = PhysicsConfig::from_state(
+            &state,
+            spacetimedb::Timestamp::from_micros_since_unix_epoch(0),
+        );
+        assert_eq!(config.damping_ratio, 1.0);
+
+        // A dropped ball still comes to rest on the ground rather than sinking into it
+        let ball = state.add_test_ball(Vector3::new(0.0, 3.0, 0.0));
+        for _ in 0..120 {
+            state.step_once();
+        }
+        let y = state.rigid_body_set[ball].translation().y;
+        assert!((y - 1.1).abs() < 0.05, "ball at {}", y);
+    }
+
+    /// Stacks five unit boxes at `damping_ratio` and returns the summed speed of all of them
+    /// over the last half second of three seconds, i.e. the jitter they never settle out of.
+    fn stack_jitter(damping_ratio: f32) -> f32 {
+        let mut state = PhysicsState::new(0);
+        set_contact_damping_ratio(&mut state, damping_ratio).unwrap();
+        // Sleeping would hide the jitter
+        let boxes: Vec<RigidBodyHandle> = (0..5)
+            .map(|level| {
+                let rb_handle = state.rigid_body_set.insert(
+                    RigidBodyBuilder::dynamic()
+                        .translation(Vector3::new(0.0, 0.6 + level as f32, 0.0))
+                        .can_sleep(false)
+                        .build(),
+                );
+                state.collider_set.insert_with_parent(
+                    ColliderBuilder::cuboid(0.5, 0.5, 0.5).build(),
+                    rb_handle,
+                    &mut state.rigid_body_set,
+                );
+                rb_handle
+            })
+            .collect();
+        let mut jitter = 0.0;
+        for step in 0..180 {
+            state.step_once();
+            if step >= 150 {
+                jitter += boxes
+                    .iter()
+                    .map(|handle| state.rigid_body_set[*handle].linvel().norm())
+                    .sum::<f32>();
+            }
+        }
+        jitter
+    }
+
+    #[test]
+    fn damped_contacts_leave_a_stack_less_residual_jitter() {
+        let undamped = stack_jitter(0.0);
+        let damped = stack_jitter(5.0);
+        assert!(
+            damped < undamped,
+            "damped {} vs undamped {}",
+            damped,
+            undamped
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Persistent force regions: gravity wells, fluid zones, force fields and magnets.
+
+use log::info;
+use spacetimedb::{reducer, ReducerContext, Table};
+
+use crate::state::*;
+use crate::tables::*;
+use crate::types::*;
+
+// --- Reducers ---
+
+#[reducer]
+pub fn create_gravity_well(
+    ctx: &ReducerContext,
+    world_id: u32,
+    x: f32,
+    y: f32,
+    z: f32,
+    strength: f32,
+) -> Result<(), String> {
+    info!("create_gravity_well called at ({}, {}, {}) with strength {}", x, y, z, strength);
+    if ![x, y, z, strength].iter().all(|v| v.is_finite()) {
+        return Err("Gravity well parameters must be finite".to_string());
+    }
+    let id = ctx
+        .db
+        .gravity_well()
+        .iter()
+        .map(|well| well.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    ctx.db
+        .gravity_well()
+        .try_insert(GravityWell {
+            id,
+            world_id,
+            x,
+            y,
+            z,
+            strength,
+        })
+        .map_err(|e| e.to_string())?;
+    info!("  -> Created gravity well {}", id);
+    Ok(())
+}
+
+#[reducer]
+pub fn remove_gravity_well(ctx: &ReducerContext, id: u32) -> Result<(), String> {
+    info!("remove_gravity_well called for {}", id);
+    if !ctx.db.gravity_well().id().delete(id) {
+        return Err(format!("Gravity well {} not found", id));
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn create_fluid_zone(
+    ctx: &ReducerContext,
+    world_id: u32,
+    center: Vec3,
+    half_extents: Vec3,
+    density: f32,
+    drag: f32,
+) -> Result<(), String> {
+    info!(
+        "create_fluid_zone called at {:?}, half extents {:?}, density {}, drag {}",
+        center, half_extents, density, drag
+    );
+    center.to_finite_vector("Fluid zone center")?;
+    let half = half_extents.to_finite_vector("Fluid zone half extents")?;
+    if half.iter().any(|h| *h <= 0.0) {
+        return Err("Fluid zone half extents must be > 0".to_string());
+    }
+    if !(density.is_finite() && density > 0.0) {
+        return Err("Fluid density must be > 0".to_string());
+    }
+    if !(drag.is_finite() && drag >= 0.0) {
+        return Err("Fluid drag must be >= 0".to_string());
+    }
+    let id = ctx
+        .db
+        .fluid_zone()
+        .iter()
+        .map(|zone| zone.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    ctx.db
+        .fluid_zone()
+        .try_insert(FluidZone {
+            id,
+            world_id,
+            center,
+            half_extents,
+            density,
+            drag,
+        })
+        .map_err(|e| e.to_string())?;
+    info!("  -> Created fluid zone {}", id);
+    Ok(())
+}
+
+#[reducer]
+pub fn remove_fluid_zone(ctx: &ReducerContext, id: u32) -> Result<(), String> {
+    info!("remove_fluid_zone called for {}", id);
+    if !ctx.db.fluid_zone().id().delete(id) {
+        return Err(format!("Fluid zone {} not found", id));
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn create_force_field(
+    ctx: &ReducerContext,
+    world_id: u32,
+    center: Vec3,
+    half_extents: Vec3,
+    force: Vec3,
+) -> Result<(), String> {
+    info!(
+        "create_force_field called at {:?}, half extents {:?}, force {:?}",
+        center, half_extents, force
+    );
+    center.to_finite_vector("Force field center")?;
+    force.to_finite_vector("Force field force")?;
+    let half = half_extents.to_finite_vector("Force field half extents")?;
+    if half.iter().any(|h| *h <= 0.0) {
+        return Err("Force field half extents must be > 0".to_string());
+    }
+    let id = ctx
+        .db
+        .force_field()
+        .iter()
+        .map(|field| field.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    ctx.db
+        .force_field()
+        .try_insert(ForceField {
+            id,
+            world_id,
+            center,
+            half_extents,
+            force,
+        })
+        .map_err(|e| e.to_string())?;
+    info!("  -> Created force field {}", id);
+    Ok(())
+}
+
+#[reducer]
+pub fn remove_force_field(ctx: &ReducerContext, id: u32) -> Result<(), String> {
+    info!("remove_force_field called for {}", id);
+    if !ctx.db.force_field().id().delete(id) {
+        return Err(format!("Force field {} not found", id));
+    }
+    Ok(())
+}
+
+#[reducer]
+pub fn create_magnet(
+    ctx: &ReducerContext,
+    entity_a: u32,
+    entity_b: u32,
+    strength: f32,
+) -> Result<(), String> {
+    info!("create_magnet called: {} <-> {} with strength {}", entity_a, entity_b, strength);
+    if !strength.is_finite() {
+        return Err("Magnet strength must be finite".to_string());
+    }
+    if entity_a == entity_b {
+        return Err("A magnet needs two different entities".to_string());
+    }
+    let (world_a, _, _) = get_entity_handles(ctx, entity_a)?;
+    let (world_b, _, _) = get_entity_handles(ctx, entity_b)?;
+    if world_a != world_b {
+        return Err(format!(
+            "Entities {} and {} are in different worlds",
+            entity_a, entity_b
+        ));
+    }
+    let id = ctx
+        .db
+        .magnet_pair()
+        .iter()
+        .map(|magnet| magnet.id)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    ctx.db
+        .magnet_pair()
+        .try_insert(MagnetPair {
+            id,
+            world_id: world_a,
+            entity_a,
+            entity_b,
+            strength,
+        })
+        .map_err(|e| e.to_string())?;
+    info!("  -> Created magnet {}", id);
+    Ok(())
+}
+
+#[reducer]
+pub fn remove_magnet(ctx: &ReducerContext, id: u32) -> Result<(), String> {
+    info!("remove_magnet called for {}", id);
+    if !ctx.db.magnet_pair().id().delete(id) {
+        return Err(format!("Magnet {} not found", id));
+    }
+    Ok(())
+}